@@ -0,0 +1,273 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use html_parser::css_layers::LayerOrder;
+use html_parser::css_parse::{CSSBlock, CSSRule, CSSStyleSheet};
+use html_parser::css_shorthand;
+use html_parser::css_variables::{self, CustomProperties};
+use html_parser::media_query::MediaEnvironment;
+use html_parser::selector::{MatchingContext, Selector, SelectorComponent, Specificity};
+use html_parser::tree_generation_2::HTMLNode;
+use tree::TreeNode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Origin {
+	UserAgent,
+	User,
+	Author
+}
+
+/// Normal declarations rank user agent < user < author, important ones reverse that and beat every normal one.
+fn origin_rank(origin: Origin, important: bool) -> u8 {
+	match (origin, important) {
+		(Origin::UserAgent, false) => 0,
+		(Origin::User, false) => 1,
+		(Origin::Author, false) => 2,
+		(Origin::Author, true) => 3,
+		(Origin::User, true) => 4,
+		(Origin::UserAgent, true) => 5
+	}
+}
+
+/// How a declaration competes in the cascade, compared field by field. `layer` is the rank of the cascade layer,
+/// reversed for important declarations. `order` is the position of the rule and of the declaration within it, so
+/// later declarations win ties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Precedence {
+	origin: u8,
+	inline: bool,
+	layer: usize,
+	specificity: Specificity,
+	order: (usize, usize)
+}
+
+struct StyleRule {
+	origin: Origin,
+	layer: usize,
+	selector: Selector,
+	block: Rc<CSSBlock>
+}
+
+/// The style rules of every stylesheet that applies to a page, in cascade order.
+pub struct Cascade {
+	rules: Vec<StyleRule>,
+	/// Indices of the rules whose every selector requires an id or a class of its subject, by one of them, so an
+	/// element is only matched against the rules it could match. The other rules are `unindexed`.
+	by_id: HashMap<String, Vec<usize>>,
+	by_class: HashMap<String, Vec<usize>>,
+	unindexed: Vec<usize>,
+	/// The layers of each origin, which its stylesheets share.
+	layers: HashMap<Origin, LayerOrder>,
+	/// `LayerOrder::ranks` of each origin, computed when the first element is cascaded.
+	ranks: OnceCell<HashMap<Origin, Vec<usize>>>,
+	/// Shared by every element cascaded, which is sound as a cascade is built for each layout of an unchanging tree.
+	context: MatchingContext
+}
+impl Cascade {
+	pub fn new() -> Cascade {
+		Cascade {
+			rules: vec![],
+			by_id: HashMap::new(),
+			by_class: HashMap::new(),
+			unindexed: vec![],
+			layers: HashMap::new(),
+			ranks: OnceCell::new(),
+			context: MatchingContext::new()
+		}
+	}
+
+	/// Adds style rules after the ones already added, outside of any layer. Rules with selectors that don't parse are
+	/// dropped.
+	pub fn add_blocks(&mut self, origin: Origin, blocks: Vec<Rc<CSSBlock>>) {
+		self.add_layered_blocks(origin, blocks.into_iter().map(|block| (LayerOrder::UNLAYERED, block)).collect());
+	}
+
+	fn add_layered_blocks(&mut self, origin: Origin, blocks: Vec<(usize, Rc<CSSBlock>)>) {
+		for (layer, block) in blocks {
+			if let Ok(selector) = Selector::from_str(block.selector.as_str()) {
+				self.index_rule(self.rules.len(), &selector);
+				self.rules.push(StyleRule { origin, layer, selector, block });
+			}
+		}
+	}
+
+	fn index_rule(&mut self, idx: usize, selector: &Selector) {
+		let keys: Option<Vec<&SelectorComponent>> = selector.selectors().iter().map(|selector| selector.subject().index_key()).collect();
+		match keys {
+			Some(keys) => for key in keys {
+				match key {
+					SelectorComponent::Id(id) => self.by_id.entry(id.clone()).or_default().push(idx),
+					SelectorComponent::Class(class) => self.by_class.entry(class.clone()).or_default().push(idx),
+					_ => panic!("Unreachable")
+				}
+			},
+			None => self.unindexed.push(idx)
+		}
+	}
+
+	/// The indices of the rules that could match `node`, in cascade order.
+	fn candidates(&self, node: &Rc<TreeNode<HTMLNode>>) -> Vec<usize> {
+		let mut candidates = self.unindexed.clone();
+		if let HTMLNode::Element(element) = &*node.value.borrow() {
+			if let Some(rules) = element.id().and_then(|id| self.by_id.get(id)) {
+				candidates.extend(rules);
+			}
+			for class in element.class_list().tokens() {
+				candidates.extend(self.by_class.get(class).into_iter().flatten());
+			}
+		}
+
+		candidates.sort_unstable();
+		candidates.dedup();
+		candidates
+	}
+
+	/// Adds the style rules of `stylesheet` that apply in `environment`, with its `@layer` rules ordered together with
+	/// those of the stylesheets of the same origin.
+	pub fn add_stylesheet(&mut self, origin: Origin, stylesheet: &CSSStyleSheet, environment: &MediaEnvironment) {
+		let blocks = stylesheet.layered_blocks(environment, self.layers.entry(origin).or_default());
+		self.add_layered_blocks(origin, blocks);
+		self.ranks = OnceCell::new();
+	}
+
+	/// Unlayered styles rank last, so they win over every layer for normal declarations and lose for important ones.
+	fn layer_rank(&self, origin: Origin, layer: usize, important: bool) -> usize {
+		let ranks = self.ranks.get_or_init(|| self.layers.iter().map(|(origin, layers)| (*origin, layers.ranks())).collect());
+		match ranks.get(&origin) {
+			Some(ranks) if important => ranks.len() - 1 - ranks[layer],
+			Some(ranks) => ranks[layer],
+			None => 0
+		}
+	}
+
+	/// The winning declaration of each property set on the element `node`, including its `style` attribute, and the
+	/// custom properties it computes to given those of its parent. `var()` references are substituted and shorthands
+	/// expanded, so a later `margin-top` overrides part of an earlier `margin`.
+	pub fn cascade(&self, node: &Rc<TreeNode<HTMLNode>>, inherited: &CustomProperties) -> (Vec<Rc<CSSRule>>, CustomProperties) {
+		let mut declarations: Vec<(Precedence, Rc<CSSRule>)> = vec![];
+		for idx in self.candidates(node) {
+			let style_rule = &self.rules[idx];
+			let specificity = match style_rule.selector.match_specificity(node, &self.context) {
+				Some(specificity) => specificity,
+				None => continue
+			};
+			for (order, rule) in style_rule.block.rules.iter().enumerate() {
+				let layer = self.layer_rank(style_rule.origin, style_rule.layer, rule.important);
+				declarations.push((Precedence { origin: origin_rank(style_rule.origin, rule.important), inline: false, layer, specificity, order: (idx, order) }, rule.clone()));
+			}
+		}
+
+		let style = match &*node.value.borrow() {
+			HTMLNode::Element(element) => element.get_attribute("style").map(String::from),
+			_ => None
+		};
+		if let Some(style) = style {
+			for (order, rule) in html_parser::parse_css_inner(&style).rules.iter().enumerate() {
+				declarations.push((Precedence { origin: origin_rank(Origin::Author, rule.important), inline: true, layer: 0, specificity: Specificity::default(), order: (self.rules.len(), order) }, rule.clone()));
+			}
+		}
+
+		let (custom, declarations): (Vec<_>, Vec<_>) = declarations.into_iter().partition(|(_, rule)| css_variables::is_custom_property(&rule.name));
+		let custom_properties = CustomProperties::compute(inherited, &winners(custom));
+		let declarations = declarations.into_iter().flat_map(|(precedence, rule)| {
+			css_shorthand::expand(&custom_properties.substitute(&rule)).into_iter().map(move |rule| (precedence, rule))
+		}).collect();
+
+		(winners(declarations), custom_properties)
+	}
+}
+
+/// The highest precedence declaration of each property, ordered by precedence.
+fn winners(declarations: Vec<(Precedence, Rc<CSSRule>)>) -> Vec<Rc<CSSRule>> {
+	let mut winners: HashMap<String, (Precedence, Rc<CSSRule>)> = HashMap::new();
+	for (precedence, rule) in declarations {
+		let current = winners.get(&rule.name).map(|(current, _)| *current);
+		if current.is_none_or(|current| precedence > current) {
+			winners.insert(rule.name.clone(), (precedence, rule));
+		}
+	}
+
+	let mut winners: Vec<(Precedence, Rc<CSSRule>)> = winners.into_values().collect();
+	winners.sort_by_key(|(precedence, _)| *precedence);
+	winners.into_iter().map(|(_, rule)| rule).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use html_parser::css_parse::create_stylesheet;
+
+	/// The winning value of each property of the element `#t` in `html`, with `stylesheets` added in order.
+	fn cascaded(stylesheets: &[(Origin, &str)], html: &str) -> HashMap<String, String> {
+		let environment = MediaEnvironment::screen(800.0, 600.0, 1.0);
+		let mut cascade = Cascade::new();
+		for (origin, css) in stylesheets {
+			cascade.add_stylesheet(*origin, &create_stylesheet(css), &environment);
+		}
+
+		let document = html_parser::parse_html_new(html);
+		let (rules, _) = cascade.cascade(&document.get_element_by_id("t").unwrap(), &CustomProperties::default());
+		rules.iter().map(|rule| (rule.name.clone(), rule.value.clone())).collect()
+	}
+
+	fn color(stylesheets: &[(Origin, &str)], html: &str) -> String {
+		cascaded(stylesheets, html)["color"].clone()
+	}
+
+	#[test]
+	fn specificity_then_order() {
+		let html = "<p id=t class=a></p>";
+		assert_eq!(color(&[(Origin::Author, "#t { color: red } .a { color: blue }")], html), "red");
+		assert_eq!(color(&[(Origin::Author, "p { color: red } .a { color: blue } p.a { color: green }")], html), "green");
+		assert_eq!(color(&[(Origin::Author, ".a { color: red } .a { color: blue }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, ".a { color: red; color: blue }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, ".a { color: red }"), (Origin::Author, "p[class] { color: blue }")], html), "blue");
+	}
+
+	#[test]
+	fn origins_and_importance() {
+		let html = "<p id=t></p>";
+		let sheets = |user_agent: &'static str, user: &'static str, author: &'static str| [(Origin::UserAgent, user_agent), (Origin::User, user), (Origin::Author, author)];
+		assert_eq!(color(&sheets("#t { color: red }", "#t { color: green }", "p { color: blue }"), html), "blue");
+		assert_eq!(color(&sheets("#t { color: red }", "#t { color: green }", ""), html), "green");
+		assert_eq!(color(&sheets("", "p { color: green !important }", "#t { color: blue !important }"), html), "green");
+		assert_eq!(color(&sheets("p { color: red !important }", "p { color: green !important }", ""), html), "red");
+		assert_eq!(color(&sheets("", "", "#t { color: blue } p { color: green !important }"), html), "green");
+	}
+
+	#[test]
+	fn inline_styles() {
+		let html = "<p id=t style='color: red'></p>";
+		assert_eq!(color(&[(Origin::Author, "#t#t { color: blue }")], html), "red");
+		assert_eq!(color(&[(Origin::Author, "p { color: blue !important }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, "p { color: blue !important }")], "<p id=t style='color: red !important'></p>"), "red");
+	}
+
+	#[test]
+	fn layers() {
+		let html = "<p id=t></p>";
+		assert_eq!(color(&[(Origin::Author, "@layer a, b; @layer b { p { color: blue } } @layer a { #t { color: red } }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, "@layer a { #t { color: red } } p { color: green }")], html), "green");
+		assert_eq!(color(&[(Origin::Author, "@layer a { p { color: red } } @layer a { p { color: blue } }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, "@layer a { p { color: red } }"), (Origin::Author, "@layer b { p { color: blue } }")], html), "blue");
+		assert_eq!(color(&[(Origin::Author, "@layer a.x { #t { color: red } } @layer a { p { color: blue } }")], html), "blue");
+	}
+
+	/// Important declarations reverse the layer order, so earlier layers win and unlayered ones lose.
+	#[test]
+	fn important_layers_reverse() {
+		let html = "<p id=t></p>";
+		assert_eq!(color(&[(Origin::Author, "@layer a, b; @layer a { p { color: red !important } } @layer b { p { color: blue !important } }")], html), "red");
+		assert_eq!(color(&[(Origin::Author, "@layer a { p { color: red !important } } #t { color: green !important }")], html), "red");
+		assert_eq!(color(&[(Origin::Author, "@layer a { p { color: red !important } } #t { color: green }")], html), "red");
+		assert_eq!(color(&[(Origin::User, "@layer a { p { color: red } }"), (Origin::Author, "@layer a { p { color: blue !important } }")], html), "blue");
+	}
+
+	#[test]
+	fn shorthands_compete_per_longhand() {
+		let styles = cascaded(&[(Origin::Author, "#t { margin-top: 1px } p { margin: 2px !important; margin-left: 3px }")], "<p id=t></p>");
+		assert_eq!((styles["margin-top"].as_str(), styles["margin-left"].as_str(), styles["margin-bottom"].as_str()), ("2px", "2px", "2px"));
+	}
+}
@@ -1,5 +1,5 @@
 pub mod html_node;
 pub mod html_block_elements;
-pub mod selector;
 pub mod html_root;
-pub mod html_text;
\ No newline at end of file
+pub mod html_text;
+pub mod html_template;
\ No newline at end of file
@@ -6,17 +6,20 @@ use crate::html_render;
 use html_parser;
 use std::rc::Rc;
 use std::collections::HashMap;
-use crate::dom::selector;
+use html_parser::selector;
+use html_parser::css_value::Color;
 
 pub struct BlockElement {
 	element: Rc<html_node::Node>,
+	/// The computed `color` of the parent, which `color` inherits.
+	parent_color: Color,
 	pre_computed_styles: HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>
 }
 
-impl<'a> BlockElement {
-	pub fn new(element: Rc<html_node::Node>) -> BlockElement {
+impl BlockElement {
+	pub fn new(element: Rc<html_node::Node>, parent_color: Color) -> BlockElement {
 		BlockElement {
-			element,
+			element, parent_color,
 			pre_computed_styles: HashMap::new(),
 		}
 	}
@@ -24,49 +27,55 @@ impl<'a> BlockElement {
 
 impl html_node::HTMLNode for BlockElement {
 	fn compute_style(&self, display: &glium::Display, self_node: Rc<html_node::RenderBox>) -> Box<dyn html_render::RenderCall> {
-		let mut pre_computed = HashMap::new();
-		self.accumulate_precomputed_styles(&mut pre_computed, self_node);
+		// Properties the element doesn't set have their initial value, except `color`, which inherits through
+		// `parent_color`. Only explicit `inherit` values come from the ancestors.
+		let mut pre_computed = self.pre_computed_styles.clone();
+		if pre_computed.iter().any(is_explicit_inherit) {
+			let parent = self_node.get_parent().unwrap();
+			parent.value.borrow().accumulate_precomputed_styles(&mut pre_computed, parent.clone());
+		}
 
 		let rect_render = html_render::RectRender::new(display);
-		let computed = styles::compute_styles(&pre_computed);
+		let viewport = display.gl_window().window().inner_size();
+		let computed = styles::compute_styles(&pre_computed, (viewport.width as f32, viewport.height as f32), self.parent_color);
 
 		let mut width: f32 = 0.0; let mut height: f32 = 0.0;
 		if let Some(styles::ComputedStyleValue::Size(w)) = computed.get(&styles::StyleName::Width) { width = *w; }
 		if let Some(styles::ComputedStyleValue::Size(h)) = computed.get(&styles::StyleName::Height) { height = *h; }
 
 		let mut detail = html_render::RectDetail::new(0.0, 0.0, width, height);
-		detail.opaque(0.0, 0.0, 0.0);
+		if let Some(styles::ComputedStyleValue::Color(color)) = computed.get(&styles::StyleName::BackgroundColor) {
+			detail.color(color.r, color.g, color.b, color.a);
+		}
 
 		Box::new(html_render::RectRenderCall::new(Rc::new(detail), Rc::new(rect_render)))
 	}
 
 	fn matches(&self, selector: &selector::Selector) -> bool {
-		if let html_node::NodeT::Element(el) = &*self.element.value.borrow() {
-			selector.matches(el)
-		} else { panic!("Unreachable") }
+		selector.matches(&self.element)
 	}
 
 	fn prepare(&mut self, global_applicable_styles: &HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>) {
-		if let html_node::NodeT::Element(el) = &*self.element.value.borrow() {
-			if let Some(style_attr) = el.get_attribute(&String::from("style")) {
-				styles::append(&mut self.pre_computed_styles, html_parser::parse_css_inner(style_attr.as_str()));
-			}
-		}
-
 		for (key, value) in global_applicable_styles {
-			self.pre_computed_styles.entry(key.clone()).or_insert(value.clone());
+			self.pre_computed_styles.insert(*key, value.clone());
 		}
 	}
 	
 	fn accumulate_precomputed_styles(&self, pcs: &mut HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>, self_node: Rc<html_node::RenderBox>) {
-		for (key, value) in &self.pre_computed_styles {
-			if !pcs.contains_key(key) || (std::mem::discriminant(pcs.get(key).unwrap().as_ref()) == std::mem::discriminant(&styles::PreComputedStyleValue::Inherit)) {
-				pcs.insert(key.clone(), value.clone());
+		for (key, value) in pcs.iter_mut() {
+			if is_explicit_inherit((key, value)) {
+				*value = self.pre_computed_styles.get(key).cloned().unwrap_or_else(|| Rc::new(styles::PreComputedStyleValue::VDefault));
 			}
 		}
 
-		let parent = self_node.get_parent().unwrap();
-		let parent_ref = &*parent.value.borrow();
-		parent_ref.accumulate_precomputed_styles(pcs, self_node.get_parent().unwrap());
+		if pcs.iter().any(is_explicit_inherit) {
+			let parent = self_node.get_parent().unwrap();
+			parent.value.borrow().accumulate_precomputed_styles(pcs, parent.clone());
+		}
 	}
-}
\ No newline at end of file
+}
+
+/// An `inherit` that must be looked up in the parent. `color` is always inherited, through `parent_color`.
+fn is_explicit_inherit((key, value): (&styles::StyleName, &Rc<styles::PreComputedStyleValue>)) -> bool {
+	*key != styles::StyleName::Color && matches!(**value, styles::PreComputedStyleValue::Inherit)
+}
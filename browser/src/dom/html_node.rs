@@ -6,7 +6,7 @@ use std::fmt;
 use crate::styles;
 use std::rc::Rc;
 use tree;
-use super::selector;
+use html_parser::selector;
 
 pub type Node = tree::TreeNode<html_parser::tree_generation_2::HTMLNode>;
 pub type NodeT = html_parser::tree_generation_2::HTMLNode;
@@ -16,6 +16,7 @@ pub trait HTMLNode {
 	fn compute_style(&self, display: &glium::Display, self_node: Rc<RenderBox>) -> Box<dyn html_render::RenderCall>;
 	fn name(&self) -> String { String::from("HTMLNode") }
 	fn prepare(&mut self, global_applicable_styles: &HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>);
+	/// Replaces the `inherit` values in `pcs` with this node's own, asking its parent for those it inherits too.
 	fn accumulate_precomputed_styles(&self, pcs: &mut HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>, self_node: Rc<RenderBox>);
 	#[allow(unused_variables)]
 	fn matches(&self, selector: &selector::Selector) -> bool { false }
@@ -3,16 +3,20 @@
 use crate::dom::html_node;
 use crate::styles;
 use crate::html_render;
+use crate::fonts::FontFace;
+use std::ops::Range;
 use std::rc::Rc;
 use std::collections::HashMap;
 
 pub struct TextNode {
-	text: String
+	text: String,
+	/// The face each byte range of `text` is drawn with, or `None` for a system font.
+	font_runs: Vec<(Range<usize>, Option<FontFace>)>
 }
 
 impl TextNode {
-	pub fn new(text: String) -> TextNode {
-		TextNode {text}
+	pub fn new(text: String, font_runs: Vec<(Range<usize>, Option<FontFace>)>) -> TextNode {
+		TextNode {text, font_runs}
 	}
 }
 
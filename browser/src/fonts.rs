@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use html_parser::css_font_face::{self, FontFaceRule, FontSource, FontStyle};
+use html_parser::css_parse::{ComponentValue, CSSRule};
+use html_parser::css_tokenize::CSSToken;
+use html_parser::css_value::CSSValue;
+
+const FORMATS: [&str; 4] = ["truetype", "opentype", "truetype-variations", "opentype-variations"];
+
+#[derive(Debug)]
+pub enum FontError {
+	Missing(PathBuf, io::Error),
+	UnsupportedFormat(String),
+	/// A file that isn't a TrueType or OpenType font.
+	InvalidFont(PathBuf),
+	Unsupported(String),
+	/// An `@font-face` none of whose sources could be loaded, by family.
+	NoUsableSource(String)
+}
+
+impl fmt::Display for FontError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FontError::Missing(path, err) => write!(f, "Could not load font {}: {}", path.display(), err),
+			FontError::UnsupportedFormat(format) => write!(f, "Could not load font: the format `{}` is not supported", format),
+			FontError::InvalidFont(path) => write!(f, "Could not load font {}: not a TrueType or OpenType file", path.display()),
+			FontError::Unsupported(source) => write!(f, "Could not load font {}: only local files are supported", source),
+			FontError::NoUsableSource(family) => write!(f, "No source of font family \"{}\" could be loaded, falling back", family)
+		}
+	}
+}
+
+/// A loaded `@font-face`, with the file of the source that was used.
+#[derive(Debug, Clone)]
+pub struct FontFace {
+	pub rule: FontFaceRule,
+	pub path: PathBuf,
+	pub data: Rc<Vec<u8>>
+}
+
+/// The computed `font-family`, `font-weight` and `font-style` of an element, which pick the faces its text is drawn
+/// with. All three are inherited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontSelection {
+	/// The family names in order of preference. Generic families are left out, as they are the system fonts.
+	pub families: Vec<String>,
+	pub weight: u16,
+	pub style: FontStyle
+}
+impl Default for FontSelection {
+	fn default() -> FontSelection {
+		FontSelection {
+			families: vec![],
+			weight: 400,
+			style: FontStyle::Normal
+		}
+	}
+}
+impl FontSelection {
+	/// The selection of an element with the winning declarations `rules`, whose parent's is `parent`.
+	pub fn cascade(rules: &[Rc<CSSRule>], parent: &FontSelection) -> FontSelection {
+		let mut font = parent.clone();
+		for rule in rules {
+			match (rule.name.as_str(), &rule.values[..]) {
+				(_, [CSSValue::Keyword(keyword)]) if keyword == "inherit" || keyword == "unset" => {},
+				("font-family", [CSSValue::Keyword(keyword)]) if keyword == "initial" => font.families = vec![],
+				("font-family", _) => font.families = rule.components.split(|value| *value == ComponentValue::Token(CSSToken::Comma)).filter_map(css_font_face::family).collect(),
+				("font-weight", [CSSValue::Keyword(keyword)]) if keyword == "initial" => font.weight = 400,
+				("font-weight", [CSSValue::Keyword(keyword)]) if keyword == "bolder" => font.weight = match parent.weight {
+					0..=349 => 400,
+					350..=549 => 700,
+					550..=899 => 900,
+					weight => weight
+				},
+				("font-weight", [CSSValue::Keyword(keyword)]) if keyword == "lighter" => font.weight = match parent.weight {
+					0..=549 => 100,
+					550..=749 => 400,
+					_ => 700
+				},
+				("font-weight", [value]) => if let Some(weight) = css_font_face::weight(value) { font.weight = weight },
+				("font-style", [CSSValue::Keyword(keyword), ..]) => match keyword.as_str() {
+					"normal" | "initial" => font.style = FontStyle::Normal,
+					"italic" => font.style = FontStyle::Italic,
+					"oblique" => font.style = FontStyle::Oblique,
+					_ => {}
+				},
+				_ => {}
+			}
+		}
+		font
+	}
+}
+
+/// How far the weights of a face are from `desired`, lower is better, following the CSS font matching rules: weights
+/// up to 500 look below 400 before above 500, lighter weights look lighter first and bolder weights look bolder first.
+fn weight_rank(desired: u16, (start, end): (u16, u16)) -> (u8, u16) {
+	if (start..=end).contains(&desired) { return (0, 0); }
+
+	let closest = if end < desired { end } else { start };
+	let (lighter, heavier) = (desired.saturating_sub(closest), closest.saturating_sub(desired));
+	match desired {
+		400..=500 if closest > desired && closest <= 500 => (1, heavier),
+		400..=500 if closest < desired => (2, lighter),
+		400..=500 => (3, heavier),
+		_ if desired < 400 && closest < desired => (1, lighter),
+		_ if desired < 400 => (2, heavier),
+		_ if closest > desired => (1, heavier),
+		_ => (2, lighter)
+	}
+}
+
+fn style_rank(desired: FontStyle, style: FontStyle) -> usize {
+	let order = match desired {
+		FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+		FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+		FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic]
+	};
+	order.iter().position(|candidate| *candidate == style).unwrap()
+}
+
+/// Checks the first four bytes for a TrueType, OpenType or collection file.
+fn is_sfnt(data: &[u8]) -> bool {
+	matches!(data.get(..4), Some([0, 1, 0, 0]) | Some(b"true") | Some(b"OTTO") | Some(b"ttcf"))
+}
+
+/// The fonts from `@font-face` rules, which font matching picks from before the system fonts.
+pub struct FontDatabase {
+	faces: Vec<FontFace>,
+	/// Font files by path, so faces sharing a file or loaded again after a stylesheet change aren't read twice.
+	files: HashMap<PathBuf, Rc<Vec<u8>>>
+}
+impl FontDatabase {
+	pub fn new() -> FontDatabase {
+		FontDatabase {
+			faces: vec![],
+			files: HashMap::new()
+		}
+	}
+
+	pub fn faces(&self) -> &[FontFace] {
+		&self.faces
+	}
+
+	pub fn add(&mut self, face: FontFace) {
+		self.faces.push(face);
+	}
+
+	/// Forgets every face, but keeps the files that were read.
+	pub fn clear(&mut self) {
+		self.faces.clear();
+	}
+
+	fn read(&mut self, path: &Path) -> Result<Rc<Vec<u8>>, FontError> {
+		if let Some(data) = self.files.get(path) {
+			return Ok(data.clone());
+		}
+
+		let data = fs::read(path).map_err(|err| FontError::Missing(path.to_path_buf(), err))?;
+		match data.get(..4) {
+			Some(b"wOFF") => return Err(FontError::UnsupportedFormat(String::from("woff"))),
+			Some(b"wOF2") => return Err(FontError::UnsupportedFormat(String::from("woff2"))),
+			_ if !is_sfnt(&data) => return Err(FontError::InvalidFont(path.to_path_buf())),
+			_ => {}
+		}
+
+		let data = Rc::new(data);
+		self.files.insert(path.to_path_buf(), data.clone());
+		Ok(data)
+	}
+
+	fn load_source(&mut self, source: &FontSource) -> Result<(PathBuf, Rc<Vec<u8>>), FontError> {
+		let (url, format) = match source {
+			FontSource::Url(url, format) => (url, format),
+			FontSource::Local(name) => return Err(FontError::Unsupported(format!("local({})", name)))
+		};
+		if let Some(format) = format {
+			if !FORMATS.contains(&format.as_str()) {
+				return Err(FontError::UnsupportedFormat(format.clone()));
+			}
+		}
+
+		let path = match url.strip_prefix("file://") {
+			Some(path) => PathBuf::from(path),
+			None if url.contains("://") || url.starts_with("data:") => return Err(FontError::Unsupported(url.clone())),
+			None => PathBuf::from(url)
+		};
+		let data = self.read(&path)?;
+		Ok((path, data))
+	}
+
+	/// Loads the first usable source of every rule. Every source that couldn't be used is reported, and a rule without
+	/// any is left out so matching falls back to the next family.
+	pub fn load_font_faces(&mut self, rules: &[FontFaceRule]) -> Vec<FontError> {
+		let mut errors = vec![];
+		for rule in rules {
+			let mut loaded = None;
+			for source in &rule.sources {
+				match self.load_source(source) {
+					Ok(file) => {
+						loaded = Some(file);
+						break;
+					},
+					Err(err) => errors.push(err)
+				}
+			}
+
+			match loaded {
+				Some((path, data)) => self.add(FontFace { rule: rule.clone(), path, data }),
+				None => errors.push(FontError::NoUsableSource(rule.family.clone()))
+			}
+		}
+		errors
+	}
+
+	/// The face to draw `ch` with, from the first family in `families` that has one covering it. Within a family the
+	/// closest style wins, then the closest weight, then the face declared last.
+	pub fn match_font(&self, families: &[&str], weight: u16, style: FontStyle, ch: char) -> Option<&FontFace> {
+		families.iter().find_map(|family| {
+			self.faces.iter().enumerate()
+				.filter(|(_, face)| face.rule.family.eq_ignore_ascii_case(family) && face.rule.covers(ch))
+				.min_by_key(|(idx, face)| (style_rank(style, face.rule.style), weight_rank(weight, face.rule.weight), usize::MAX - idx))
+				.map(|(_, face)| face)
+		})
+	}
+
+	/// Splits `text` into the byte ranges drawn with the same face for `font`. A range without a face is drawn with a
+	/// system font.
+	pub fn font_runs(&self, text: &str, font: &FontSelection) -> Vec<(Range<usize>, Option<FontFace>)> {
+		let families: Vec<&str> = font.families.iter().map(String::as_str).collect();
+		let mut runs: Vec<(Range<usize>, Option<&FontFace>)> = vec![];
+		for (idx, ch) in text.char_indices() {
+			let face = self.match_font(&families, font.weight, font.style, ch);
+			match runs.last_mut() {
+				Some((range, last)) if last.map(|last| last as *const FontFace) == face.map(|face| face as *const FontFace) => range.end = idx + ch.len_utf8(),
+				_ => runs.push((idx..idx + ch.len_utf8(), face))
+			}
+		}
+		runs.into_iter().map(|(range, face)| (range, face.cloned())).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use html_parser::css_parse::{create_block, create_stylesheet};
+	use html_parser::media_query::MediaEnvironment;
+
+	/// A database with a face for each `@font-face` in `css`, without loading their files.
+	fn database(css: &str) -> FontDatabase {
+		let mut fonts = FontDatabase::new();
+		for (idx, rule) in create_stylesheet(css).font_faces(&MediaEnvironment::screen(800.0, 600.0, 1.0)).into_iter().enumerate() {
+			fonts.add(FontFace { rule, path: PathBuf::from(idx.to_string()), data: Rc::new(vec![]) });
+		}
+		fonts
+	}
+
+	/// The index of the face `fonts` draws `ch` with, by the order of the rules.
+	fn matched(fonts: &FontDatabase, families: &[&str], weight: u16, style: FontStyle, ch: char) -> Option<String> {
+		fonts.match_font(families, weight, style, ch).map(|face| face.path.display().to_string())
+	}
+
+	#[test]
+	fn weights() {
+		assert_eq!(weight_rank(400, (300, 500)), (0, 0));
+		assert!(weight_rank(400, (500, 500)) < weight_rank(400, (300, 300)));
+		assert!(weight_rank(400, (300, 300)) < weight_rank(400, (600, 600)));
+		assert!(weight_rank(300, (200, 200)) < weight_rank(300, (400, 400)));
+		assert!(weight_rank(600, (800, 800)) < weight_rank(600, (500, 500)));
+		assert!(weight_rank(700, (900, 900)) < weight_rank(700, (600, 600)));
+	}
+
+	#[test]
+	fn matching() {
+		let fonts = database(r#"
+			@font-face { font-family: A; src: url(a.ttf); font-weight: 300 }
+			@font-face { font-family: A; src: url(a.ttf); font-weight: 700 }
+			@font-face { font-family: A; src: url(a.ttf); font-style: italic }
+			@font-face { font-family: A; src: url(a.ttf); font-weight: 700 }
+			@font-face { font-family: B; src: url(b.ttf); unicode-range: U+0-7F }
+		"#);
+		assert_eq!(matched(&fonts, &["a"], 400, FontStyle::Normal, 'x').as_deref(), Some("0"));
+		assert_eq!(matched(&fonts, &["A"], 600, FontStyle::Normal, 'x').as_deref(), Some("3"));
+		assert_eq!(matched(&fonts, &["A"], 700, FontStyle::Oblique, 'x').as_deref(), Some("2"));
+		assert_eq!(matched(&fonts, &["C", "B", "A"], 400, FontStyle::Normal, 'x').as_deref(), Some("4"));
+		assert_eq!(matched(&fonts, &["B", "A"], 400, FontStyle::Normal, 'é').as_deref(), Some("0"));
+		assert_eq!(matched(&fonts, &["B"], 400, FontStyle::Normal, 'é'), None);
+	}
+
+	#[test]
+	fn runs() {
+		let fonts = database("@font-face { font-family: B; src: url(b.ttf); unicode-range: U+0-7F } @font-face { font-family: C; src: url(c.ttf); unicode-range: U+E9 }");
+		let font = FontSelection { families: vec![String::from("B"), String::from("C")], ..FontSelection::default() };
+		let runs: Vec<(std::ops::Range<usize>, Option<String>)> = fonts.font_runs("ab éé☃c", &font).into_iter().map(|(range, face)| (range, face.map(|face| face.path.display().to_string()))).collect();
+		assert_eq!(runs, [(0..3, Some(String::from("0"))), (3..7, Some(String::from("1"))), (7..10, None), (10..11, Some(String::from("0")))]);
+	}
+
+	#[test]
+	fn selection() {
+		let cascade = |css: &str, parent: &FontSelection| FontSelection::cascade(&create_block(css).rules, parent);
+		let parent = cascade("font-family: \"Open Sans\", Fira  Code, sans-serif; font-weight: 600; font-style: oblique 10deg", &FontSelection::default());
+		assert_eq!(parent, FontSelection { families: vec![String::from("Open Sans"), String::from("Fira Code")], weight: 600, style: FontStyle::Oblique });
+		assert_eq!(cascade("color: red", &parent), parent);
+		assert_eq!(cascade("font-weight: bolder; font-style: inherit", &parent).weight, 900);
+		assert_eq!(cascade("font-weight: lighter", &parent).weight, 400);
+		assert_eq!(cascade("font-family: initial; font-weight: initial; font-style: initial", &parent), FontSelection::default());
+	}
+
+	#[test]
+	fn unloadable_sources() {
+		let rules = create_stylesheet("@font-face { font-family: A; src: local(A), url(https://x/a.ttf), url(a.woff) format(woff), url(/missing.ttf) }").font_faces(&MediaEnvironment::screen(800.0, 600.0, 1.0));
+		let mut fonts = FontDatabase::new();
+		let errors: Vec<String> = fonts.load_font_faces(&rules).iter().map(|error| error.to_string()).collect();
+		assert_eq!(errors.len(), 5);
+		assert_eq!(errors[2], "Could not load font: the format `woff` is not supported");
+		assert!(errors[3].starts_with("Could not load font /missing.ttf"));
+		assert_eq!(errors[4], "No source of font family \"A\" could be loaded, falling back");
+		assert!(fonts.faces().is_empty());
+	}
+}
@@ -1,12 +1,13 @@
 #![allow(dead_code)]
 
-use glium;
 use std::rc::Rc;
 
 #[allow(unused_imports)]
 use glium::{glutin, Surface};
 use glium::index::PrimitiveType;
 
+use crate::styles;
+
 pub trait RenderCall {
 	fn render(&self, target: &mut glium::Frame);
 }
@@ -16,6 +17,7 @@ struct Vertex {
 	position: [f32; 2],
 	tex_coord: [f32; 2]
 }
+implement_vertex!(Vertex, position, tex_coord);
 
 pub struct RectRender {
 	vertex_buffer: glium::VertexBuffer<Vertex>,
@@ -26,8 +28,6 @@ impl RectRender {
 	pub fn new(display: &glium::Display) -> RectRender {
 		RectRender {
 			vertex_buffer: {
-				implement_vertex!(Vertex, position, tex_coord);
-			
 				glium::VertexBuffer::new(display,
 					&[
 						Vertex { position: [0.0, 0.0], tex_coord: [0.0, 0.0] },
@@ -83,7 +83,7 @@ impl RectDetail {
 		self.color = (r, g, b, 1.0);
 	}
 
-	fn uniforms(&self, target: &glium::Frame) -> glium::uniforms::UniformsStorage<[f32; 4], glium::uniforms::UniformsStorage<[[f32; 4]; 4], glium::uniforms::EmptyUniforms>> {
+	fn uniforms(&self, target: &glium::Frame) -> glium::uniforms::UniformsStorage<'_, [f32; 4], glium::uniforms::UniformsStorage<'_, [[f32; 4]; 4], glium::uniforms::EmptyUniforms>> {
 		let scale_x = 2.0 / (target.get_dimensions().0 as f32);
 		let scale_y = 2.0 / (target.get_dimensions().1 as f32);
 
@@ -101,7 +101,11 @@ impl RectDetail {
 impl RectRender {
 	fn render(&self, shape: &RectDetail, target: &mut glium::Frame) {
 		let uniforms = shape.uniforms(target);
-        target.draw(&self.vertex_buffer, &self.index_buffer, &self.program, &uniforms, &Default::default()).unwrap();
+		let parameters = glium::DrawParameters {
+			blend: glium::Blend::alpha_blending(),
+			..Default::default()
+		};
+        target.draw(&self.vertex_buffer, &self.index_buffer, &self.program, &uniforms, &parameters).unwrap();
 	}
 }
 pub struct RectRenderCall {
@@ -161,6 +165,10 @@ impl Renderer {
 		&self.display
 	} 
 
+	pub fn set_title(&self, title: &str) {
+		self.display.gl_window().window().set_title(title);
+	}
+
 	pub fn render(&mut self) {
 		let mut target = self.display.draw();
 		target.clear_color(1.0, 1.0, 1.0, 0.0);
@@ -176,6 +184,19 @@ impl Renderer {
 		self.render_calls.push(rc);
 	}
 
+	pub fn clear(&mut self) {
+		self.render_calls.clear();
+	}
+
+	/// The window as seen by media queries. One CSS pixel is drawn as one physical pixel.
+	pub fn media_environment(&self) -> html_parser::media_query::MediaEnvironment {
+		let gl_window = self.display.gl_window();
+		let size = gl_window.window().inner_size();
+		let mut environment = html_parser::media_query::MediaEnvironment::screen(size.width as f32, size.height as f32, gl_window.window().scale_factor() as f32);
+		environment.supports_declaration = styles::supports_declaration;
+		environment
+	}
+
 	pub fn event(&mut self, event: glium::glutin::event::Event<()>, control_flow: &mut glium::glutin::event_loop::ControlFlow) {
 		*control_flow = match event {
 			glutin::event::Event::WindowEvent { event, .. } => match event {
@@ -1,17 +1,14 @@
 #![allow(dead_code)]
 
 #[macro_use] extern crate glium;
-#[macro_use] extern crate lazy_static;
-use std::str::FromStr;
-use std::collections::HashMap;
 
-use html_parser;
 mod html_render;
 mod dom;
-use dom::html_node;
-use dom::selector;
 mod styles;
 mod web_frame;
+mod stylesheet_loader;
+mod cascade;
+mod fonts;
 
 fn main() {
     // let dom_tree = html_parser::parse_html_new("<style>a { width: inherit; height: default; } </style> <style>div { width: 100px; } \na {height: 100px;}</style><div><a></a></div>");
@@ -60,6 +57,12 @@ fn main() {
     //     renderer.event(event, control_flow);
     // });
     let mut app = web_frame::WebApplication::new();
-    app.load_html("<div style=\"width: 100px; height: 20px;\"></div>");
+    match std::env::args().nth(1) {
+        Some(path) => if let Err(err) = app.load_file(std::path::Path::new(&path)) {
+            println!("Could not load {}: {}", path, err);
+            return;
+        },
+        None => app.load_html("<div style=\"width: 100px; height: 20px;\"></div>")
+    }
     app.start();
 }
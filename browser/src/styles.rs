@@ -1,24 +1,49 @@
 #![allow(dead_code)]
 
-use html_parser;
 use std::collections::HashMap;
 use std::rc::Rc;
+use html_parser::css_calc::{CalcContext, CalcNode, CalcType};
+use html_parser::css_color::{self, SpecifiedColor};
+use html_parser::css_shorthand;
+use html_parser::css_variables;
+use html_parser::css_value::{Color, CSSValue};
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub enum StyleName {
 	Width,
-	Height
+	Height,
+	MarginTop,
+	MarginRight,
+	MarginBottom,
+	MarginLeft,
+	PaddingTop,
+	PaddingRight,
+	PaddingBottom,
+	PaddingLeft,
+	Top,
+	Right,
+	Bottom,
+	Left,
+	Color,
+	BackgroundColor,
+	BorderTopColor,
+	BorderRightColor,
+	BorderBottomColor,
+	BorderLeftColor
 }
 
 #[derive(Debug)]
 pub enum SizeType {
 	Pixels(f32),
-	Percent(f32)
+	Percent(f32),
+	/// A relative length or math function, which needs the viewport and font size to resolve.
+	Calc(CalcNode)
 }
 
 #[derive(Debug)]
 pub enum PreComputedStyleValue {
 	Size(SizeType),
+	Color(SpecifiedColor),
 	Inherit,
 	VDefault
 }
@@ -26,35 +51,67 @@ pub enum PreComputedStyleValue {
 #[derive(Debug)]
 pub enum ComputedStyleValue {
 	Size(f32), // px
+	Color(Color),
 	VDefault
 }
 
-fn parse_size(size: &String) -> PreComputedStyleValue {
-	lazy_static! {
-		static ref RE: regex::Regex = regex::Regex::new(r"([0-9.\-]+)(px|%)").unwrap();
+fn parse_size(size: &[CSSValue]) -> Option<PreComputedStyleValue> {
+	match size {
+		[CSSValue::Keyword(keyword)] if keyword == "inherit" => Some(PreComputedStyleValue::Inherit),
+		[CSSValue::Keyword(keyword)] if keyword == "default" || keyword == "initial" || keyword == "unset" || keyword == "auto" => Some(PreComputedStyleValue::VDefault),
+		[CSSValue::Length(value, unit)] => Some(PreComputedStyleValue::Size(match unit.to_px(*value) {
+			Some(px) => SizeType::Pixels(px),
+			None => SizeType::Calc(CalcNode::Length(*value, *unit))
+		})),
+		[CSSValue::Calc(calc)] if calc.kind() == CalcType::Length => Some(PreComputedStyleValue::Size(SizeType::Calc(calc.clone()))),
+		[CSSValue::Number(value)] if *value == 0.0 => Some(PreComputedStyleValue::Size(SizeType::Pixels(0.0))),
+		[CSSValue::Percentage(value)] => Some(PreComputedStyleValue::Size(SizeType::Percent(*value))),
+		_ => None
 	}
+}
 
-	if *size == String::from("inherit") {
-		return PreComputedStyleValue::Inherit;
-	} else if *size == String::from("default") {
-		return PreComputedStyleValue::VDefault;
+fn parse_color(color: &[CSSValue]) -> Option<PreComputedStyleValue> {
+	match color {
+		[CSSValue::Keyword(keyword)] if keyword == "inherit" => Some(PreComputedStyleValue::Inherit),
+		[CSSValue::Keyword(keyword)] if keyword == "default" || keyword == "initial" || keyword == "unset" => Some(PreComputedStyleValue::VDefault),
+		[value] => Some(PreComputedStyleValue::Color(css_color::from_value(value)?)),
+		_ => None
 	}
+}
 
-	let m = RE.captures(size).expect("Invalid size value");
-	match &m[2] {
-		"px" => PreComputedStyleValue::Size(SizeType::Pixels(*&m[1].parse().unwrap())),
-		"%" => PreComputedStyleValue::Size(SizeType::Percent(*&m[1].parse().unwrap())),
-		_ => panic!("Invalid size unit {}", &m[2])
+fn compute_size(size: &PreComputedStyleValue, context: &CalcContext) -> ComputedStyleValue {
+	match size {
+		PreComputedStyleValue::Size(SizeType::Pixels(v)) => ComputedStyleValue::Size(*v),
+		PreComputedStyleValue::Size(SizeType::Percent(v)) => ComputedStyleValue::Size(context.percentage_basis.unwrap_or(0.0) * v / 100.0),
+		PreComputedStyleValue::Size(SizeType::Calc(calc)) => ComputedStyleValue::Size(calc.resolve(context).unwrap_or(0.0)),
+		_ => ComputedStyleValue::Size(0.0)
 	}
 }
 
-fn compute_size(size: &PreComputedStyleValue) -> ComputedStyleValue {
-	match size {
-		PreComputedStyleValue::Size(size) => match size {
-			SizeType::Pixels(v) => ComputedStyleValue::Size(*v),
-			_ => ComputedStyleValue::Size(0.0)
+/// `currentcolor` is the element's `color`, and in `color` itself the inherited one.
+fn compute_color(name: StyleName, color: &PreComputedStyleValue, current_color: Color) -> ComputedStyleValue {
+	ComputedStyleValue::Color(match color {
+		PreComputedStyleValue::Color(SpecifiedColor::Color(color)) => *color,
+		PreComputedStyleValue::Color(SpecifiedColor::CurrentColor) => current_color,
+		_ => match name {
+			StyleName::BackgroundColor => Color::new(0.0, 0.0, 0.0, 0.0),
+			_ => current_color
 		}
-		PreComputedStyleValue::VDefault | _ => ComputedStyleValue::Size(0.0)
+	})
+}
+
+impl StyleName {
+	/// Whether percentages are of the containing block's height rather than its width.
+	fn is_vertical(&self) -> bool {
+		matches!(self, StyleName::Height | StyleName::Top | StyleName::Bottom)
+	}
+
+	fn is_color(&self) -> bool {
+		matches!(self, StyleName::Color | StyleName::BackgroundColor | StyleName::BorderTopColor | StyleName::BorderRightColor | StyleName::BorderBottomColor | StyleName::BorderLeftColor)
+	}
+
+	fn parse(&self, values: &[CSSValue]) -> Option<PreComputedStyleValue> {
+		if self.is_color() { parse_color(values) } else { parse_size(values) }
 	}
 }
 
@@ -63,22 +120,92 @@ pub fn append(styles: &mut HashMap<StyleName, Rc<PreComputedStyleValue>>, block:
 }
 
 pub fn append_ref(styles: &mut HashMap<StyleName, Rc<PreComputedStyleValue>>, block: &html_parser::css_parse::CSSBlock) {
-	for rule in &block.rules {
-		match rule.name.as_str() {
-			"width" => {styles.insert(StyleName::Width, Rc::new(parse_size(&rule.value)));},
-			"height" => {styles.insert(StyleName::Height, Rc::new(parse_size(&rule.value)));},
-			_ => { println!("Invalid style-rule: {}", rule.name) }
+	append_rules(styles, &block.rules);
+}
+
+fn style_name(name: &str) -> Option<StyleName> {
+	Some(match name {
+		"width" => StyleName::Width,
+		"height" => StyleName::Height,
+		"margin-top" => StyleName::MarginTop,
+		"margin-right" => StyleName::MarginRight,
+		"margin-bottom" => StyleName::MarginBottom,
+		"margin-left" => StyleName::MarginLeft,
+		"padding-top" => StyleName::PaddingTop,
+		"padding-right" => StyleName::PaddingRight,
+		"padding-bottom" => StyleName::PaddingBottom,
+		"padding-left" => StyleName::PaddingLeft,
+		"top" => StyleName::Top,
+		"right" => StyleName::Right,
+		"bottom" => StyleName::Bottom,
+		"left" => StyleName::Left,
+		"color" => StyleName::Color,
+		"background-color" => StyleName::BackgroundColor,
+		"border-top-color" => StyleName::BorderTopColor,
+		"border-right-color" => StyleName::BorderRightColor,
+		"border-bottom-color" => StyleName::BorderBottomColor,
+		"border-left-color" => StyleName::BorderLeftColor,
+		_ => return None
+	})
+}
+
+/// Unknown properties and invalid values were reported as diagnostics when their stylesheet was loaded, so they and
+/// the properties that aren't rendered yet are skipped quietly.
+pub fn append_rules(styles: &mut HashMap<StyleName, Rc<PreComputedStyleValue>>, rules: &[Rc<html_parser::css_parse::CSSRule>]) {
+	for rule in rules {
+		if let Some(name) = style_name(&rule.name) {
+			if let Some(value) = name.parse(&rule.values) {
+				styles.insert(name, Rc::new(value));
+			}
 		}
 	}
 }
 
-pub fn compute_styles(styles: &HashMap<StyleName, Rc<PreComputedStyleValue>>) -> HashMap<StyleName, ComputedStyleValue> {
+/// Whether a declaration would be rendered, which is what `@supports` tests. A shorthand is supported when all of
+/// its longhands are, and a value with `var()` when the property is.
+pub fn supports_declaration(rule: &Rc<html_parser::css_parse::CSSRule>) -> bool {
+	if css_variables::is_custom_property(&rule.name) { return true; }
+	if css_variables::contains_var(&rule.components) {
+		let longhands = css_shorthand::longhands(&rule.name).unwrap_or_else(|| vec![rule.name.clone()]);
+		return longhands.iter().all(|name| style_name(name).is_some());
+	}
+
+	let longhands = css_shorthand::expand(rule);
+	!longhands.is_empty() && longhands.iter().all(|rule| style_name(&rule.name).is_some_and(|name| name.parse(&rule.values).is_some()))
+}
+
+/// The computed `color` of an element with `styles`, whose parent's is `parent_color`. `color` is inherited, so
+/// without a value of its own, or with `inherit` or `currentcolor`, the element has its parent's.
+pub fn compute_current_color(styles: &HashMap<StyleName, Rc<PreComputedStyleValue>>, parent_color: Color) -> Color {
+	match styles.get(&StyleName::Color) {
+		Some(color) => match compute_color(StyleName::Color, color, parent_color) {
+			ComputedStyleValue::Color(color) => color,
+			_ => panic!("Unreachable")
+		},
+		None => parent_color
+	}
+}
+
+/// Resolves styles for a viewport of `viewport` pixels. There is no box layout yet, so the viewport is also the
+/// containing block, and font sizes are the default 16px. `parent_color` is the computed `color` of the parent.
+pub fn compute_styles(styles: &HashMap<StyleName, Rc<PreComputedStyleValue>>, viewport: (f32, f32), parent_color: Color) -> HashMap<StyleName, ComputedStyleValue> {
+	let (viewport_width, viewport_height) = viewport;
+	let current_color = compute_current_color(styles, parent_color);
+
 	let mut new_map = HashMap::new();
 	for (rule, value) in styles {
-		match rule {
-			StyleName::Width => new_map.insert(StyleName::Width, compute_size(value)),
-			StyleName::Height => new_map.insert(StyleName::Height, compute_size(value))
+		if rule.is_color() {
+			new_map.insert(*rule, compute_color(*rule, value, current_color));
+			continue;
+		}
+
+		let context = CalcContext {
+			percentage_basis: Some(if rule.is_vertical() { viewport_height } else { viewport_width }),
+			font_size: 16.0,
+			root_font_size: 16.0,
+			viewport_width, viewport_height
 		};
+		new_map.insert(*rule, compute_size(value, &context));
 	}
 
 	new_map
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use html_parser::css_font_face::FontSource;
+use html_parser::css_lint::Diagnostic;
+use html_parser::css_parse::{CSSStatement, CSSStyleSheet, ImportRule};
+use html_parser::source_position::SourcePosition;
+
+#[derive(Debug)]
+pub enum LoadError {
+	Missing(PathBuf, io::Error),
+	Unsupported(String),
+	Cycle(Vec<PathBuf>),
+	/// A `<style>` element whose contents aren't text, which is skipped.
+	NotText,
+	/// A problem in a stylesheet, by where the stylesheet came from.
+	Css(String, Diagnostic)
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LoadError::Missing(path, err) => write!(f, "Could not load stylesheet {}: {}", path.display(), err),
+			LoadError::Unsupported(url) => write!(f, "Could not load stylesheet {}: only local files are supported", url),
+			LoadError::Cycle(chain) => write!(f, "Stylesheet import cycle: {}", chain.iter().map(|path| path.display().to_string()).collect::<Vec<String>>().join(" -> ")),
+			LoadError::NotText => write!(f, "Skipped a <style> element whose contents aren't text"),
+			LoadError::Css(source, diagnostic) => write!(f, "{}:{}", source, diagnostic)
+		}
+	}
+}
+
+/// Resolves `url` against the directory `base`. Only relative paths, absolute paths and `file://` URLs are local.
+fn resolve(base: &Path, url: &str) -> Option<PathBuf> {
+	let url = url.split(['?', '#']).next().unwrap();
+	if let Some(path) = url.strip_prefix("file://") {
+		return Some(PathBuf::from(path));
+	}
+	if url.contains("://") || url.starts_with("//") || url.starts_with("data:") {
+		return None;
+	}
+
+	Some(base.join(url))
+}
+
+/// Makes the font files of `@font-face` rules in `statements` absolute, so they can be loaded without knowing which
+/// stylesheet they came from. URLs that aren't local are kept as they are.
+fn resolve_font_sources(statements: &mut [CSSStatement], base: &Path) {
+	for statement in statements {
+		match statement {
+			CSSStatement::FontFace(rule) => for source in rule.sources.iter_mut() {
+				if let FontSource::Url(url, _) = source {
+					if let Some(path) = resolve(base, url) {
+						*url = path.to_string_lossy().into_owned();
+					}
+				}
+			},
+			CSSStatement::Media(rule) => resolve_font_sources(&mut rule.statements, base),
+			CSSStatement::Supports(rule) => resolve_font_sources(&mut rule.statements, base),
+			CSSStatement::Layer(rule) => if let Some(statements) = &mut rule.statements {
+				resolve_font_sources(statements, base);
+			},
+			_ => {}
+		}
+	}
+}
+
+/// Loads the stylesheets referenced by `@import` rules and `<link>` elements from disk. Imports and font files are
+/// resolved relative to the file that contains them, and a file that imports itself, directly or not, is skipped.
+pub struct StylesheetLoader {
+	loading: Vec<PathBuf>,
+	errors: Vec<LoadError>
+}
+impl StylesheetLoader {
+	pub fn new() -> StylesheetLoader {
+		StylesheetLoader {
+			loading: vec![],
+			errors: vec![]
+		}
+	}
+
+	pub fn errors(&self) -> &[LoadError] {
+		&self.errors
+	}
+
+	/// Records a problem found outside of the loader, so it is reported with the others.
+	pub fn error(&mut self, error: LoadError) {
+		self.errors.push(error);
+	}
+
+	/// Parses `css`, keeping its diagnostics as errors of `source`. `start` is where the stylesheet begins in
+	/// `source`, for the text of a `<style>` element.
+	pub fn parse(&mut self, css: &str, source: &str, start: SourcePosition) -> CSSStyleSheet {
+		let (stylesheet, diagnostics) = html_parser::parse_css(css);
+		self.errors.extend(diagnostics.iter().map(|diagnostic| LoadError::Css(String::from(source), diagnostic.relative_to(start))));
+		stylesheet
+	}
+
+	/// Fills in the stylesheets of every `@import` in `stylesheet`, whose own location is the directory `base`.
+	pub fn load_imports(&mut self, stylesheet: &mut CSSStyleSheet, base: &Path) {
+		resolve_font_sources(&mut stylesheet.statements, base);
+		for statement in stylesheet.statements.iter_mut() {
+			if let CSSStatement::Import(rule) = statement {
+				self.load(rule, base);
+			}
+		}
+	}
+
+	pub fn load(&mut self, rule: &mut ImportRule, base: &Path) {
+		let path = match resolve(base, &rule.url) {
+			Some(path) => path,
+			None => {
+				self.errors.push(LoadError::Unsupported(rule.url.clone()));
+				return;
+			}
+		};
+		let path = fs::canonicalize(&path).unwrap_or(path);
+
+		if self.loading.contains(&path) {
+			let mut chain = self.loading.clone();
+			chain.push(path);
+			self.errors.push(LoadError::Cycle(chain));
+			return;
+		}
+
+		let css = match fs::read_to_string(&path) {
+			Ok(css) => css,
+			Err(err) => {
+				self.errors.push(LoadError::Missing(path, err));
+				return;
+			}
+		};
+
+		let mut stylesheet = self.parse(css.as_str(), &path.display().to_string(), SourcePosition::new());
+		self.loading.push(path.clone());
+		self.load_imports(&mut stylesheet, path.parent().unwrap_or(base));
+		self.loading.pop();
+
+		rule.stylesheet = Some(stylesheet);
+	}
+}
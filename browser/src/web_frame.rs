@@ -1,75 +1,315 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
 
-use html_parser;
 use crate::html_render;
 use crate::styles;
 use crate::dom;
-use crate::dom::html_node;
-use crate::dom::selector;
+use crate::dom::html_node::{self, HTMLNode};
+use crate::stylesheet_loader::{LoadError, StylesheetLoader};
+use crate::fonts::{FontDatabase, FontSelection};
+use crate::cascade::{Cascade, Origin};
+use html_parser::css_lint;
+use html_parser::document::Document;
+use html_parser::css_parse::{self, CSSBlock, CSSStatement, CSSStyleSheet, ImportRule};
+use html_parser::css_value::Color;
+use html_parser::css_variables::CustomProperties;
+use html_parser::media_query::{MediaEnvironment, MediaQueryList};
+use html_parser::source_position::SourcePosition;
+
+/// What the cascade computed for an element.
+#[derive(Clone)]
+struct ElementStyle {
+	styles: HashMap<styles::StyleName, Rc<styles::PreComputedStyleValue>>,
+	custom_properties: CustomProperties,
+	/// The computed `color`, which children inherit.
+	color: Color,
+	font: FontSelection
+}
+
+/// A node compared by identity. It holds the node, so no other node can take its place while it is a key.
+struct NodeKey(Rc<html_node::Node>);
+impl PartialEq for NodeKey {
+	fn eq(&self, other: &NodeKey) -> bool {
+		Rc::ptr_eq(&self.0, &other.0)
+	}
+}
+impl Eq for NodeKey {}
+impl std::hash::Hash for NodeKey {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		Rc::as_ptr(&self.0).hash(state);
+	}
+}
+
+struct Page {
+	document: Document,
+	stylesheets: Vec<(Origin, Rc<CSSStyleSheet>)>,
+	/// The style of each element, for the `Document::version` in `styled_version`. Any change to the document can
+	/// affect other elements through sibling combinators and `:has()`, so a new version forgets every style.
+	styles: RefCell<HashMap<NodeKey, ElementStyle>>,
+	styled_version: Cell<usize>,
+	fonts: FontDatabase
+}
+impl Page {
+	fn new(document: Document, stylesheets: Vec<(Origin, Rc<CSSStyleSheet>)>) -> Page {
+		Page {
+			styled_version: Cell::new(document.version()),
+			document, stylesheets,
+			styles: RefCell::new(HashMap::new()),
+			fonts: FontDatabase::new()
+		}
+	}
+
+	/// Loads the `@font-face` rules of every stylesheet again, reporting the fonts that couldn't be loaded.
+	fn load_fonts(&mut self, environment: &MediaEnvironment) {
+		let rules: Vec<_> = self.stylesheets.iter().flat_map(|(_, stylesheet)| stylesheet.font_faces(environment)).collect();
+		self.fonts.clear();
+		report(&self.fonts.load_font_faces(&rules));
+	}
+
+	fn restyle_all(&self) {
+		self.styles.borrow_mut().clear();
+	}
+
+	/// Forgets every style if the document changed since they were computed.
+	fn forget_stale_styles(&self) {
+		if self.styled_version.get() != self.document.version() {
+			self.restyle_all();
+			self.styled_version.set(self.document.version());
+		}
+	}
+}
+
+/// Prints problems with the page's stylesheets and fonts, which don't stop it from being shown, to stderr.
+fn report<E: fmt::Display>(errors: &[E]) {
+	for error in errors {
+		eprintln!("{}", error);
+	}
+}
 
 pub struct WebApplication {
 	renderer: html_render::Renderer,
-	event_loop: glium::glutin::event_loop::EventLoop<()>
+	event_loop: glium::glutin::event_loop::EventLoop<()>,
+	page: Option<Page>,
+	stylesheets: Vec<(Origin, Rc<CSSStyleSheet>)>
 }
 impl WebApplication {
 	pub fn new() -> WebApplication {
 		let (renderer, event_loop) = html_render::Renderer::create_context_and_loop("Browser");
 		WebApplication {
-			renderer, event_loop
+			renderer, event_loop,
+			page: None,
+			stylesheets: vec![]
 		}
 	}
 
-	fn _start(event_loop: glium::glutin::event_loop::EventLoop<()>, mut renderer: html_render::Renderer) {
+	fn _start(event_loop: glium::glutin::event_loop::EventLoop<()>, mut renderer: html_render::Renderer, mut page: Option<Page>) {
 		event_loop.run(move |event, _, control_flow| {
+			if let glium::glutin::event::Event::WindowEvent { event: glium::glutin::event::WindowEvent::Resized(..), .. } = &event {
+				if let Some(page) = &mut page {
+					// `@font-face` rules in `@media` rules may apply to the new size, or no longer apply.
+					page.load_fonts(&renderer.media_environment());
+					page.restyle_all();
+					WebApplication::layout(&mut renderer, page);
+				}
+			}
 			renderer.event(event, control_flow);
 		});
 	}
 
 	pub fn start(self) {
-		WebApplication::_start(self.event_loop, self.renderer);
+		WebApplication::_start(self.event_loop, self.renderer, self.page);
+	}
+
+	/// Loads an HTML file, resolving its stylesheets relative to the file.
+	pub fn load_file(&mut self, path: &Path) -> io::Result<()> {
+		let html = fs::read_to_string(path)?;
+		self.load_html_at(html.as_str(), path.parent().unwrap_or(Path::new(".")));
+		Ok(())
+	}
+
+	/// Adds a user agent or user stylesheet, which applies to documents loaded afterwards.
+	pub fn add_stylesheet(&mut self, origin: Origin, css: &str) {
+		let mut loader = StylesheetLoader::new();
+		let stylesheet = loader.parse(css, "<user agent or user stylesheet>", SourcePosition::new());
+		report(loader.errors());
+		self.stylesheets.push((origin, Rc::new(stylesheet)));
 	}
 
 	pub fn load_html(&mut self, html: &str) {
-		let dom_tree = html_parser::parse_html_new(html);
-		let annotated_tree = dom_tree.clone().map(|node| {
-			match &*node.value.borrow() {
-				html_node::NodeT::Root => Box::new(dom::html_root::RootElement::new()) as Box<dyn html_node::HTMLNode>,
-				html_node::NodeT::Element(_) => Box::new(dom::html_block_elements::BlockElement::new(node.clone())) as Box<dyn html_node::HTMLNode>,
-				html_node::NodeT::Text(text) => Box::new(dom::html_text::TextNode::new(text.clone())) as Box<dyn html_node::HTMLNode>
+		self.load_html_at(html, Path::new("."));
+	}
+
+	/// Loads `html` as if it was a document in the directory `base`.
+	pub fn load_html_at(&mut self, html: &str, base: &Path) {
+		let document = html_parser::parse_html_new(html);
+		if let Some(title) = document.title() {
+			self.renderer.set_title(title.as_str());
+		}
+
+		WebApplication::report_inline_styles(&document);
+		let mut stylesheets = self.stylesheets.clone();
+		stylesheets.extend(WebApplication::load_stylesheets(&document, base).into_iter().map(|stylesheet| (Origin::Author, Rc::new(stylesheet))));
+		let mut page = Page::new(document, stylesheets);
+		page.load_fonts(&self.renderer.media_environment());
+		WebApplication::layout(&mut self.renderer, &page);
+		self.page = Some(page);
+	}
+
+	pub fn document(&self) -> Option<&Document> {
+		self.page.as_ref().map(|page| &page.document)
+	}
+
+	/// The fonts of the loaded document's `@font-face` rules.
+	pub fn fonts(&self) -> Option<&FontDatabase> {
+		self.page.as_ref().map(|page| &page.fonts)
+	}
+
+	/// The stylesheets of the loaded document in cascade order: user agent and user stylesheets first, then the
+	/// document's own.
+	pub fn stylesheets(&self) -> Vec<(Origin, Rc<CSSStyleSheet>)> {
+		self.page.as_ref().map_or(vec![], |page| page.stylesheets.clone())
+	}
+
+	/// Changes the stylesheet at `idx` of `stylesheets()` through its CSSOM methods and restyles the page.
+	pub fn update_stylesheet<A, R>(&mut self, idx: usize, cb: A) -> Option<R> where A: FnOnce(&mut CSSStyleSheet) -> R {
+		let page = self.page.as_mut()?;
+		let result = cb(Rc::make_mut(&mut page.stylesheets.get_mut(idx)?.1));
+
+		page.load_fonts(&self.renderer.media_environment());
+		page.restyle_all();
+		WebApplication::layout(&mut self.renderer, page);
+		Some(result)
+	}
+
+	/// Changes the declarations of an element's `style` attribute and restyles the page.
+	pub fn update_inline_style<A, R>(&mut self, node: &Rc<html_node::Node>, cb: A) -> Option<R> where A: FnOnce(&mut CSSBlock) -> R {
+		let page = self.page.as_ref()?;
+		let mut block = match &*node.value.borrow() {
+			html_node::NodeT::Element(el) => html_parser::parse_css_inner(el.get_attribute("style").unwrap_or("")),
+			_ => return None
+		};
+
+		let result = cb(&mut block);
+		if block.rules.is_empty() {
+			page.document.remove_attribute(node, "style");
+		} else {
+			page.document.set_attribute(node, "style", block.css_text().as_str());
+		}
+
+		WebApplication::layout(&mut self.renderer, page);
+		Some(result)
+	}
+
+	/// Reports the diagnostics of every `style` attribute, positioned within the attribute value.
+	fn report_inline_styles(document: &Document) {
+		let mut errors = vec![];
+		for node in document.root().find(|node| matches!(&*node.value.borrow(), html_node::NodeT::Element(el) if el.has_attribute("style"))) {
+			if let html_node::NodeT::Element(el) = &*node.value.borrow() {
+				let source = format!("style attribute of <{}>", el.tagname());
+				errors.extend(css_lint::lint_declarations(el.get_attribute_unwrapped("style")).into_iter().map(|diagnostic| LoadError::Css(source.clone(), diagnostic)));
 			}
+		}
+		report(&errors);
+	}
+
+	/// The `<style>` and `<link rel=stylesheet>` stylesheets of `document` in document order, with imports loaded.
+	fn load_stylesheets(document: &Document, base: &Path) -> Vec<CSSStyleSheet> {
+		let mut loader = StylesheetLoader::new();
+		let sources = document.root().find(|node| match &*node.value.borrow() {
+			html_node::NodeT::Element(el) if el.is_html_tag("link") => el.has_attribute("href") && el.get_attribute("rel").is_some_and(|rel| {
+				rel.split_ascii_whitespace().any(|rel| rel.eq_ignore_ascii_case("stylesheet"))
+			}),
+			html_node::NodeT::Element(el) => el.is_html_tag("style"),
+			_ => false
 		});
-	
-		let styles_elements = dom_tree.clone().find(|node| {
+
+		let stylesheets = sources.iter().filter_map(|node| {
+			if let html_node::NodeT::Element(el) = &*node.value.borrow() {
+				if el.is_html_tag("link") {
+					let media = css_parse::parse_component_values(el.get_attribute("media").unwrap_or(""));
+					let mut rule = ImportRule::new(String::from(el.get_attribute_unwrapped("href")), MediaQueryList::parse(&media));
+					loader.load(&mut rule, base);
+					return Some(CSSStyleSheet { statements: vec![CSSStatement::Import(rule)] });
+				}
+			}
+
+			let (css, start) = match node.get_child(0) {
+				Some(child) => if let html_node::NodeT::Text(text, span) = &*child.value.borrow() {
+					(text.clone(), span.start)
+				} else {
+					loader.error(LoadError::NotText);
+					return None;
+				},
+				None => (String::new(), SourcePosition::new())
+			};
+			let mut stylesheet = loader.parse(css.as_str(), "<style>", start);
+			loader.load_imports(&mut stylesheet, base);
+			Some(stylesheet)
+		}).collect();
+
+		report(loader.errors());
+		stylesheets
+	}
+
+	/// Rebuilds the render calls for `page`, evaluating `@media` rules against the current window size.
+	fn layout(renderer: &mut html_render::Renderer, page: &Page) {
+		renderer.clear();
+		let environment = renderer.media_environment();
+		let mut cascade = Cascade::new();
+		for (origin, stylesheet) in &page.stylesheets {
+			cascade.add_stylesheet(*origin, stylesheet, &environment);
+		}
+
+		page.forget_stale_styles();
+
+		// Elements are only cascaded again when their style was forgotten. `map` visits parents first, so the parent's
+		// custom properties, color and font are always there to inherit. The root's color is black.
+		let black = Color::new(0.0, 0.0, 0.0, 1.0);
+		let dom_tree = page.document.root();
+		let annotated_tree = dom_tree.clone().map(|node| {
 			match &*node.value.borrow() {
-				html_node::NodeT::Root => false,
-				html_node::NodeT::Element(el) => el.is_tag(&String::from("style")),
-				html_node::NodeT::Text(_) => false
+				html_node::NodeT::Root | html_node::NodeT::DocumentFragment => Box::new(dom::html_root::RootElement::new()) as Box<dyn html_node::HTMLNode>,
+				html_node::NodeT::Element(el) if el.is_html_tag("template") => Box::new(dom::html_template::TemplateElement::new()) as Box<dyn html_node::HTMLNode>,
+				html_node::NodeT::Element(_) => {
+					let parent_color = node.get_parent().and_then(|parent| page.styles.borrow().get(&NodeKey(parent)).map(|style| style.color)).unwrap_or(black);
+					let cached = page.styles.borrow().get(&NodeKey(node.clone())).cloned();
+					let style = cached.unwrap_or_else(|| {
+						let (inherited, parent_font) = node.get_parent().and_then(|parent| {
+							page.styles.borrow().get(&NodeKey(parent)).map(|style| (style.custom_properties.clone(), style.font.clone()))
+						}).unwrap_or_default();
+						let (rules, custom_properties) = cascade.cascade(&node, &inherited);
+
+						let mut styles = HashMap::new();
+						styles::append_rules(&mut styles, &rules);
+						let color = styles::compute_current_color(&styles, parent_color);
+						let font = FontSelection::cascade(&rules, &parent_font);
+						let style = ElementStyle { styles, custom_properties, color, font };
+						page.styles.borrow_mut().insert(NodeKey(node.clone()), style.clone());
+						style
+					});
+
+					let mut element = dom::html_block_elements::BlockElement::new(node.clone(), parent_color);
+					element.prepare(&style.styles);
+					Box::new(element) as Box<dyn html_node::HTMLNode>
+				},
+				html_node::NodeT::Text(text, _) => {
+					let font = node.get_parent().and_then(|parent| page.styles.borrow().get(&NodeKey(parent)).map(|style| style.font.clone())).unwrap_or_default();
+					Box::new(dom::html_text::TextNode::new(text.clone(), page.fonts.font_runs(text, &font))) as Box<dyn html_node::HTMLNode>
+				}
 			}
 		});
-		let total_style = styles_elements.iter().map(|element| {
-			if let Some(element) = element.get_child(0) {
-				if let html_node::NodeT::Text(text) = &*element.value.borrow() {
-					text.clone()
-				} else { panic!("Invalid CSS") }
-			} else { String::new() }
-		}).collect::<Vec<String>>().join("\n");
-		let total_style_blocks = html_parser::parse_css(total_style.as_str());
 
 		for rendercall in annotated_tree.map_linear(|node| {
-			let mut relevant_styles = HashMap::new();
-			for block in &total_style_blocks {
-				let sel = selector::Selector::from_str(block.selector.as_str()).ok().unwrap();
-				if node.value.borrow().matches(&sel) {
-					styles::append_ref(&mut relevant_styles, block.as_ref());
-				}
-			}
-			node.value.borrow_mut().prepare(&relevant_styles);
-			node.value.borrow().compute_style(self.renderer.display(), node.clone())
+			node.value.borrow().compute_style(renderer.display(), node.clone())
 		}) {
-			self.renderer.add(rendercall);
+			renderer.add(rendercall);
 		}
 
-		self.renderer.queue();
+		renderer.queue();
 	}
 }
@@ -0,0 +1,232 @@
+use super::tree_generation_2::{HTMLElement, Namespace};
+
+#[derive(Debug, Clone)]
+pub struct Attribute {
+	namespace: Option<Namespace>,
+	prefix: Option<String>,
+	local_name: String,
+	value: String
+}
+impl Attribute {
+	pub(crate) fn new(namespace: Option<Namespace>, prefix: Option<String>, local_name: String, value: String) -> Attribute {
+		Attribute {
+			namespace, prefix, local_name, value
+		}
+	}
+
+	pub fn namespace(&self) -> Option<Namespace> {
+		self.namespace
+	}
+
+	pub fn prefix(&self) -> Option<&str> {
+		self.prefix.as_deref()
+	}
+
+	pub fn local_name(&self) -> &str {
+		&self.local_name
+	}
+
+	pub fn qualified_name(&self) -> String {
+		match &self.prefix {
+			Some(prefix) => format!("{}:{}", prefix, self.local_name),
+			None => self.local_name.clone()
+		}
+	}
+
+	pub fn value(&self) -> &str {
+		&self.value
+	}
+
+	pub(crate) fn set_value(&mut self, value: String) {
+		self.value = value;
+	}
+
+	pub(crate) fn has_qualified_name(&self, name: &str) -> bool {
+		match &self.prefix {
+			Some(prefix) => name.split_once(':') == Some((prefix.as_str(), self.local_name.as_str())),
+			None => self.local_name == name
+		}
+	}
+}
+
+fn tokens(value: Option<&str>) -> Vec<&str> {
+	let mut tokens: Vec<&str> = vec![];
+	for token in value.unwrap_or("").split_ascii_whitespace() {
+		if !tokens.contains(&token) { tokens.push(token); }
+	}
+
+	tokens
+}
+
+/// A read-only view of the whitespace separated tokens in an element's `class` attribute.
+pub struct ClassList<'a> {
+	element: &'a HTMLElement
+}
+impl<'a> ClassList<'a> {
+	pub(crate) fn new(element: &'a HTMLElement) -> ClassList<'a> {
+		ClassList {
+			element
+		}
+	}
+
+	pub fn contains(&self, token: &str) -> bool {
+		self.tokens().contains(&token)
+	}
+
+	pub fn tokens(&self) -> Vec<&'a str> {
+		tokens(self.element.get_attribute("class"))
+	}
+
+	pub fn len(&self) -> usize {
+		self.tokens().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.tokens().is_empty()
+	}
+}
+
+/// Edits an element's `class` attribute as an ordered set of tokens, like `DOMTokenList`.
+pub struct ClassListMut<'a> {
+	element: &'a mut HTMLElement
+}
+impl<'a> ClassListMut<'a> {
+	pub(crate) fn new(element: &'a mut HTMLElement) -> ClassListMut<'a> {
+		ClassListMut {
+			element
+		}
+	}
+
+	fn owned_tokens(&self) -> Vec<String> {
+		tokens(self.element.get_attribute("class")).into_iter().map(String::from).collect()
+	}
+
+	fn update(&mut self, tokens: Vec<String>) {
+		if tokens.is_empty() && !self.element.has_attribute("class") { return; }
+		self.element.set_attribute("class", &tokens.join(" "));
+	}
+
+	pub fn contains(&self, token: &str) -> bool {
+		tokens(self.element.get_attribute("class")).contains(&token)
+	}
+
+	pub fn add(&mut self, token: &str) {
+		let mut tokens = self.owned_tokens();
+		if !tokens.iter().any(|existing| existing == token) { tokens.push(String::from(token)); }
+		self.update(tokens);
+	}
+
+	pub fn remove(&mut self, token: &str) {
+		let tokens = self.owned_tokens().into_iter().filter(|existing| existing != token).collect();
+		self.update(tokens);
+	}
+
+	/// Removes `token` if present and adds it otherwise, returning whether it is now present.
+	pub fn toggle(&mut self, token: &str) -> bool {
+		if self.contains(token) {
+			self.remove(token);
+			false
+		} else {
+			self.add(token);
+			true
+		}
+	}
+
+	pub fn replace(&mut self, token: &str, new_token: &str) -> bool {
+		if !self.contains(token) { return false; }
+
+		let mut tokens: Vec<String> = vec![];
+		for existing in self.owned_tokens() {
+			let replaced = if existing == token { String::from(new_token) } else { existing };
+			if !tokens.contains(&replaced) { tokens.push(replaced); }
+		}
+		self.update(tokens);
+		true
+	}
+}
+
+#[derive(Debug)]
+pub struct DatasetNameError {}
+
+fn data_attribute_to_property(name: &str) -> Option<String> {
+	let rest = name.strip_prefix("data-")?;
+	let mut property = String::new();
+	let mut chars = rest.chars().peekable();
+	while let Some(ch) = chars.next() {
+		match chars.peek() {
+			Some(next) if ch == '-' && next.is_ascii_lowercase() => {
+				property.push(next.to_ascii_uppercase());
+				chars.next();
+			},
+			_ => property.push(ch)
+		}
+	}
+
+	Some(property)
+}
+
+fn property_to_data_attribute(property: &str) -> Result<String, DatasetNameError> {
+	let bytes = property.as_bytes();
+	if bytes.windows(2).any(|pair| pair[0] == b'-' && pair[1].is_ascii_lowercase()) {
+		return Err(DatasetNameError {});
+	}
+
+	let mut name = String::from("data-");
+	for ch in property.chars() {
+		if ch.is_ascii_uppercase() {
+			name.push('-');
+			name.push(ch.to_ascii_lowercase());
+		} else {
+			name.push(ch);
+		}
+	}
+
+	Ok(name)
+}
+
+/// A read-only view of an element's `data-*` attributes, keyed by their camelCased names.
+pub struct Dataset<'a> {
+	element: &'a HTMLElement
+}
+impl<'a> Dataset<'a> {
+	pub(crate) fn new(element: &'a HTMLElement) -> Dataset<'a> {
+		Dataset {
+			element
+		}
+	}
+
+	pub fn get(&self, property: &str) -> Option<&'a str> {
+		self.element.get_attribute(&property_to_data_attribute(property).ok()?)
+	}
+
+	pub fn entries(&self) -> Vec<(String, &'a str)> {
+		self.element.attributes().iter().filter(|attr| attr.namespace().is_none()).filter_map(|attr| {
+			data_attribute_to_property(&attr.qualified_name()).map(|property| (property, attr.value()))
+		}).collect()
+	}
+}
+
+pub struct DatasetMut<'a> {
+	element: &'a mut HTMLElement
+}
+impl<'a> DatasetMut<'a> {
+	pub(crate) fn new(element: &'a mut HTMLElement) -> DatasetMut<'a> {
+		DatasetMut {
+			element
+		}
+	}
+
+	pub fn get(&self, property: &str) -> Option<&str> {
+		self.element.get_attribute(&property_to_data_attribute(property).ok()?)
+	}
+
+	pub fn set(&mut self, property: &str, value: &str) -> Result<(), DatasetNameError> {
+		let name = property_to_data_attribute(property)?;
+		self.element.set_attribute(&name, value);
+		Ok(())
+	}
+
+	pub fn remove(&mut self, property: &str) -> Option<String> {
+		self.element.remove_attribute(&property_to_data_attribute(property).ok()?)
+	}
+}
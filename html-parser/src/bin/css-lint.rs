@@ -0,0 +1,42 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use html_parser::css_lint;
+
+/// Prints the diagnostics of stylesheets, or of every `<style>` in HTML files, and exits with 1 if there were any.
+fn main() {
+	let paths: Vec<String> = env::args().skip(1).collect();
+	if paths.is_empty() {
+		eprintln!("usage: css-lint <file.css | file.html>...");
+		process::exit(2);
+	}
+
+	let mut found = false;
+	for path in &paths {
+		let source = match fs::read_to_string(path) {
+			Ok(source) => source,
+			Err(err) => {
+				eprintln!("{}: {}", path, err);
+				process::exit(2);
+			}
+		};
+
+		let is_html = Path::new(path).extension().and_then(|extension| extension.to_str()).is_some_and(|extension| {
+			extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+		});
+		let diagnostics = if is_html {
+			css_lint::lint_document(&html_parser::parse_html_new(source.as_str()))
+		} else {
+			css_lint::lint(source.as_str())
+		};
+
+		for diagnostic in &diagnostics {
+			println!("{}:{}", path, diagnostic);
+		}
+		found |= !diagnostics.is_empty();
+	}
+
+	process::exit(if found { 1 } else { 0 });
+}
@@ -0,0 +1,247 @@
+use super::css_parse::ComponentValue;
+use super::css_tokenize::CSSToken;
+use super::css_value::LengthUnit;
+
+/// What relative lengths and percentages in a math function resolve against.
+#[derive(Debug, Clone, Copy)]
+pub struct CalcContext {
+	/// The length a percentage is a percentage of, if percentages are allowed.
+	pub percentage_basis: Option<f32>,
+	pub font_size: f32,
+	pub root_font_size: f32,
+	pub viewport_width: f32,
+	pub viewport_height: f32
+}
+impl CalcContext {
+	/// Converts a length to pixels. `ex` and `ch` are taken as half an `em`, as there are no font metrics.
+	pub fn to_px(&self, value: f32, unit: LengthUnit) -> f32 {
+		match unit {
+			LengthUnit::Em => value * self.font_size,
+			LengthUnit::Rem => value * self.root_font_size,
+			LengthUnit::Ex | LengthUnit::Ch => value * self.font_size / 2.0,
+			LengthUnit::Vw => value * self.viewport_width / 100.0,
+			LengthUnit::Vh => value * self.viewport_height / 100.0,
+			LengthUnit::Vmin => value * self.viewport_width.min(self.viewport_height) / 100.0,
+			LengthUnit::Vmax => value * self.viewport_width.max(self.viewport_height) / 100.0,
+			unit => unit.to_px(value).unwrap()
+		}
+	}
+}
+
+/// Whether an expression is a plain number or a length. Percentages count as lengths, since the math functions are
+/// only used where a percentage resolves to one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcType {
+	Number,
+	Length
+}
+
+/// The expression tree of a `calc()`, `min()`, `max()` or `clamp()`, which is type checked when parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+	Number(f32),
+	Length(f32, LengthUnit),
+	Percentage(f32),
+	Add(Box<CalcNode>, Box<CalcNode>),
+	Subtract(Box<CalcNode>, Box<CalcNode>),
+	Multiply(Box<CalcNode>, Box<CalcNode>),
+	Divide(Box<CalcNode>, Box<CalcNode>),
+	Min(Vec<CalcNode>),
+	Max(Vec<CalcNode>),
+	Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>)
+}
+impl CalcNode {
+	/// Parses a math function. Returns `None` for other values and for invalid or mistyped expressions.
+	pub fn parse(value: &ComponentValue) -> Option<CalcNode> {
+		let (name, arguments) = match value {
+			ComponentValue::Function { name, arguments } => (name.to_ascii_lowercase(), arguments),
+			_ => return None
+		};
+
+		let arguments: Vec<&[ComponentValue]> = arguments.split(|value| *value == ComponentValue::Token(CSSToken::Comma)).collect();
+		let mut arguments = arguments.into_iter().map(parse_sum).collect::<Option<Vec<CalcNode>>>()?;
+		let kind = arguments[0].kind();
+		if arguments.iter().any(|argument| argument.kind() != kind) { return None; }
+
+		Some(match (name.as_str(), arguments.len()) {
+			("calc", 1) => arguments.remove(0),
+			("min", _) => CalcNode::Min(arguments),
+			("max", _) => CalcNode::Max(arguments),
+			("clamp", 3) => {
+				let max = arguments.pop().unwrap();
+				let value = arguments.pop().unwrap();
+				let min = arguments.pop().unwrap();
+				CalcNode::Clamp(Box::new(min), Box::new(value), Box::new(max))
+			},
+			_ => return None
+		})
+	}
+
+	pub fn kind(&self) -> CalcType {
+		match self {
+			CalcNode::Number(_) => CalcType::Number,
+			CalcNode::Length(..) | CalcNode::Percentage(_) => CalcType::Length,
+			CalcNode::Add(left, _) | CalcNode::Subtract(left, _) | CalcNode::Divide(left, _) => left.kind(),
+			CalcNode::Multiply(left, right) => if left.kind() == CalcType::Length || right.kind() == CalcType::Length { CalcType::Length } else { CalcType::Number },
+			CalcNode::Min(arguments) | CalcNode::Max(arguments) => arguments[0].kind(),
+			CalcNode::Clamp(_, value, _) => value.kind()
+		}
+	}
+
+	/// Evaluates the expression to a number or to pixels. Returns `None` if it contains a percentage without a basis
+	/// or doesn't give a finite result.
+	pub fn resolve(&self, context: &CalcContext) -> Option<f32> {
+		let result = match self {
+			CalcNode::Number(value) => *value,
+			CalcNode::Length(value, unit) => context.to_px(*value, *unit),
+			CalcNode::Percentage(value) => context.percentage_basis? * value / 100.0,
+			CalcNode::Add(left, right) => left.resolve(context)? + right.resolve(context)?,
+			CalcNode::Subtract(left, right) => left.resolve(context)? - right.resolve(context)?,
+			CalcNode::Multiply(left, right) => left.resolve(context)? * right.resolve(context)?,
+			CalcNode::Divide(left, right) => left.resolve(context)? / right.resolve(context)?,
+			CalcNode::Min(arguments) => arguments.iter().map(|argument| argument.resolve(context)).collect::<Option<Vec<f32>>>()?.into_iter().fold(f32::INFINITY, f32::min),
+			CalcNode::Max(arguments) => arguments.iter().map(|argument| argument.resolve(context)).collect::<Option<Vec<f32>>>()?.into_iter().fold(f32::NEG_INFINITY, f32::max),
+			// The minimum wins over the maximum when they overlap.
+			CalcNode::Clamp(min, value, max) => value.resolve(context)?.min(max.resolve(context)?).max(min.resolve(context)?)
+		};
+
+		if result.is_finite() { Some(result) } else { None }
+	}
+}
+
+fn skip_whitespace(values: &[ComponentValue], idx: &mut usize) -> bool {
+	let start = *idx;
+	while values.get(*idx).is_some_and(|value| value.is_whitespace()) { *idx += 1; }
+	*idx > start
+}
+
+/// Parses `<calc-sum>` and checks it covers all of `values`.
+fn parse_sum(values: &[ComponentValue]) -> Option<CalcNode> {
+	let mut idx = 0;
+	skip_whitespace(values, &mut idx);
+	let mut sum = parse_product(values, &mut idx)?;
+
+	loop {
+		let spaced = skip_whitespace(values, &mut idx);
+		let operator = match values.get(idx) {
+			None => return Some(sum),
+			Some(ComponentValue::Token(CSSToken::Delim(operator))) if *operator == '+' || *operator == '-' => *operator,
+			Some(_) => return None
+		};
+
+		// `+` and `-` need whitespace on both sides, so they can't be read as the sign of a number.
+		idx += 1;
+		if !spaced || !skip_whitespace(values, &mut idx) { return None; }
+		let right = parse_product(values, &mut idx)?;
+		if sum.kind() != right.kind() { return None; }
+
+		sum = match operator {
+			'+' => CalcNode::Add(Box::new(sum), Box::new(right)),
+			_ => CalcNode::Subtract(Box::new(sum), Box::new(right))
+		};
+	}
+}
+
+fn parse_product(values: &[ComponentValue], idx: &mut usize) -> Option<CalcNode> {
+	let mut product = parse_value(values.get(*idx)?)?;
+	*idx += 1;
+
+	loop {
+		let mut next = *idx;
+		skip_whitespace(values, &mut next);
+		let operator = match values.get(next) {
+			Some(ComponentValue::Token(CSSToken::Delim(operator))) if *operator == '*' || *operator == '/' => *operator,
+			_ => return Some(product)
+		};
+
+		next += 1;
+		skip_whitespace(values, &mut next);
+		let right = parse_value(values.get(next)?)?;
+		*idx = next + 1;
+
+		// At least one side of a product and the right side of a quotient must be a number.
+		product = match operator {
+			'*' if product.kind() == CalcType::Number || right.kind() == CalcType::Number => CalcNode::Multiply(Box::new(product), Box::new(right)),
+			'/' if right.kind() == CalcType::Number => CalcNode::Divide(Box::new(product), Box::new(right)),
+			_ => return None
+		};
+	}
+}
+
+fn parse_value(value: &ComponentValue) -> Option<CalcNode> {
+	match value {
+		ComponentValue::Token(CSSToken::Number { value, .. }) => Some(CalcNode::Number(*value)),
+		ComponentValue::Token(CSSToken::Percentage { value, .. }) => Some(CalcNode::Percentage(*value)),
+		ComponentValue::Token(CSSToken::Dimension { value, unit, .. }) => Some(CalcNode::Length(*value, LengthUnit::from_unit(unit)?)),
+		ComponentValue::Block { open: '(', contents } => parse_sum(contents),
+		value => CalcNode::parse(value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::parse_component_values;
+
+	const CONTEXT: CalcContext = CalcContext {
+		percentage_basis: Some(200.0),
+		font_size: 20.0,
+		root_font_size: 16.0,
+		viewport_width: 1000.0,
+		viewport_height: 500.0
+	};
+
+	fn parse(css: &str) -> Option<CalcNode> {
+		CalcNode::parse(&parse_component_values(css)[0])
+	}
+
+	fn resolve(css: &str) -> Option<f32> {
+		parse(css).unwrap_or_else(|| panic!("{} failed to parse", css)).resolve(&CONTEXT)
+	}
+
+	#[test]
+	fn type_checking() {
+		let kinds = [
+			("calc(1 + 2)", CalcType::Number),
+			("calc(1px + 50%)", CalcType::Length),
+			("calc(2 * 3px)", CalcType::Length),
+			("calc(3px * 2 / 4)", CalcType::Length),
+			("min(1px, 2em, 3%)", CalcType::Length),
+			("clamp(1, 2, 3)", CalcType::Number)
+		];
+		for (css, kind) in kinds {
+			assert_eq!(parse(css).map(|calc| calc.kind()), Some(kind), "{}", css);
+		}
+
+		for css in ["calc(1px + 2)", "calc(1px * 2px)", "calc(2 / 1px)", "min(1px, 2)", "clamp(1px, 2px)", "calc(1px, 2px)", "calc(1deg)", "calc(1px +2px)", "calc(1px *)", "rgb(1, 2, 3)"] {
+			assert_eq!(parse(css), None, "{}", css);
+		}
+	}
+
+	#[test]
+	fn precedence() {
+		assert_eq!(resolve("calc(1 + 2 * 3)"), Some(7.0));
+		assert_eq!(resolve("calc((1 + 2) * 3)"), Some(9.0));
+		assert_eq!(resolve("calc(10 - 4 - 3)"), Some(3.0));
+		assert_eq!(resolve("calc(12 / 2 / 3)"), Some(2.0));
+		assert_eq!(resolve("calc(2 * calc(1px + 1px))"), Some(4.0));
+	}
+
+	#[test]
+	fn units() {
+		assert_eq!(resolve("calc(1in + 1em + 1rem)"), Some(96.0 + 20.0 + 16.0));
+		assert_eq!(resolve("calc(10vw - 10vh + 1vmin + 1vmax)"), Some(100.0 - 50.0 + 5.0 + 10.0));
+		assert_eq!(resolve("calc(50% + 2ch)"), Some(100.0 + 20.0));
+		assert_eq!(parse("calc(50%)").unwrap().resolve(&CalcContext { percentage_basis: None, ..CONTEXT }), None);
+	}
+
+	#[test]
+	fn comparison_functions() {
+		assert_eq!(resolve("min(10px, 5%, 1em)"), Some(10.0));
+		assert_eq!(resolve("max(10px, 5%, 1em)"), Some(20.0));
+		assert_eq!(resolve("clamp(10px, 50%, 40px)"), Some(40.0));
+		assert_eq!(resolve("clamp(10px, 1px, 40px)"), Some(10.0));
+		assert_eq!(resolve("clamp(50px, 1px, 40px)"), Some(50.0));
+		assert_eq!(resolve("calc(1 / 0)"), None);
+	}
+}
@@ -0,0 +1,361 @@
+use super::css_calc::{CalcContext, CalcNode, CalcType};
+use super::css_parse::ComponentValue;
+use super::css_tokenize::CSSToken;
+use super::css_value::{Color, CSSValue};
+
+/// A color as written, before `currentcolor` is resolved against the element's `color`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecifiedColor {
+	Color(Color),
+	CurrentColor
+}
+
+const NAMED_COLORS: [(&str, u32); 148] = [
+	("aliceblue", 0xf0f8ff), ("antiquewhite", 0xfaebd7), ("aqua", 0x00ffff), ("aquamarine", 0x7fffd4), ("azure", 0xf0ffff),
+	("beige", 0xf5f5dc), ("bisque", 0xffe4c4), ("black", 0x000000), ("blanchedalmond", 0xffebcd), ("blue", 0x0000ff),
+	("blueviolet", 0x8a2be2), ("brown", 0xa52a2a), ("burlywood", 0xdeb887), ("cadetblue", 0x5f9ea0), ("chartreuse", 0x7fff00),
+	("chocolate", 0xd2691e), ("coral", 0xff7f50), ("cornflowerblue", 0x6495ed), ("cornsilk", 0xfff8dc), ("crimson", 0xdc143c),
+	("cyan", 0x00ffff), ("darkblue", 0x00008b), ("darkcyan", 0x008b8b), ("darkgoldenrod", 0xb8860b), ("darkgray", 0xa9a9a9),
+	("darkgreen", 0x006400), ("darkgrey", 0xa9a9a9), ("darkkhaki", 0xbdb76b), ("darkmagenta", 0x8b008b), ("darkolivegreen", 0x556b2f),
+	("darkorange", 0xff8c00), ("darkorchid", 0x9932cc), ("darkred", 0x8b0000), ("darksalmon", 0xe9967a), ("darkseagreen", 0x8fbc8f),
+	("darkslateblue", 0x483d8b), ("darkslategray", 0x2f4f4f), ("darkslategrey", 0x2f4f4f), ("darkturquoise", 0x00ced1), ("darkviolet", 0x9400d3),
+	("deeppink", 0xff1493), ("deepskyblue", 0x00bfff), ("dimgray", 0x696969), ("dimgrey", 0x696969), ("dodgerblue", 0x1e90ff),
+	("firebrick", 0xb22222), ("floralwhite", 0xfffaf0), ("forestgreen", 0x228b22), ("fuchsia", 0xff00ff), ("gainsboro", 0xdcdcdc),
+	("ghostwhite", 0xf8f8ff), ("gold", 0xffd700), ("goldenrod", 0xdaa520), ("gray", 0x808080), ("green", 0x008000),
+	("greenyellow", 0xadff2f), ("grey", 0x808080), ("honeydew", 0xf0fff0), ("hotpink", 0xff69b4), ("indianred", 0xcd5c5c),
+	("indigo", 0x4b0082), ("ivory", 0xfffff0), ("khaki", 0xf0e68c), ("lavender", 0xe6e6fa), ("lavenderblush", 0xfff0f5),
+	("lawngreen", 0x7cfc00), ("lemonchiffon", 0xfffacd), ("lightblue", 0xadd8e6), ("lightcoral", 0xf08080), ("lightcyan", 0xe0ffff),
+	("lightgoldenrodyellow", 0xfafad2), ("lightgray", 0xd3d3d3), ("lightgreen", 0x90ee90), ("lightgrey", 0xd3d3d3), ("lightpink", 0xffb6c1),
+	("lightsalmon", 0xffa07a), ("lightseagreen", 0x20b2aa), ("lightskyblue", 0x87cefa), ("lightslategray", 0x778899), ("lightslategrey", 0x778899),
+	("lightsteelblue", 0xb0c4de), ("lightyellow", 0xffffe0), ("lime", 0x00ff00), ("limegreen", 0x32cd32), ("linen", 0xfaf0e6),
+	("magenta", 0xff00ff), ("maroon", 0x800000), ("mediumaquamarine", 0x66cdaa), ("mediumblue", 0x0000cd), ("mediumorchid", 0xba55d3),
+	("mediumpurple", 0x9370db), ("mediumseagreen", 0x3cb371), ("mediumslateblue", 0x7b68ee), ("mediumspringgreen", 0x00fa9a), ("mediumturquoise", 0x48d1cc),
+	("mediumvioletred", 0xc71585), ("midnightblue", 0x191970), ("mintcream", 0xf5fffa), ("mistyrose", 0xffe4e1), ("moccasin", 0xffe4b5),
+	("navajowhite", 0xffdead), ("navy", 0x000080), ("oldlace", 0xfdf5e6), ("olive", 0x808000), ("olivedrab", 0x6b8e23),
+	("orange", 0xffa500), ("orangered", 0xff4500), ("orchid", 0xda70d6), ("palegoldenrod", 0xeee8aa), ("palegreen", 0x98fb98),
+	("paleturquoise", 0xafeeee), ("palevioletred", 0xdb7093), ("papayawhip", 0xffefd5), ("peachpuff", 0xffdab9), ("peru", 0xcd853f),
+	("pink", 0xffc0cb), ("plum", 0xdda0dd), ("powderblue", 0xb0e0e6), ("purple", 0x800080), ("rebeccapurple", 0x663399),
+	("red", 0xff0000), ("rosybrown", 0xbc8f8f), ("royalblue", 0x4169e1), ("saddlebrown", 0x8b4513), ("salmon", 0xfa8072),
+	("sandybrown", 0xf4a460), ("seagreen", 0x2e8b57), ("seashell", 0xfff5ee), ("sienna", 0xa0522d), ("silver", 0xc0c0c0),
+	("skyblue", 0x87ceeb), ("slateblue", 0x6a5acd), ("slategray", 0x708090), ("slategrey", 0x708090), ("snow", 0xfffafa),
+	("springgreen", 0x00ff7f), ("steelblue", 0x4682b4), ("tan", 0xd2b48c), ("teal", 0x008080), ("thistle", 0xd8bfd8),
+	("tomato", 0xff6347), ("turquoise", 0x40e0d0), ("violet", 0xee82ee), ("wheat", 0xf5deb3), ("white", 0xffffff),
+	("whitesmoke", 0xf5f5f5), ("yellow", 0xffff00), ("yellowgreen", 0x9acd32)
+];
+
+/// System colors, as a light color scheme draws them.
+const SYSTEM_COLORS: [(&str, u32); 19] = [
+	("accentcolor", 0x0060df), ("accentcolortext", 0xffffff), ("activetext", 0xee0000), ("buttonborder", 0x767676), ("buttonface", 0xefefef),
+	("buttontext", 0x000000), ("canvas", 0xffffff), ("canvastext", 0x000000), ("field", 0xffffff), ("fieldtext", 0x000000),
+	("graytext", 0x808080), ("highlight", 0x3399ff), ("highlighttext", 0xffffff), ("linktext", 0x0000ee), ("mark", 0xffff00),
+	("marktext", 0x000000), ("selecteditem", 0x3399ff), ("selecteditemtext", 0xffffff), ("visitedtext", 0x551a8b)
+];
+
+fn from_rgb(rgb: u32) -> Color {
+	Color::new(((rgb >> 16) & 0xff) as f32 / 255.0, ((rgb >> 8) & 0xff) as f32 / 255.0, (rgb & 0xff) as f32 / 255.0, 1.0)
+}
+
+/// A named color, system color or `transparent`, by its lowercase name.
+pub fn named(name: &str) -> Option<Color> {
+	if name == "transparent" { return Some(Color::new(0.0, 0.0, 0.0, 0.0)); }
+	NAMED_COLORS.iter().chain(SYSTEM_COLORS.iter()).find(|(named, _)| *named == name).map(|(_, rgb)| from_rgb(*rgb))
+}
+
+fn keyword(name: &str) -> Option<SpecifiedColor> {
+	let name = name.to_ascii_lowercase();
+	if name == "currentcolor" { return Some(SpecifiedColor::CurrentColor); }
+	named(&name).map(SpecifiedColor::Color)
+}
+
+/// An argument of a color function. Angles are in degrees, `none` is a missing component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Argument {
+	Number(f32),
+	Percentage(f32),
+	Angle(f32),
+	None
+}
+impl Argument {
+	fn parse(value: &ComponentValue) -> Option<Argument> {
+		Some(match value {
+			ComponentValue::Token(CSSToken::Number { value, .. }) => Argument::Number(*value),
+			ComponentValue::Token(CSSToken::Percentage { value, .. }) => Argument::Percentage(*value),
+			ComponentValue::Token(CSSToken::Dimension { value, unit, .. }) => Argument::Angle(match unit.to_ascii_lowercase().as_str() {
+				"deg" => *value,
+				"grad" => *value * 0.9,
+				"rad" => value.to_degrees(),
+				"turn" => *value * 360.0,
+				_ => return None
+			}),
+			ComponentValue::Token(CSSToken::Ident(ident)) if ident.eq_ignore_ascii_case("none") => Argument::None,
+			value => match CalcNode::parse(value)? {
+				// Numbers don't depend on the context.
+				calc if calc.kind() == CalcType::Number => Argument::Number(calc.resolve(&CalcContext {
+					percentage_basis: None,
+					font_size: 16.0,
+					root_font_size: 16.0,
+					viewport_width: 0.0,
+					viewport_height: 0.0
+				})?),
+				_ => return None
+			}
+		})
+	}
+
+	/// The component as a number, with 100% being `full`.
+	fn scaled(self, full: f32) -> Option<f32> {
+		match self {
+			Argument::Number(value) => Some(value),
+			Argument::Percentage(value) => Some(value / 100.0 * full),
+			Argument::None => Some(0.0),
+			Argument::Angle(_) => None
+		}
+	}
+
+	fn hue(self) -> Option<f32> {
+		match self {
+			Argument::Number(degrees) | Argument::Angle(degrees) => Some(degrees.rem_euclid(360.0)),
+			Argument::None => Some(0.0),
+			Argument::Percentage(_) => None
+		}
+	}
+
+	fn alpha(self) -> Option<f32> {
+		Some(self.scaled(1.0)?.clamp(0.0, 1.0))
+	}
+}
+
+/// Splits the arguments of a color function into its three channels and alpha. The legacy syntax separates them with
+/// commas and doesn't allow `none`, the modern one uses spaces and `/` before alpha.
+fn arguments(values: &[ComponentValue], legacy_allowed: bool) -> Option<([Argument; 3], Option<Argument>, bool)> {
+	let values: Vec<&ComponentValue> = values.iter().filter(|value| !value.is_whitespace()).collect();
+	let legacy = values.iter().any(|value| **value == ComponentValue::Token(CSSToken::Comma));
+
+	let (channels, alpha): (Vec<&ComponentValue>, Option<&ComponentValue>) = if legacy {
+		if !legacy_allowed { return None; }
+		let parts: Vec<&[&ComponentValue]> = values.split(|value| **value == ComponentValue::Token(CSSToken::Comma)).collect();
+		if !(3..=4).contains(&parts.len()) || parts.iter().any(|part| part.len() != 1) { return None; }
+		(parts[..3].iter().map(|part| part[0]).collect(), parts.get(3).map(|part| part[0]))
+	} else {
+		match values.iter().position(|value| **value == ComponentValue::Token(CSSToken::Delim('/'))) {
+			Some(slash) if slash + 2 == values.len() => (values[..slash].to_vec(), Some(values[slash + 1])),
+			Some(_) => return None,
+			None => (values, None)
+		}
+	};
+	if channels.len() != 3 { return None; }
+
+	let channels = [Argument::parse(channels[0])?, Argument::parse(channels[1])?, Argument::parse(channels[2])?];
+	let alpha = match alpha {
+		Some(alpha) => Some(Argument::parse(alpha)?),
+		None => None
+	};
+	if legacy && (channels.contains(&Argument::None) || alpha == Some(Argument::None)) { return None; }
+	Some((channels, alpha, legacy))
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+	let channel = |n: f32| {
+		let k = (n + hue / 30.0) % 12.0;
+		let a = saturation * lightness.min(1.0 - lightness);
+		lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+	};
+	(channel(0.0), channel(8.0), channel(4.0))
+}
+
+fn hwb_to_rgb(hue: f32, whiteness: f32, blackness: f32) -> (f32, f32, f32) {
+	if whiteness + blackness >= 1.0 {
+		let gray = whiteness / (whiteness + blackness);
+		return (gray, gray, gray);
+	}
+	let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+	let scale = |channel: f32| channel * (1.0 - whiteness - blackness) + whiteness;
+	(scale(r), scale(g), scale(b))
+}
+
+/// Applies the sRGB transfer function to a linear channel.
+fn gamma(channel: f32) -> f32 {
+	let magnitude = channel.abs();
+	let encoded = if magnitude <= 0.0031308 { 12.92 * magnitude } else { 1.055 * magnitude.powf(1.0 / 2.4) - 0.055 };
+	encoded.copysign(channel)
+}
+
+fn multiply(matrix: [[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+	let row = |row: [f32; 3]| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+	[row(matrix[0]), row(matrix[1]), row(matrix[2])]
+}
+
+/// CIE Lab with a D50 white point to sRGB, through XYZ.
+fn lab_to_rgb(lightness: f32, a: f32, b: f32) -> (f32, f32, f32) {
+	const KAPPA: f32 = 24389.0 / 27.0;
+	const EPSILON: f32 = 216.0 / 24389.0;
+	let fy = (lightness + 16.0) / 116.0;
+	let fx = a / 500.0 + fy;
+	let fz = fy - b / 200.0;
+
+	let x = if fx.powi(3) > EPSILON { fx.powi(3) } else { (116.0 * fx - 16.0) / KAPPA };
+	let y = if lightness > KAPPA * EPSILON { fy.powi(3) } else { lightness / KAPPA };
+	let z = if fz.powi(3) > EPSILON { fz.powi(3) } else { (116.0 * fz - 16.0) / KAPPA };
+	let xyz_d50 = [x * 0.3457 / 0.3585, y, z * (1.0 - 0.3457 - 0.3585) / 0.3585];
+
+	let xyz_d65 = multiply([
+		[0.955_473_4, -0.023_098_455, 0.063_259_24],
+		[-0.028_369_71, 1.009_995_4, 0.021_041_441],
+		[0.012_314_015, -0.020_507_65, 1.330_365_9]
+	], xyz_d50);
+	let [r, g, b] = multiply([
+		[3.240_97, -1.537_383_2, -0.498_610_76],
+		[-0.969_243_65, 1.875_967_5, 0.041_555_06],
+		[0.055_630_08, -0.203_976_96, 1.056_971_5]
+	], xyz_d65);
+	(gamma(r), gamma(g), gamma(b))
+}
+
+fn oklab_to_rgb(lightness: f32, a: f32, b: f32) -> (f32, f32, f32) {
+	let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+	let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+	let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+	let [r, g, b] = multiply([
+		[4.076_741_7, -3.307_711_6, 0.230_969_94],
+		[-1.268_438, 2.609_757_4, -0.341_319_38],
+		[-0.004_196_086_3, -0.703_418_6, 1.707_614_7]
+	], [l, m, s]);
+	(gamma(r), gamma(g), gamma(b))
+}
+
+/// Polar coordinates to the `a` and `b` axes.
+fn from_polar(chroma: f32, hue: f32) -> (f32, f32) {
+	(chroma * hue.to_radians().cos(), chroma * hue.to_radians().sin())
+}
+
+fn function(name: &str, values: &[ComponentValue]) -> Option<Color> {
+	let name = name.to_ascii_lowercase();
+	let legacy_allowed = ["rgb", "rgba", "hsl", "hsla"].contains(&name.as_str());
+	let ([first, second, third], alpha, legacy) = arguments(values, legacy_allowed)?;
+
+	let (r, g, b) = match name.as_str() {
+		"rgb" | "rgba" => {
+			let is_percentage = |argument: &Argument| matches!(argument, Argument::Percentage(_));
+			if legacy && !(is_percentage(&first) == is_percentage(&second) && is_percentage(&second) == is_percentage(&third)) { return None; }
+			let channel = |argument: Argument| Some(argument.scaled(255.0)? / 255.0);
+			(channel(first)?, channel(second)?, channel(third)?)
+		},
+		"hsl" | "hsla" => {
+			if legacy && !matches!((second, third), (Argument::Percentage(_), Argument::Percentage(_))) { return None; }
+			hsl_to_rgb(first.hue()?, second.scaled(100.0)?.clamp(0.0, 100.0) / 100.0, third.scaled(100.0)?.clamp(0.0, 100.0) / 100.0)
+		},
+		"hwb" => hwb_to_rgb(first.hue()?, second.scaled(100.0)?.clamp(0.0, 100.0) / 100.0, third.scaled(100.0)?.clamp(0.0, 100.0) / 100.0),
+		"lab" => lab_to_rgb(first.scaled(100.0)?.clamp(0.0, 100.0), second.scaled(125.0)?, third.scaled(125.0)?),
+		"lch" => {
+			let (a, b) = from_polar(second.scaled(150.0)?.max(0.0), third.hue()?);
+			lab_to_rgb(first.scaled(100.0)?.clamp(0.0, 100.0), a, b)
+		},
+		"oklab" => oklab_to_rgb(first.scaled(1.0)?.clamp(0.0, 1.0), second.scaled(0.4)?, third.scaled(0.4)?),
+		"oklch" => {
+			let (a, b) = from_polar(second.scaled(0.4)?.max(0.0), third.hue()?);
+			oklab_to_rgb(first.scaled(1.0)?.clamp(0.0, 1.0), a, b)
+		},
+		_ => return None
+	};
+
+	let alpha = match alpha {
+		Some(alpha) => alpha.alpha()?,
+		None => 1.0
+	};
+	Some(Color::new(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), alpha))
+}
+
+/// Parses a hex color, named or system color, `transparent`, `currentcolor`, or one of the `rgb()`, `hsl()`,
+/// `hwb()`, `lab()`, `lch()`, `oklab()` and `oklch()` functions. Colors outside sRGB are clamped into it.
+pub fn parse(value: &ComponentValue) -> Option<SpecifiedColor> {
+	match value {
+		ComponentValue::Token(CSSToken::Hash(hash, _)) => Color::from_hex(hash).map(SpecifiedColor::Color),
+		ComponentValue::Token(CSSToken::Ident(ident)) => keyword(ident),
+		ComponentValue::Function { name, arguments } => function(name, arguments).map(SpecifiedColor::Color),
+		_ => None
+	}
+}
+
+/// Like `parse`, for a value that was already typed.
+pub fn from_value(value: &CSSValue) -> Option<SpecifiedColor> {
+	match value {
+		CSSValue::Color(color) => Some(SpecifiedColor::Color(*color)),
+		CSSValue::Keyword(name) => keyword(name),
+		_ => None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::parse_component_values;
+
+	/// The color `css` parses to with its channels rounded to bytes, `None` for `currentcolor` or an invalid color.
+	fn rgba(css: &str) -> Option<[u8; 4]> {
+		match parse(&parse_component_values(css)[0])? {
+			SpecifiedColor::Color(color) => Some([color.r, color.g, color.b, color.a].map(|channel| (channel * 255.0).round() as u8)),
+			SpecifiedColor::CurrentColor => None
+		}
+	}
+
+	#[test]
+	fn keywords() {
+		assert_eq!(rgba("Red"), Some([255, 0, 0, 255]));
+		assert_eq!(rgba("rebeccapurple"), Some([0x66, 0x33, 0x99, 255]));
+		assert_eq!(rgba("transparent"), Some([0, 0, 0, 0]));
+		assert_eq!(rgba("CanvasText"), Some([0, 0, 0, 255]));
+		assert_eq!(parse(&parse_component_values("currentColor")[0]), Some(SpecifiedColor::CurrentColor));
+		assert_eq!(parse(&parse_component_values("reddish")[0]), None);
+	}
+
+	#[test]
+	fn hex() {
+		assert_eq!(rgba("#f80"), Some([255, 0x88, 0, 255]));
+		assert_eq!(rgba("#f808"), Some([255, 0x88, 0, 0x88]));
+		assert_eq!(rgba("#FF880080"), Some([255, 0x88, 0, 0x80]));
+		assert_eq!(rgba("#ff88"), Some([255, 255, 0x88, 0x88]));
+		assert_eq!(rgba("#ff8800f"), None);
+	}
+
+	#[test]
+	fn rgb() {
+		assert_eq!(rgba("rgb(255, 128, 0)"), Some([255, 128, 0, 255]));
+		assert_eq!(rgba("rgba(100%, 50%, 0%, 0.5)"), Some([255, 128, 0, 128]));
+		assert_eq!(rgba("RGB(255 128 0 / 25%)"), Some([255, 128, 0, 64]));
+		assert_eq!(rgba("rgb(none 300 -20)"), Some([0, 255, 0, 255]));
+		assert_eq!(rgba("rgb(calc(200 + 55) 0 0 / 2)"), Some([255, 0, 0, 255]));
+		assert_eq!(rgba("rgb(255, 50%, 0)"), None);
+		assert_eq!(rgba("rgb(255, 0, none)"), None);
+		assert_eq!(rgba("rgb(255 0 0, 1)"), None);
+		assert_eq!(rgba("rgb(255 0)"), None);
+		assert_eq!(rgba("rgb(255 0 0 / 1 / 1)"), None);
+	}
+
+	#[test]
+	fn hsl() {
+		assert_eq!(rgba("hsl(120, 100%, 50%)"), Some([0, 255, 0, 255]));
+		assert_eq!(rgba("hsla(0.5turn, 100%, 25%, 0.5)"), Some([0, 128, 128, 128]));
+		assert_eq!(rgba("hsl(-120deg 100 50 / 1)"), Some([0, 0, 255, 255]));
+		assert_eq!(rgba("hsl(none 0% 100%)"), Some([255, 255, 255, 255]));
+		assert_eq!(rgba("hsl(30 100% 50%)"), Some([255, 128, 0, 255]));
+		assert_eq!(rgba("hsl(120, 100, 50)"), None);
+		assert_eq!(rgba("hsl(50%, 100%, 50%)"), None);
+	}
+
+	#[test]
+	fn hwb() {
+		assert_eq!(rgba("hwb(0 0% 0%)"), Some([255, 0, 0, 255]));
+		assert_eq!(rgba("hwb(240 20% 20% / 50%)"), Some([51, 51, 204, 128]));
+		assert_eq!(rgba("hwb(90 60% 60%)"), Some([128, 128, 128, 255]));
+		assert_eq!(rgba("hwb(0, 0%, 0%)"), None);
+	}
+
+	#[test]
+	fn lab() {
+		assert_eq!(rgba("lab(100 0 0)"), Some([255, 255, 255, 255]));
+		assert_eq!(rgba("lch(0% 0 0)"), Some([0, 0, 0, 255]));
+		assert_eq!(rgba("oklab(1 0 0)"), Some([255, 255, 255, 255]));
+		assert_eq!(rgba("oklch(62.8% 0.2577 29.23)"), Some([255, 0, 0, 255]));
+		assert_eq!(rgba("lab(50, 0, 0)"), None);
+	}
+}
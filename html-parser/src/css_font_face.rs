@@ -0,0 +1,212 @@
+use std::rc::Rc;
+use super::css_parse::{ComponentValue, CSSRule};
+use super::css_tokenize::CSSToken;
+use super::css_value::CSSValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontStyle {
+	Normal,
+	Italic,
+	Oblique
+}
+
+/// One entry of `src`, tried in order until one loads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSource {
+	/// A font file, with the format hint from `format()` if there was one.
+	Url(String, Option<String>),
+	/// An installed font, by its full or PostScript name.
+	Local(String)
+}
+
+/// The descriptors of an `@font-face` rule that take part in font matching.
+#[derive(Debug, Clone)]
+pub struct FontFaceRule {
+	pub family: String,
+	pub sources: Vec<FontSource>,
+	/// The inclusive range of weights the face covers.
+	pub weight: (u16, u16),
+	pub style: FontStyle,
+	/// Inclusive code point ranges, all of Unicode when not given.
+	pub unicode_range: Vec<(u32, u32)>,
+	/// The descriptors as written.
+	pub declarations: Vec<Rc<CSSRule>>
+}
+
+pub const DESCRIPTORS: [&str; 14] = [
+	"font-family", "src", "font-weight", "font-style", "font-stretch", "unicode-range", "font-display", "font-feature-settings",
+	"font-variation-settings", "ascent-override", "descent-override", "line-gap-override", "size-adjust", "font-named-instance"
+];
+
+/// A family name, quoted or as identifiers. Generic families and CSS-wide keywords aren't names of a face.
+pub fn family(values: &[ComponentValue]) -> Option<String> {
+	let values: Vec<&ComponentValue> = values.iter().filter(|value| !value.is_whitespace()).collect();
+	match &values[..] {
+		[ComponentValue::Token(CSSToken::String(family))] => Some(family.clone()),
+		values if !values.is_empty() => values.iter().map(|value| match value {
+			ComponentValue::Token(CSSToken::Ident(ident)) if !["inherit", "initial", "unset", "default", "serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui"].iter().any(|generic| ident.eq_ignore_ascii_case(generic)) => Some(ident.clone()),
+			_ => None
+		}).collect::<Option<Vec<String>>>().map(|words| words.join(" ")),
+		_ => None
+	}
+}
+
+fn source(values: &[&ComponentValue]) -> Option<FontSource> {
+	let string_argument = |arguments: &[ComponentValue]| -> Option<String> {
+		let arguments: Vec<&ComponentValue> = arguments.iter().filter(|value| !value.is_whitespace()).collect();
+		match &arguments[..] {
+			[ComponentValue::Token(CSSToken::String(string))] => Some(string.clone()),
+			words if !words.is_empty() => words.iter().map(|word| match word {
+				ComponentValue::Token(CSSToken::Ident(ident)) => Some(ident.clone()),
+				_ => None
+			}).collect::<Option<Vec<String>>>().map(|words| words.join(" ")),
+			_ => None
+		}
+	};
+
+	let (first, hints) = values.split_first()?;
+	match first {
+		ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("local") && hints.is_empty() => Some(FontSource::Local(string_argument(arguments)?)),
+		ComponentValue::Token(CSSToken::Url(_)) | ComponentValue::Function { .. } => {
+			let url = match first {
+				ComponentValue::Token(CSSToken::Url(url)) => url.clone(),
+				ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("url") => string_argument(arguments)?,
+				_ => return None
+			};
+
+			let mut format = None;
+			for hint in hints {
+				match hint {
+					ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("format") && format.is_none() => format = Some(string_argument(arguments)?.to_ascii_lowercase()),
+					ComponentValue::Function { name, .. } if name.eq_ignore_ascii_case("tech") => {},
+					_ => return None
+				}
+			}
+			Some(FontSource::Url(url, format))
+		},
+		_ => None
+	}
+}
+
+/// An absolute weight: `normal`, `bold` or a number from 1 to 1000.
+pub fn weight(value: &CSSValue) -> Option<u16> {
+	match value {
+		CSSValue::Keyword(keyword) if keyword == "normal" => Some(400),
+		CSSValue::Keyword(keyword) if keyword == "bold" => Some(700),
+		CSSValue::Number(weight) if (1.0..=1000.0).contains(weight) => Some(*weight as u16),
+		_ => None
+	}
+}
+
+/// Parses `U+26`, `U+0-7F` and `U+4??` ranges.
+fn unicode_range(range: &str) -> Option<(u32, u32)> {
+	let range = range.trim();
+	let digits = range.strip_prefix("U+").or_else(|| range.strip_prefix("u+"))?;
+	let (start, end) = match digits.split_once('-') {
+		Some((start, end)) => (String::from(start), String::from(end)),
+		None if digits.contains('?') => (digits.replace('?', "0"), digits.replace('?', "F")),
+		None => (String::from(digits), String::from(digits))
+	};
+
+	let start = u32::from_str_radix(&start, 16).ok()?;
+	let end = u32::from_str_radix(&end, 16).ok()?;
+	if start > end || end > 0x10ffff { return None; }
+	Some((start, end))
+}
+
+impl FontFaceRule {
+	/// Parses the descriptors of an `@font-face`, dropping unknown ones. Returns `None` without a valid `font-family`
+	/// and `src`.
+	pub fn parse(mut declarations: Vec<Rc<CSSRule>>) -> Option<FontFaceRule> {
+		declarations.retain(|declaration| DESCRIPTORS.contains(&declaration.name.as_str()));
+		let mut family_name = None;
+		let mut sources = vec![];
+		let mut font_weight = (400, 400);
+		let mut style = FontStyle::Normal;
+		let mut ranges = vec![(0, 0x10ffff)];
+
+		for declaration in &declarations {
+			match declaration.name.as_str() {
+				"font-family" => family_name = family(&declaration.components),
+				"src" => {
+					let values: Vec<&ComponentValue> = declaration.components.iter().filter(|value| !value.is_whitespace()).collect();
+					sources = values.split(|value| **value == ComponentValue::Token(CSSToken::Comma)).filter_map(source).collect();
+				},
+				"font-weight" => match &declaration.values[..] {
+					[CSSValue::Keyword(keyword)] if keyword == "auto" => font_weight = (1, 1000),
+					[single] => if let Some(single) = weight(single) { font_weight = (single, single) },
+					[start, end] => if let (Some(start), Some(end)) = (weight(start), weight(end)) { font_weight = (start.min(end), start.max(end)) },
+					_ => {}
+				},
+				"font-style" => match declaration.values.first() {
+					Some(CSSValue::Keyword(keyword)) if keyword == "normal" => style = FontStyle::Normal,
+					Some(CSSValue::Keyword(keyword)) if keyword == "italic" => style = FontStyle::Italic,
+					Some(CSSValue::Keyword(keyword)) if keyword == "oblique" => style = FontStyle::Oblique,
+					_ => {}
+				},
+				"unicode-range" => if let Some(parsed) = declaration.value.split(',').map(unicode_range).collect::<Option<Vec<(u32, u32)>>>() {
+					ranges = parsed;
+				},
+				_ => {}
+			}
+		}
+
+		if sources.is_empty() { return None; }
+		Some(FontFaceRule {
+			family: family_name?,
+			sources,
+			weight: font_weight,
+			style,
+			unicode_range: ranges,
+			declarations
+		})
+	}
+
+	pub fn covers(&self, ch: char) -> bool {
+		self.unicode_range.iter().any(|(start, end)| (*start..=*end).contains(&(ch as u32)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::create_block;
+
+	fn parse(css: &str) -> Option<FontFaceRule> {
+		FontFaceRule::parse(create_block(css).rules)
+	}
+
+	fn url(url: &str, format: Option<&str>) -> FontSource {
+		FontSource::Url(String::from(url), format.map(String::from))
+	}
+
+	#[test]
+	fn families_and_sources() {
+		let rule = parse("font-family: \"Open Sans\"; src: local(Open Sans Bold), url(a.woff2) format(\"woff2\"), url('a.ttf') format(truetype) tech(variations), url(b.otf)").unwrap();
+		assert_eq!(rule.family, "Open Sans");
+		assert_eq!(rule.sources, [FontSource::Local(String::from("Open Sans Bold")), url("a.woff2", Some("woff2")), url("a.ttf", Some("truetype")), url("b.otf", None)]);
+
+		assert_eq!(parse("font-family: My  Font; src: url(a.ttf)").unwrap().family, "My Font");
+		assert_eq!(parse("font-family: x; src: url(a.ttf) format(a) format(b), url(b.ttf) junk, url(c.ttf)").unwrap().sources, [url("c.ttf", None)]);
+		assert!(parse("font-family: serif; src: url(a.ttf)").is_none());
+		assert!(parse("font-family: a, b; src: url(a.ttf)").is_none());
+		assert!(parse("font-family: x").is_none());
+		assert!(parse("font-family: x; src: junk").is_none());
+	}
+
+	#[test]
+	fn descriptors() {
+		let rule = parse("font-family: x; src: url(a.ttf); font-weight: 700 300; font-style: italic; unicode-range: U+0-7F, U+4??, u+2603").unwrap();
+		assert_eq!((rule.weight, rule.style), ((300, 700), FontStyle::Italic));
+		assert_eq!(rule.unicode_range, [(0, 0x7f), (0x400, 0x4ff), (0x2603, 0x2603)]);
+		assert!(rule.covers('a') && rule.covers('Ї') && rule.covers('☃') && !rule.covers('é'));
+		assert_eq!(rule.declarations.len(), 5);
+
+		let rule = parse("font-family: x; src: url(a.ttf); font-weight: bold; color: red; unicode-range: U+7F-0").unwrap();
+		assert_eq!((rule.weight, rule.style), ((700, 700), FontStyle::Normal));
+		assert_eq!(rule.unicode_range, [(0, 0x10ffff)]);
+		assert_eq!(rule.declarations.len(), 4);
+		assert_eq!(parse("font-family: x; src: url(a.ttf); font-weight: auto").unwrap().weight, (1, 1000));
+		assert_eq!(parse("font-family: x; src: url(a.ttf); font-weight: 1001").unwrap().weight, (400, 400));
+	}
+}
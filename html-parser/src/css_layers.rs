@@ -0,0 +1,107 @@
+/// The cascade layers of one origin, as a tree in the order each layer was first declared. Layer `UNLAYERED` is the
+/// root, holding the styles outside any `@layer`.
+#[derive(Debug, Clone)]
+pub struct LayerOrder {
+	/// The full dotted name of each layer, empty for the root and anonymous layers.
+	names: Vec<String>,
+	children: Vec<Vec<usize>>
+}
+impl LayerOrder {
+	pub const UNLAYERED: usize = 0;
+
+	pub fn new() -> LayerOrder {
+		LayerOrder {
+			names: vec![String::new()],
+			children: vec![vec![]]
+		}
+	}
+
+	fn child(&mut self, parent: usize, name: Option<&str>) -> usize {
+		if let Some(name) = name {
+			let full_name = if parent == LayerOrder::UNLAYERED { String::from(name) } else { format!("{}.{}", self.names[parent], name) };
+			if let Some(existing) = self.children[parent].iter().find(|child| self.names[**child] == full_name) {
+				return *existing;
+			}
+			self.names.push(full_name);
+		} else {
+			self.names.push(String::new());
+		}
+
+		self.children.push(vec![]);
+		let layer = self.names.len() - 1;
+		self.children[parent].push(layer);
+		layer
+	}
+
+	/// The layer `name` within `parent`, added after its existing siblings if it is new. `name` can be dotted, and
+	/// without one the layer is anonymous, so a new one each time.
+	pub fn declare(&mut self, parent: usize, name: Option<&str>) -> usize {
+		match name {
+			Some(name) => name.split('.').fold(parent, |layer, segment| self.child(layer, Some(segment))),
+			None => self.child(parent, None)
+		}
+	}
+
+	/// The full name of `layer`, `None` for the root and anonymous layers.
+	pub fn name(&self, layer: usize) -> Option<&str> {
+		Some(self.names[layer].as_str()).filter(|name| !name.is_empty())
+	}
+
+	fn rank(&self, layer: usize, ranks: &mut Vec<usize>, next: &mut usize) {
+		for child in &self.children[layer] {
+			self.rank(*child, ranks, next);
+		}
+		ranks[layer] = *next;
+		*next += 1;
+	}
+
+	/// The precedence of each layer for normal declarations, higher wins. Earlier layers lose to later ones, and the
+	/// styles directly in a layer beat those of the layers nested in it, so unlayered styles rank last.
+	pub fn ranks(&self) -> Vec<usize> {
+		let mut ranks = vec![0; self.names.len()];
+		self.rank(LayerOrder::UNLAYERED, &mut ranks, &mut 0);
+		ranks
+	}
+}
+
+impl Default for LayerOrder {
+	fn default() -> LayerOrder {
+		LayerOrder::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn declaring() {
+		let mut layers = LayerOrder::new();
+		let base = layers.declare(LayerOrder::UNLAYERED, Some("base"));
+		let nested = layers.declare(LayerOrder::UNLAYERED, Some("base.reset"));
+		assert_eq!(layers.declare(base, Some("reset")), nested);
+		assert_eq!(layers.declare(LayerOrder::UNLAYERED, Some("base")), base);
+		assert_eq!((layers.name(base), layers.name(nested)), (Some("base"), Some("base.reset")));
+
+		let anonymous = layers.declare(LayerOrder::UNLAYERED, None);
+		assert_ne!(layers.declare(LayerOrder::UNLAYERED, None), anonymous);
+		assert_eq!(layers.name(anonymous), None);
+		assert_eq!(layers.name(LayerOrder::UNLAYERED), None);
+	}
+
+	/// Later layers beat earlier ones, a layer's own styles beat its sublayers', and unlayered styles beat every layer.
+	#[test]
+	fn ranks() {
+		let mut layers = LayerOrder::new();
+		let theme = layers.declare(LayerOrder::UNLAYERED, Some("theme"));
+		let base = layers.declare(LayerOrder::UNLAYERED, Some("base"));
+		let theme_dark = layers.declare(theme, Some("dark"));
+		let theme_light = layers.declare(theme, Some("light"));
+		let base_reset = layers.declare(base, Some("reset"));
+
+		let ranks = layers.ranks();
+		let mut order = vec![LayerOrder::UNLAYERED, theme, base, theme_dark, theme_light, base_reset];
+		order.sort_by_key(|layer| ranks[*layer]);
+		assert_eq!(order, [theme_dark, theme_light, theme, base_reset, base, LayerOrder::UNLAYERED]);
+	}
+}
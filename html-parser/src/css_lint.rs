@@ -0,0 +1,291 @@
+use std::fmt;
+use std::rc::Rc;
+use super::css_font_face::{self, FontFaceRule};
+use super::css_parse::{self, ComponentValue, DeclarationListItem, LayerRule, Rule, ValueSpan};
+use super::css_properties;
+use super::css_supports::SupportsQuery;
+use super::document::Document;
+use super::source_position::{SourcePosition, SourceSpan};
+use super::tree_generation_2::HTMLNode;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+	UnknownProperty(String),
+	/// A known property, with the value that doesn't fit its grammar.
+	InvalidValue(String, String),
+	InvalidDeclaration,
+	/// A block or function still open at the end of the stylesheet, keyed by its opening character.
+	UnclosedBlock(char),
+	UnsupportedAtRule(String),
+	/// An `@import` after other rules or inside a block, which is ignored.
+	MisplacedImport,
+	/// An `@font-face` without a usable `font-family` or `src`, which is ignored.
+	InvalidFontFace,
+	/// An at-rule, by name, whose prelude doesn't fit its grammar.
+	InvalidPrelude(String)
+}
+
+/// A problem found while parsing a stylesheet. Everything but an unclosed block makes the parser drop a declaration
+/// or rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	pub kind: DiagnosticKind,
+	pub span: SourceSpan
+}
+impl Diagnostic {
+	fn new(kind: DiagnosticKind, span: SourceSpan) -> Diagnostic {
+		Diagnostic {
+			kind, span
+		}
+	}
+
+	/// Moves the diagnostic of a stylesheet embedded at `start` of a larger document into that document.
+	pub fn relative_to(&self, start: SourcePosition) -> Diagnostic {
+		let shift = |position: SourcePosition| SourcePosition {
+			offset: start.offset + position.offset,
+			line: start.line + position.line - 1,
+			column: if position.line == 1 { start.column + position.column - 1 } else { position.column }
+		};
+		Diagnostic::new(self.kind.clone(), SourceSpan::new(shift(self.span.start), shift(self.span.end)))
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}: ", self.span.start.line, self.span.start.column)?;
+		match &self.kind {
+			DiagnosticKind::UnknownProperty(name) => write!(f, "unknown property `{}`", name),
+			DiagnosticKind::InvalidValue(name, value) => write!(f, "invalid value for `{}`: `{}`", name, value),
+			DiagnosticKind::InvalidDeclaration => write!(f, "invalid declaration"),
+			DiagnosticKind::UnclosedBlock(open) => write!(f, "unclosed `{}`", open),
+			DiagnosticKind::UnsupportedAtRule(name) => write!(f, "unsupported at-rule `@{}`", name),
+			DiagnosticKind::MisplacedImport => write!(f, "`@import` must come before all other rules and is ignored"),
+			DiagnosticKind::InvalidFontFace => write!(f, "`@font-face` needs a valid `font-family` and `src` and is ignored"),
+			DiagnosticKind::InvalidPrelude(name) => write!(f, "invalid prelude for `@{}`", name)
+		}
+	}
+}
+
+struct Linter {
+	diagnostics: Vec<Diagnostic>
+}
+impl Linter {
+	fn rules(&mut self, values: &[ComponentValue], spans: &[ValueSpan], top_level: bool) {
+		let mut imports_allowed = top_level;
+		for (idx, rule) in css_parse::rule_list(values, top_level) {
+			let span = spans[idx].span;
+			match rule {
+				Rule::Qualified(rule) => {
+					imports_allowed = false;
+					self.declarations(&rule.block, &spans[idx + rule.prelude.len()].contents, true);
+				},
+				Rule::At(rule) => match rule.name.to_ascii_lowercase().as_str() {
+					"charset" => {},
+					"import" => if !imports_allowed {
+						self.diagnostics.push(Diagnostic::new(DiagnosticKind::MisplacedImport, span));
+					},
+					"font-face" => {
+						imports_allowed = false;
+						if let Some(block) = &rule.block {
+							self.font_face(block, &spans[idx + 1 + rule.prelude.len()].contents, span);
+						}
+					},
+					"layer" => {
+						if LayerRule::parse(&rule.prelude, rule.block.as_ref()).is_none() {
+							self.diagnostics.push(Diagnostic::new(DiagnosticKind::InvalidPrelude(rule.name.clone()), span));
+						}
+						if let Some(block) = &rule.block {
+							imports_allowed = false;
+							self.rules(block, &spans[idx + 1 + rule.prelude.len()].contents, false);
+						}
+					},
+					"supports" => {
+						imports_allowed = false;
+						if SupportsQuery::parse(&rule.prelude).is_none() {
+							self.diagnostics.push(Diagnostic::new(DiagnosticKind::InvalidPrelude(rule.name.clone()), span));
+						} else if let Some(block) = &rule.block {
+							self.rules(block, &spans[idx + 1 + rule.prelude.len()].contents, false);
+						}
+					},
+					"media" => {
+						imports_allowed = false;
+						if let Some(block) = &rule.block {
+							self.rules(block, &spans[idx + 1 + rule.prelude.len()].contents, false);
+						}
+					},
+					_ => {
+						imports_allowed = false;
+						self.diagnostics.push(Diagnostic::new(DiagnosticKind::UnsupportedAtRule(rule.name.clone()), span));
+					}
+				}
+			}
+		}
+	}
+
+	fn font_face(&mut self, values: &[ComponentValue], spans: &[ValueSpan], span: SourceSpan) {
+		let mut declarations = vec![];
+		for (idx, item) in css_parse::declaration_list(values) {
+			let kind = match item {
+				None => DiagnosticKind::InvalidDeclaration,
+				Some(DeclarationListItem::At(rule)) => DiagnosticKind::UnsupportedAtRule(rule.name),
+				Some(DeclarationListItem::Rule(_)) => DiagnosticKind::InvalidDeclaration,
+				Some(DeclarationListItem::Declaration(declaration)) if css_font_face::DESCRIPTORS.contains(&declaration.name.as_str()) => {
+					declarations.push(Rc::new(declaration));
+					continue;
+				},
+				Some(DeclarationListItem::Declaration(declaration)) => DiagnosticKind::UnknownProperty(declaration.name)
+			};
+			self.diagnostics.push(Diagnostic::new(kind, spans[idx].span));
+		}
+
+		if FontFaceRule::parse(declarations).is_none() {
+			self.diagnostics.push(Diagnostic::new(DiagnosticKind::InvalidFontFace, span));
+		}
+	}
+
+	/// Checks the declarations of a style rule, and the rules nested in it when `nested`, or of a `style` attribute.
+	/// Only `@media` and `@supports` can be nested.
+	fn declarations(&mut self, values: &[ComponentValue], spans: &[ValueSpan], nested: bool) {
+		for (idx, item) in css_parse::declaration_list(values) {
+			let span = spans[idx].span;
+			let kind = match item {
+				None => DiagnosticKind::InvalidDeclaration,
+				Some(DeclarationListItem::At(rule)) if nested && rule.name.eq_ignore_ascii_case("supports") && SupportsQuery::parse(&rule.prelude).is_none() => {
+					DiagnosticKind::InvalidPrelude(rule.name)
+				},
+				Some(DeclarationListItem::At(rule)) if nested && (rule.name.eq_ignore_ascii_case("media") || rule.name.eq_ignore_ascii_case("supports")) => {
+					if let Some(block) = &rule.block {
+						self.declarations(block, &spans[idx + 1 + rule.prelude.len()].contents, true);
+					}
+					continue;
+				},
+				Some(DeclarationListItem::At(rule)) => DiagnosticKind::UnsupportedAtRule(rule.name),
+				Some(DeclarationListItem::Rule(rule)) if nested => {
+					self.declarations(&rule.block, &spans[idx + rule.prelude.len()].contents, true);
+					continue;
+				},
+				Some(DeclarationListItem::Rule(_)) => DiagnosticKind::InvalidDeclaration,
+				Some(DeclarationListItem::Declaration(declaration)) => {
+					let declaration = Rc::new(declaration);
+					if !css_properties::is_known(&declaration.name) {
+						DiagnosticKind::UnknownProperty(declaration.name.clone())
+					} else if !css_properties::is_valid(&declaration) {
+						DiagnosticKind::InvalidValue(declaration.name.clone(), declaration.value.clone())
+					} else { continue; }
+				}
+			};
+			self.diagnostics.push(Diagnostic::new(kind, span));
+		}
+	}
+}
+
+/// The diagnostics of a stylesheet, in source order.
+pub fn lint(css: &str) -> Vec<Diagnostic> {
+	let (values, spans, unclosed) = css_parse::parse_component_values_spanned(css);
+	lint_values(&values, &spans, unclosed)
+}
+
+/// Like `lint`, for a stylesheet that is already split into component values.
+pub(crate) fn lint_values(values: &[ComponentValue], spans: &[ValueSpan], unclosed: Vec<(char, SourceSpan)>) -> Vec<Diagnostic> {
+	let mut linter = Linter {
+		diagnostics: unclosed.into_iter().map(|(open, span)| Diagnostic::new(DiagnosticKind::UnclosedBlock(open), span)).collect()
+	};
+	linter.rules(values, spans, true);
+
+	linter.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+	linter.diagnostics
+}
+
+/// The diagnostics of the declarations in a `style` attribute.
+pub fn lint_declarations(css: &str) -> Vec<Diagnostic> {
+	let (values, spans, unclosed) = css_parse::parse_component_values_spanned(css);
+	let mut linter = Linter {
+		diagnostics: unclosed.into_iter().map(|(open, span)| Diagnostic::new(DiagnosticKind::UnclosedBlock(open), span)).collect()
+	};
+	linter.declarations(&values, &spans, false);
+
+	linter.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+	linter.diagnostics
+}
+
+/// The diagnostics of every `<style>` element in `document`, positioned in the HTML source.
+pub fn lint_document(document: &Document) -> Vec<Diagnostic> {
+	let styles = document.root().find(|node| matches!(&*node.value.borrow(), HTMLNode::Element(el) if el.is_html_tag("style")));
+	styles.iter().flat_map(|style| {
+		let text = style.get_child(0);
+		let text = text.as_ref().map(|text| text.value.borrow());
+		match text.as_deref() {
+			Some(HTMLNode::Text(css, span)) => lint(css).iter().map(|diagnostic| diagnostic.relative_to(span.start)).collect(),
+			_ => vec![]
+		}
+	}).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::parse_html_new;
+
+	/// The kind of each diagnostic with the line and column it starts at.
+	fn positioned(diagnostics: Vec<Diagnostic>) -> Vec<(DiagnosticKind, usize, usize)> {
+		diagnostics.into_iter().map(|diagnostic| (diagnostic.kind, diagnostic.span.start.line, diagnostic.span.start.column)).collect()
+	}
+
+	fn unknown(name: &str) -> DiagnosticKind {
+		DiagnosticKind::UnknownProperty(String::from(name))
+	}
+
+	#[test]
+	fn declarations() {
+		assert_eq!(positioned(lint("a { colr: red; color: 12px; color red; color: blue }")), [
+			(unknown("colr"), 1, 5),
+			(DiagnosticKind::InvalidValue(String::from("color"), String::from("12px")), 1, 16),
+			(DiagnosticKind::InvalidDeclaration, 1, 29)
+		]);
+		assert!(lint("a { --anything: { 1 }; color: var(--x); margin: 1px auto !important }").is_empty());
+	}
+
+	#[test]
+	fn rules() {
+		let css = "@foo;\n@import 'a.css';\nb { @media print { c: d } & e { f: g } }\n@font-face { src: url(x.ttf) }\n@layer a.;\n@supports display: grid { }\n@media print { a { color: red }";
+		assert_eq!(positioned(lint(css)), [
+			(DiagnosticKind::UnsupportedAtRule(String::from("foo")), 1, 1),
+			(DiagnosticKind::MisplacedImport, 2, 1),
+			(unknown("c"), 3, 20),
+			(unknown("f"), 3, 33),
+			(DiagnosticKind::InvalidFontFace, 4, 1),
+			(DiagnosticKind::InvalidPrelude(String::from("layer")), 5, 1),
+			(DiagnosticKind::InvalidPrelude(String::from("supports")), 6, 1),
+			(DiagnosticKind::UnclosedBlock('{'), 7, 14)
+		]);
+	}
+
+	#[test]
+	fn spans() {
+		let diagnostics = lint("a {\n  colr: red;\n}\nb { color: red");
+		assert_eq!(diagnostics.len(), 2);
+		assert_eq!(diagnostics[0].span, SourceSpan::new(SourcePosition { offset: 6, line: 2, column: 3 }, SourcePosition { offset: 10, line: 2, column: 7 }));
+		assert_eq!(diagnostics[1].kind, DiagnosticKind::UnclosedBlock('{'));
+		assert_eq!((diagnostics[1].span.start.offset, diagnostics[1].span.end.offset), (21, 22));
+		assert_eq!(diagnostics[0].to_string(), "2:3: unknown property `colr`");
+	}
+
+	#[test]
+	fn style_attributes() {
+		assert_eq!(positioned(lint_declarations("color: red; colr: blue; .a { color: red }; @media print {}")), [
+			(unknown("colr"), 1, 13),
+			(DiagnosticKind::InvalidDeclaration, 1, 25),
+			(DiagnosticKind::UnsupportedAtRule(String::from("media")), 1, 44)
+		]);
+	}
+
+	#[test]
+	fn positions_in_documents() {
+		let document = parse_html_new("<html><head>\n<style>a { b: c }\n  d { colr: red }</style></head></html>");
+		assert_eq!(positioned(lint_document(&document)), [(unknown("b"), 2, 12), (unknown("colr"), 3, 7)]);
+
+		let diagnostic = Diagnostic::new(DiagnosticKind::InvalidDeclaration, SourceSpan::new(SourcePosition { offset: 2, line: 1, column: 3 }, SourcePosition { offset: 3, line: 1, column: 4 }));
+		let shifted = diagnostic.relative_to(SourcePosition { offset: 100, line: 5, column: 10 });
+		assert_eq!((shifted.span.start.line, shifted.span.start.column, shifted.span.end.offset), (5, 12, 103));
+	}
+}
@@ -1,58 +1,785 @@
 use std::rc::Rc;
-use regex::Regex;
+use super::css_font_face::FontFaceRule;
+use super::css_layers::LayerOrder;
+use super::css_supports::SupportsQuery;
+use super::css_tokenize::{self, CSSToken};
+use super::css_value::CSSValue;
+use super::media_query::{MediaEnvironment, MediaQueryList};
+use super::source_position::{SourcePosition, SourceSpan};
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentValue {
+	Token(CSSToken),
+	Function { name: String, arguments: Vec<ComponentValue> },
+	/// A `{}`, `[]` or `()` block, keyed by its opening character.
+	Block { open: char, contents: Vec<ComponentValue> }
+}
+impl ComponentValue {
+	pub fn to_css(&self) -> String {
+		match self {
+			ComponentValue::Token(token) => token.to_css(),
+			ComponentValue::Function { name, arguments } => format!("{}({})", css_tokenize::serialize_identifier(name), serialize(arguments)),
+			ComponentValue::Block { open, contents } => format!("{}{}{}", open, serialize(contents), closing(*open))
+		}
+	}
+
+	pub fn is_whitespace(&self) -> bool {
+		*self == ComponentValue::Token(CSSToken::Whitespace)
+	}
+}
+
+fn closing(open: char) -> char {
+	match open {
+		'{' => '}',
+		'[' => ']',
+		'(' => ')',
+		_ => panic!("Unreachable")
+	}
+}
+
+pub fn serialize(values: &[ComponentValue]) -> String {
+	values.iter().map(|value| value.to_css()).collect()
+}
+
+/// `values` without leading and trailing whitespace.
+pub(crate) fn trim(values: &[ComponentValue]) -> &[ComponentValue] {
+	let start = values.iter().position(|value| !value.is_whitespace()).unwrap_or(values.len());
+	let end = values.iter().rposition(|value| !value.is_whitespace()).map_or(start, |end| end + 1);
+	&values[start..end]
+}
+
+#[derive(Debug, Clone)]
+pub struct AtRule {
+	pub name: String,
+	pub prelude: Vec<ComponentValue>,
+	pub block: Option<Vec<ComponentValue>>
+}
+
+#[derive(Debug, Clone)]
+pub struct QualifiedRule {
+	pub prelude: Vec<ComponentValue>,
+	pub block: Vec<ComponentValue>
+}
+
+#[derive(Debug, Clone)]
+pub enum Rule {
+	At(AtRule),
+	Qualified(QualifiedRule)
+}
+
+/// A declaration, `name: value`. Property names are lowercased, custom properties keep their case.
 #[derive(Debug)]
 pub struct CSSRule {
 	pub name: String,
-	pub value: String
+	pub value: String,
+	pub important: bool,
+	pub components: Vec<ComponentValue>,
+	pub values: Vec<CSSValue>
 }
 
 impl CSSRule {
-	fn new(name: String, value: String) -> CSSRule {
+	pub(crate) fn new(name: String, components: Vec<ComponentValue>, important: bool) -> CSSRule {
+		let name = if name.starts_with("--") { name } else { name.to_ascii_lowercase() };
 		CSSRule {
-			name, value
+			value: serialize(trim(&components)),
+			values: CSSValue::from_components(&components),
+			name, important, components
 		}
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CSSBlock {
 	pub rules: Vec<Rc<CSSRule>>,
-	pub selector: String
+	pub selector: String,
+	/// The rules nested in this one, in source order. Style rules have their selectors already resolved against this
+	/// one, so `& .title` inside `.card` is `.card .title`, and `@media` and `@supports` rules hold a rule with this
+	/// selector for the declarations in them.
+	pub children: Vec<CSSStatement>
 }
 
 impl CSSBlock {
-	fn new() -> CSSBlock {
+	pub(crate) fn new() -> CSSBlock {
 		CSSBlock {
 			rules: vec![],
-			selector: String::new()
+			selector: String::new(),
+			children: vec![]
 		}
 	}
 }
 
+/// An `@layer`. The statement form lists layer names to fix their order, the block form has at most one name and is
+/// anonymous without one. Names are dotted, like `framework.base`.
+#[derive(Debug, Clone)]
+pub struct LayerRule {
+	pub names: Vec<String>,
+	pub statements: Option<Vec<CSSStatement>>
+}
+impl LayerRule {
+	pub(crate) fn parse(prelude: &[ComponentValue], block: Option<&Vec<ComponentValue>>) -> Option<LayerRule> {
+		let names = trim(prelude).split(|value| *value == ComponentValue::Token(CSSToken::Comma)).map(|name| {
+			let name = trim(name);
+			let mut segments = vec![];
+			for (idx, value) in name.iter().enumerate() {
+				match value {
+					ComponentValue::Token(CSSToken::Ident(segment)) if idx % 2 == 0 => segments.push(segment.clone()),
+					ComponentValue::Token(CSSToken::Delim('.')) if idx % 2 == 1 => {},
+					_ => return None
+				}
+			}
+			if segments.len() * 2 == name.len() + 1 { Some(segments.join(".")) } else { None }
+		});
+
+		match block {
+			Some(block) => Some(LayerRule {
+				names: if trim(prelude).is_empty() { vec![] } else { vec![names.collect::<Option<Vec<String>>>().filter(|names| names.len() == 1)?.remove(0)] },
+				statements: Some(statements(&parse_rule_list(block, false), false))
+			}),
+			None => Some(LayerRule {
+				names: names.collect::<Option<Vec<String>>>()?,
+				statements: None
+			})
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaRule {
+	pub queries: MediaQueryList,
+	pub statements: Vec<CSSStatement>
+}
+
+/// An `@supports`, whose rules only apply when its condition holds.
+#[derive(Debug, Clone)]
+pub struct SupportsRule {
+	pub query: SupportsQuery,
+	pub statements: Vec<CSSStatement>
+}
+
+/// An `@import`. `stylesheet` is filled in by whoever can fetch `url`, until then the rule contributes nothing.
+#[derive(Debug, Clone)]
+pub struct ImportRule {
+	pub url: String,
+	pub queries: MediaQueryList,
+	pub stylesheet: Option<CSSStyleSheet>
+}
+impl ImportRule {
+	pub fn new(url: String, queries: MediaQueryList) -> ImportRule {
+		ImportRule {
+			url, queries,
+			stylesheet: None
+		}
+	}
+
+	fn parse(prelude: &[ComponentValue]) -> Option<ImportRule> {
+		let prelude = trim(prelude);
+		let url = match prelude.first()? {
+			ComponentValue::Token(CSSToken::String(url)) | ComponentValue::Token(CSSToken::Url(url)) => url.clone(),
+			ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("url") => match trim(arguments) {
+				[ComponentValue::Token(CSSToken::String(url))] => url.clone(),
+				_ => return None
+			},
+			_ => return None
+		};
+
+		Some(ImportRule::new(url, MediaQueryList::parse(&prelude[1..])))
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum CSSStatement {
+	Style(Rc<CSSBlock>),
+	Media(MediaRule),
+	Import(ImportRule),
+	FontFace(FontFaceRule),
+	Layer(LayerRule),
+	Supports(SupportsRule)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CSSStyleSheet {
+	pub statements: Vec<CSSStatement>
+}
+impl CSSStyleSheet {
+	fn collect_blocks(statements: &[CSSStatement], environment: &MediaEnvironment, blocks: &mut Vec<Rc<CSSBlock>>) {
+		for statement in statements {
+			match statement {
+				CSSStatement::Style(block) => {
+					blocks.push(block.clone());
+					CSSStyleSheet::collect_blocks(&block.children, environment, blocks);
+				},
+				CSSStatement::Media(rule) => if rule.queries.matches(environment) {
+					CSSStyleSheet::collect_blocks(&rule.statements, environment, blocks);
+				},
+				CSSStatement::Supports(rule) => if rule.query.matches(environment) {
+					CSSStyleSheet::collect_blocks(&rule.statements, environment, blocks);
+				},
+				CSSStatement::Import(rule) => if let Some(stylesheet) = &rule.stylesheet {
+					if rule.queries.matches(environment) {
+						CSSStyleSheet::collect_blocks(&stylesheet.statements, environment, blocks);
+					}
+				},
+				CSSStatement::Layer(rule) => if let Some(statements) = &rule.statements {
+					CSSStyleSheet::collect_blocks(statements, environment, blocks);
+				},
+				CSSStatement::FontFace(_) => {}
+			}
+		}
+	}
+
+	fn collect_font_faces(statements: &[CSSStatement], environment: &MediaEnvironment, faces: &mut Vec<FontFaceRule>) {
+		for statement in statements {
+			match statement {
+				CSSStatement::FontFace(rule) => faces.push(rule.clone()),
+				CSSStatement::Media(rule) => if rule.queries.matches(environment) {
+					CSSStyleSheet::collect_font_faces(&rule.statements, environment, faces);
+				},
+				CSSStatement::Supports(rule) => if rule.query.matches(environment) {
+					CSSStyleSheet::collect_font_faces(&rule.statements, environment, faces);
+				},
+				CSSStatement::Import(rule) => if let Some(stylesheet) = &rule.stylesheet {
+					if rule.queries.matches(environment) {
+						CSSStyleSheet::collect_font_faces(&stylesheet.statements, environment, faces);
+					}
+				},
+				CSSStatement::Layer(rule) => if let Some(statements) = &rule.statements {
+					CSSStyleSheet::collect_font_faces(statements, environment, faces);
+				},
+				CSSStatement::Style(_) => {}
+			}
+		}
+	}
+
+	fn collect_layered_blocks(statements: &[CSSStatement], environment: &MediaEnvironment, layers: &mut LayerOrder, layer: usize, blocks: &mut Vec<(usize, Rc<CSSBlock>)>) {
+		for statement in statements {
+			match statement {
+				CSSStatement::Style(block) => {
+					blocks.push((layer, block.clone()));
+					CSSStyleSheet::collect_layered_blocks(&block.children, environment, layers, layer, blocks);
+				},
+				CSSStatement::Media(rule) => if rule.queries.matches(environment) {
+					CSSStyleSheet::collect_layered_blocks(&rule.statements, environment, layers, layer, blocks);
+				},
+				CSSStatement::Supports(rule) => if rule.query.matches(environment) {
+					CSSStyleSheet::collect_layered_blocks(&rule.statements, environment, layers, layer, blocks);
+				},
+				CSSStatement::Import(rule) => if let Some(stylesheet) = &rule.stylesheet {
+					if rule.queries.matches(environment) {
+						CSSStyleSheet::collect_layered_blocks(&stylesheet.statements, environment, layers, layer, blocks);
+					}
+				},
+				CSSStatement::Layer(rule) => match &rule.statements {
+					Some(statements) => {
+						let inner = layers.declare(layer, rule.names.first().map(|name| name.as_str()));
+						CSSStyleSheet::collect_layered_blocks(statements, environment, layers, inner, blocks);
+					},
+					None => for name in &rule.names {
+						layers.declare(layer, Some(name));
+					}
+				},
+				CSSStatement::FontFace(_) => {}
+			}
+		}
+	}
+
+	/// The style rules whose enclosing `@media` and `@supports` rules all match `environment`, in source order.
+	pub fn blocks(&self, environment: &MediaEnvironment) -> Vec<Rc<CSSBlock>> {
+		let mut blocks = vec![];
+		CSSStyleSheet::collect_blocks(&self.statements, environment, &mut blocks);
+		blocks
+	}
+
+	/// Like `blocks`, with the cascade layer of each block. Layers are declared in `layers` as they're found, so
+	/// stylesheets of the same origin can share one order.
+	pub fn layered_blocks(&self, environment: &MediaEnvironment, layers: &mut LayerOrder) -> Vec<(usize, Rc<CSSBlock>)> {
+		let mut blocks = vec![];
+		CSSStyleSheet::collect_layered_blocks(&self.statements, environment, layers, LayerOrder::UNLAYERED, &mut blocks);
+		blocks
+	}
+
+	/// The `@font-face` rules that apply in `environment`, in source order.
+	pub fn font_faces(&self, environment: &MediaEnvironment) -> Vec<FontFaceRule> {
+		let mut faces = vec![];
+		CSSStyleSheet::collect_font_faces(&self.statements, environment, &mut faces);
+		faces
+	}
+}
+
+/// Where a component value came from, with the spans of the values inside it if it is a block or function.
+#[derive(Debug, Clone, Default)]
+pub struct ValueSpan {
+	pub span: SourceSpan,
+	pub contents: Vec<ValueSpan>
+}
+
+/// Turns a token stream into component values, following the consume algorithms of CSS Syntax Level 3.
+struct Parser {
+	tokens: Vec<(CSSToken, SourceSpan)>,
+	position: usize,
+	/// Blocks and functions that were still open at the end of input, with the span of their opening token.
+	unclosed: Vec<(char, SourceSpan)>
+}
+impl Parser {
+	fn new(tokens: Vec<(CSSToken, SourceSpan)>) -> Parser {
+		Parser {
+			tokens, position: 0,
+			unclosed: vec![]
+		}
+	}
+
+	fn next_token(&mut self) -> Option<(CSSToken, SourceSpan)> {
+		let token = self.tokens.get(self.position).cloned();
+		if token.is_some() { self.position += 1; }
+		token
+	}
+
+	fn consume_component_value(&mut self, token: CSSToken, span: SourceSpan) -> (ComponentValue, ValueSpan) {
+		let (open, name) = match token {
+			CSSToken::OpenCurly => ('{', None),
+			CSSToken::OpenSquare => ('[', None),
+			CSSToken::OpenParen => ('(', None),
+			CSSToken::Function(name) => ('(', Some(name)),
+			token => return (ComponentValue::Token(token), ValueSpan { span, contents: vec![] })
+		};
+
+		let (contents, spans, end) = self.consume_block_contents(open, span);
+		let value = match name {
+			Some(name) => ComponentValue::Function { name, arguments: contents },
+			None => ComponentValue::Block { open, contents }
+		};
+		(value, ValueSpan { span: SourceSpan::new(span.start, end), contents: spans })
+	}
+
+	/// Consumes up to and including the token closing `open`, or to the end of input.
+	fn consume_block_contents(&mut self, open: char, start: SourceSpan) -> (Vec<ComponentValue>, Vec<ValueSpan>, SourcePosition) {
+		let mut contents = vec![];
+		let mut spans: Vec<ValueSpan> = vec![];
+		loop {
+			match self.next_token() {
+				None => {
+					self.unclosed.push((open, start));
+					let end = spans.last().map_or(start.end, |last| last.span.end);
+					return (contents, spans, end);
+				},
+				Some((CSSToken::CloseCurly, span)) if open == '{' => return (contents, spans, span.end),
+				Some((CSSToken::CloseSquare, span)) if open == '[' => return (contents, spans, span.end),
+				Some((CSSToken::CloseParen, span)) if open == '(' => return (contents, spans, span.end),
+				Some((token, span)) => {
+					let (value, span) = self.consume_component_value(token, span);
+					contents.push(value);
+					spans.push(span);
+				}
+			}
+		}
+	}
+}
+
+/// Parses `css` into component values along with where each came from, and the blocks left unclosed.
+pub fn parse_component_values_spanned(css: &str) -> (Vec<ComponentValue>, Vec<ValueSpan>, Vec<(char, SourceSpan)>) {
+	let mut parser = Parser::new(css_tokenize::tokenize_spanned(css));
+	let mut values = vec![];
+	let mut spans = vec![];
+	while let Some((token, span)) = parser.next_token() {
+		let (value, span) = parser.consume_component_value(token, span);
+		values.push(value);
+		spans.push(span);
+	}
+
+	(values, spans, parser.unclosed)
+}
+
+pub fn parse_component_values(css: &str) -> Vec<ComponentValue> {
+	parse_component_values_spanned(css).0
+}
+
+/// Consumes an at-rule starting at `values[*idx]`, up to its `;` or `{}` block.
+fn consume_at_rule(values: &[ComponentValue], idx: &mut usize, name: &str) -> AtRule {
+	let mut prelude = vec![];
+	*idx += 1;
+	while *idx < values.len() {
+		*idx += 1;
+		match &values[*idx - 1] {
+			ComponentValue::Token(CSSToken::Semicolon) => break,
+			ComponentValue::Block { open: '{', contents } => return AtRule { name: String::from(name), prelude, block: Some(contents.clone()) },
+			value => prelude.push(value.clone())
+		}
+	}
+
+	AtRule { name: String::from(name), prelude, block: None }
+}
+
+/// The rules in a stylesheet or in the block of an at-rule like `@media`. A qualified rule without a block is dropped.
+pub fn parse_rule_list(values: &[ComponentValue], top_level: bool) -> Vec<Rule> {
+	rule_list(values, top_level).into_iter().map(|(_, rule)| rule).collect()
+}
+
+/// Like `parse_rule_list`, with the index in `values` each rule starts at.
+pub fn rule_list(values: &[ComponentValue], top_level: bool) -> Vec<(usize, Rule)> {
+	let mut rules = vec![];
+	let mut idx = 0;
+
+	while idx < values.len() {
+		match &values[idx] {
+			ComponentValue::Token(CSSToken::Whitespace) => idx += 1,
+			ComponentValue::Token(CSSToken::CDO) | ComponentValue::Token(CSSToken::CDC) if top_level => idx += 1,
+			ComponentValue::Token(CSSToken::AtKeyword(name)) => rules.push((idx, Rule::At(consume_at_rule(values, &mut idx, name)))),
+			_ => {
+				let prelude_end = values[idx..].iter().position(|value| matches!(value, ComponentValue::Block { open: '{', .. }));
+				match prelude_end {
+					Some(end) => {
+						let block = match &values[idx + end] {
+							ComponentValue::Block { contents, .. } => contents.clone(),
+							_ => panic!("Unreachable")
+						};
+						rules.push((idx, Rule::Qualified(QualifiedRule { prelude: values[idx..idx + end].to_vec(), block })));
+						idx += end + 1;
+					},
+					None => idx = values.len()
+				}
+			}
+		}
+	}
+
+	rules
+}
+
+/// Declarations, at-rules and nested style rules inside a block. Invalid declarations are skipped up to the next `;`.
+#[derive(Debug)]
+pub enum DeclarationListItem {
+	Declaration(CSSRule),
+	At(AtRule),
+	Rule(QualifiedRule)
+}
+
+pub fn parse_declaration_list(values: &[ComponentValue]) -> Vec<DeclarationListItem> {
+	declaration_list(values).into_iter().filter_map(|(_, item)| item).collect()
+}
+
+/// Like `parse_declaration_list`, with the index in `values` each item starts at. Invalid declarations are `None`.
+pub fn declaration_list(values: &[ComponentValue]) -> Vec<(usize, Option<DeclarationListItem>)> {
+	let mut items = vec![];
+	let mut idx = 0;
+
+	while idx < values.len() {
+		match &values[idx] {
+			ComponentValue::Token(CSSToken::Whitespace) | ComponentValue::Token(CSSToken::Semicolon) => idx += 1,
+			ComponentValue::Token(CSSToken::AtKeyword(name)) => items.push((idx, Some(DeclarationListItem::At(consume_at_rule(values, &mut idx, name))))),
+			value => {
+				let end = values[idx..].iter().position(|value| *value == ComponentValue::Token(CSSToken::Semicolon)).map_or(values.len(), |end| idx + end);
+				// A `{}` block before the `;` makes this a nested rule, unless it is the value of a custom property.
+				let is_custom = matches!(value, ComponentValue::Token(CSSToken::Ident(name)) if name.starts_with("--"));
+				match values[idx..end].iter().position(|value| matches!(value, ComponentValue::Block { open: '{', .. })) {
+					Some(block) if !is_custom => {
+						let contents = match &values[idx + block] {
+							ComponentValue::Block { contents, .. } => contents.clone(),
+							_ => panic!("Unreachable")
+						};
+						items.push((idx, Some(DeclarationListItem::Rule(QualifiedRule { prelude: values[idx..idx + block].to_vec(), block: contents }))));
+						idx += block + 1;
+					},
+					_ => {
+						items.push((idx, parse_declaration(&values[idx..end]).map(DeclarationListItem::Declaration)));
+						idx = end;
+					}
+				}
+			}
+		}
+	}
+
+	items
+}
+
+pub(crate) fn parse_declaration(values: &[ComponentValue]) -> Option<CSSRule> {
+	let name = match values.first() {
+		Some(ComponentValue::Token(CSSToken::Ident(name))) => name.clone(),
+		_ => return None
+	};
+
+	let mut rest = values[1..].iter().skip_while(|value| value.is_whitespace());
+	if rest.next() != Some(&ComponentValue::Token(CSSToken::Colon)) { return None; }
+
+	let mut value: Vec<ComponentValue> = trim(&rest.cloned().collect::<Vec<_>>()).to_vec();
+	let significant: Vec<usize> = value.iter().enumerate().filter(|(_, value)| !value.is_whitespace()).map(|(idx, _)| idx).collect();
+	let mut important = false;
+	if let [.., bang, last] = significant[..] {
+		let is_bang = value[bang] == ComponentValue::Token(CSSToken::Delim('!'));
+		let is_important = matches!(&value[last], ComponentValue::Token(CSSToken::Ident(ident)) if ident.eq_ignore_ascii_case("important"));
+		if is_bang && is_important {
+			value.truncate(bang);
+			value = trim(&value).to_vec();
+			important = true;
+		}
+	}
+
+	Some(CSSRule::new(name, value, important))
+}
+
+pub fn parse_stylesheet(css: &str) -> Vec<Rule> {
+	parse_rule_list(&parse_component_values(css), true)
+}
+
+fn declarations(values: &[ComponentValue]) -> Vec<Rc<CSSRule>> {
+	parse_declaration_list(values).into_iter().filter_map(|item| match item {
+		DeclarationListItem::Declaration(declaration) => Some(Rc::new(declaration)),
+		DeclarationListItem::At(_) | DeclarationListItem::Rule(_) => None
+	}).collect()
+}
+
+/// Parses the declarations of a `style` attribute.
 pub fn create_block(css: &str) -> CSSBlock {
-	let rule_re = Regex::new(r"([a-z\-]+)\s*:\s*([^;]+);").unwrap();
 	let mut block = CSSBlock::new();
+	block.rules = declarations(&parse_component_values(css));
+	block
+}
 
-	block.rules = rule_re.captures_iter(css).map(|x| {
-		Rc::new(CSSRule::new(String::from(&x[1]), String::from((&x[2]).trim())))
-	}).collect();
+fn contains_nesting_selector(selector: &[ComponentValue]) -> bool {
+	selector.iter().any(|value| match value {
+		ComponentValue::Token(CSSToken::Delim('&')) => true,
+		ComponentValue::Function { arguments, .. } => contains_nesting_selector(arguments),
+		_ => false
+	})
+}
 
-	block
+fn is_combinator(value: &ComponentValue) -> bool {
+	matches!(value, ComponentValue::Token(CSSToken::Whitespace) | ComponentValue::Token(CSSToken::Delim('>' | '+' | '~')))
+}
+
+/// Replaces every `&` in `selector` with `parent`. Only a single compound selector can be written in place, and only
+/// where it can't merge with the compound around it, like `p` in `.x&`. Anything else becomes `:is(<parent>)`, which
+/// also keeps the specificity of the most specific selector in a list.
+fn replace_nesting_selector(selector: &[ComponentValue], parent: &[ComponentValue]) -> Vec<ComponentValue> {
+	let is_compound = !parent.iter().any(|value| is_combinator(value) || *value == ComponentValue::Token(CSSToken::Comma));
+	let starts_with_type = matches!(parent.first(), Some(ComponentValue::Token(CSSToken::Ident(_))) | Some(ComponentValue::Token(CSSToken::Delim('*' | '|'))));
+
+	let mut replaced: Vec<ComponentValue> = vec![];
+	for value in selector {
+		match value {
+			ComponentValue::Token(CSSToken::Delim('&')) => {
+				let starts_compound = replaced.last().is_none_or(|value| is_combinator(value) || *value == ComponentValue::Token(CSSToken::Comma));
+				if is_compound && (starts_compound || !starts_with_type) {
+					replaced.extend_from_slice(parent);
+				} else {
+					replaced.push(ComponentValue::Token(CSSToken::Colon));
+					replaced.push(ComponentValue::Function { name: String::from("is"), arguments: parent.to_vec() });
+				}
+			},
+			ComponentValue::Function { name, arguments } => replaced.push(ComponentValue::Function { name: name.clone(), arguments: replace_nesting_selector(arguments, parent) }),
+			value => replaced.push(value.clone())
+		}
+	}
+	replaced
 }
 
-pub fn create_blocks(css: &str) -> Vec<Rc<CSSBlock>> {
-	let block_re = Regex::new(r"([a-zA-Z_0-9.\->\s_#]+)\s*\{([^}]+)\}").unwrap();
-	let rule_re = Regex::new(r"([a-z\-]+)\s*:\s*([^;]+);").unwrap();
+/// Resolves the selector list of a nested rule against that of its parent. A selector starting with a combinator is
+/// relative to the parent, so `.a, .b { > p {} }` gives `:is(.a, .b) > p`, and one without `&` is a descendant of it.
+fn nested_selector(prelude: &[ComponentValue], parent: &str) -> String {
+	let parent = parse_component_values(parent);
+	let mut selectors = vec![];
+	for selector in prelude.split(|value| *value == ComponentValue::Token(CSSToken::Comma)).map(trim) {
+		let mut selector = selector.to_vec();
+		if selector.first().is_some_and(is_combinator) || !contains_nesting_selector(&selector) {
+			selector.splice(0..0, [ComponentValue::Token(CSSToken::Delim('&')), ComponentValue::Token(CSSToken::Whitespace)]);
+		}
 
-	block_re.captures_iter(css).map(|x| {
-		let mut block = CSSBlock::new();
-		block.selector = String::from((&x[1]).trim());
+		let resolved = serialize(&replace_nesting_selector(&selector, trim(&parent)));
+		if !selectors.contains(&resolved) { selectors.push(resolved); }
+	}
 
-		block.rules = rule_re.captures_iter(&x[2]).map(|x| {
-			Rc::new(CSSRule::new(String::from(&x[1]), String::from((&x[2]).trim())))
-		}).collect();
+	selectors.join(", ")
+}
+
+/// A style rule with the declarations of `contents` and the rules nested in it.
+fn style_block_with_selector(selector: String, contents: &[ComponentValue]) -> Rc<CSSBlock> {
+	let mut block = CSSBlock::new();
+	block.selector = selector;
+
+	for item in parse_declaration_list(contents) {
+		match item {
+			DeclarationListItem::Declaration(declaration) => block.rules.push(Rc::new(declaration)),
+			DeclarationListItem::Rule(rule) => {
+				let child = style_block_with_selector(nested_selector(&rule.prelude, &block.selector), &rule.block);
+				block.children.push(CSSStatement::Style(child));
+			},
+			DeclarationListItem::At(rule) => block.children.extend(nested_conditional_rule(&rule, &block.selector))
+		}
+	}
+	Rc::new(block)
+}
+
+/// A `@media` or `@supports` rule nested in a style rule with the selector `selector`. Its declarations apply to that
+/// selector, as if they were in a `& {}` rule.
+fn nested_conditional_rule(rule: &AtRule, selector: &str) -> Option<CSSStatement> {
+	let statements = vec![CSSStatement::Style(style_block_with_selector(String::from(selector), rule.block.as_ref()?))];
+	if rule.name.eq_ignore_ascii_case("media") {
+		Some(CSSStatement::Media(MediaRule { queries: MediaQueryList::parse(&rule.prelude), statements }))
+	} else if rule.name.eq_ignore_ascii_case("supports") {
+		Some(CSSStatement::Supports(SupportsRule { query: SupportsQuery::parse(&rule.prelude)?, statements }))
+	} else {
+		None
+	}
+}
+
+/// `@import` is only valid at the top level, before any rule other than `@charset`, `@layer` statements or another
+/// `@import`.
+pub(crate) fn statements(rules: &[Rule], top_level: bool) -> Vec<CSSStatement> {
+	let mut imports_allowed = top_level;
+	rules.iter().filter_map(|rule| {
+		let is_import = matches!(rule, Rule::At(rule) if rule.name.eq_ignore_ascii_case("import"));
+		let before_imports = matches!(rule, Rule::At(rule) if rule.name.eq_ignore_ascii_case("charset") || (rule.name.eq_ignore_ascii_case("layer") && rule.block.is_none()));
+		let allowed = imports_allowed;
+		if !is_import && !before_imports { imports_allowed = false; }
 
-		Rc::new(block)
+		match rule {
+			Rule::Qualified(rule) => Some(CSSStatement::Style(style_block_with_selector(serialize(trim(&rule.prelude)), &rule.block))),
+			Rule::At(rule) => at_rule_statement(rule, is_import && allowed)
+		}
 	}).collect()
-}
\ No newline at end of file
+}
+
+fn at_rule_statement(rule: &AtRule, import_allowed: bool) -> Option<CSSStatement> {
+	if rule.name.eq_ignore_ascii_case("media") {
+		Some(CSSStatement::Media(MediaRule {
+			queries: MediaQueryList::parse(&rule.prelude),
+			statements: statements(&parse_rule_list(rule.block.as_ref()?, false), false)
+		}))
+	} else if rule.name.eq_ignore_ascii_case("import") && import_allowed && rule.block.is_none() {
+		Some(CSSStatement::Import(ImportRule::parse(&rule.prelude)?))
+	} else if rule.name.eq_ignore_ascii_case("supports") {
+		Some(CSSStatement::Supports(SupportsRule {
+			query: SupportsQuery::parse(&rule.prelude)?,
+			statements: statements(&parse_rule_list(rule.block.as_ref()?, false), false)
+		}))
+	} else if rule.name.eq_ignore_ascii_case("layer") {
+		Some(CSSStatement::Layer(LayerRule::parse(&rule.prelude, rule.block.as_ref())?))
+	} else if rule.name.eq_ignore_ascii_case("font-face") {
+		Some(CSSStatement::FontFace(FontFaceRule::parse(declarations(rule.block.as_ref()?))?))
+	} else {
+		None
+	}
+}
+
+/// Parses a stylesheet, keeping the `@media` rules so they can be evaluated against different environments.
+pub fn create_stylesheet(css: &str) -> CSSStyleSheet {
+	stylesheet_of(&parse_component_values(css))
+}
+
+pub(crate) fn stylesheet_of(values: &[ComponentValue]) -> CSSStyleSheet {
+	CSSStyleSheet {
+		statements: statements(&parse_rule_list(values, true), true)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ident(name: &str) -> ComponentValue {
+		ComponentValue::Token(CSSToken::Ident(String::from(name)))
+	}
+
+	/// The name, value and importance of each declaration of `css`.
+	fn declarations_of(css: &str) -> Vec<(String, String, bool)> {
+		create_block(css).rules.iter().map(|rule| (rule.name.clone(), rule.value.clone(), rule.important)).collect()
+	}
+
+	#[test]
+	fn component_values() {
+		assert_eq!(parse_component_values("a(b [c]) {d}"), [
+			ComponentValue::Function { name: String::from("a"), arguments: vec![
+				ident("b"), ComponentValue::Token(CSSToken::Whitespace), ComponentValue::Block { open: '[', contents: vec![ident("c")] }
+			]},
+			ComponentValue::Token(CSSToken::Whitespace),
+			ComponentValue::Block { open: '{', contents: vec![ident("d")] }
+		]);
+		assert_eq!(parse_component_values("(a]"), [ComponentValue::Block { open: '(', contents: vec![ident("a"), ComponentValue::Token(CSSToken::CloseSquare)] }]);
+	}
+
+	#[test]
+	fn unclosed_blocks() {
+		let (values, spans, unclosed) = parse_component_values_spanned("a { b: c(d");
+		assert_eq!(serialize(&values), "a { b: c(d)}");
+		assert_eq!(unclosed.iter().map(|(open, span)| (*open, span.start.column)).collect::<Vec<(char, usize)>>(), [('(', 8), ('{', 3)]);
+		assert_eq!((spans[2].span.start.offset, spans[2].span.end.offset), (2, 10));
+	}
+
+	#[test]
+	fn rule_lists() {
+		let rules = parse_stylesheet("<!-- @charset \"x\"; a, b { c: d } --> @media print { e {} } f");
+		assert_eq!(rules.len(), 3);
+		match &rules[0] {
+			Rule::At(rule) => assert_eq!((rule.name.as_str(), serialize(trim(&rule.prelude)).as_str(), rule.block.is_none()), ("charset", "\"x\"", true)),
+			rule => panic!("{:?}", rule)
+		}
+		match &rules[1] {
+			Rule::Qualified(rule) => assert_eq!((serialize(trim(&rule.prelude)), serialize(&rule.block)), (String::from("a, b"), String::from(" c: d "))),
+			rule => panic!("{:?}", rule)
+		}
+		match &rules[2] {
+			Rule::At(rule) => assert_eq!((rule.name.as_str(), serialize(rule.block.as_ref().unwrap()).as_str()), ("media", " e {} ")),
+			rule => panic!("{:?}", rule)
+		}
+	}
+
+	#[test]
+	fn declaration_lists() {
+		assert_eq!(declarations_of("COLOR : red ; margin:0 auto!IMPORTANT;;--Gap: { a: b } ; padding: 1px ! important"), [
+			(String::from("color"), String::from("red"), false),
+			(String::from("margin"), String::from("0 auto"), true),
+			(String::from("--Gap"), String::from("{ a: b }"), false),
+			(String::from("padding"), String::from("1px"), true)
+		]);
+		assert_eq!(declarations_of("a; b: c; 1px: d; : e; f: g"), [(String::from("b"), String::from("c"), false), (String::from("f"), String::from("g"), false)]);
+		assert!(matches!(&parse_declaration_list(&parse_component_values("a: b; .c { d: e } @media print {}"))[..], [
+			DeclarationListItem::Declaration(_), DeclarationListItem::Rule(_), DeclarationListItem::At(_)
+		]));
+	}
+
+	/// The selector of every style rule of `css`, nested ones resolved, in cascade order.
+	fn selectors(css: &str) -> Vec<String> {
+		create_stylesheet(css).blocks(&MediaEnvironment::screen(800.0, 600.0, 1.0)).iter().map(|block| block.selector.clone()).collect()
+	}
+
+	#[test]
+	fn nesting() {
+		assert_eq!(selectors(".card { & .title {} .body {} > p {} + & {} }"), [".card", ".card .title", ".card .body", ".card > p", ".card + .card"]);
+		assert_eq!(selectors(".a { &.b {} .x& {} :not(&) {} &:hover, & & {} }"), [".a", ".a.b", ".x.a", ":not(.a)", ".a:hover, .a .a"]);
+		assert_eq!(selectors("p { .x& {} &.y {} }"), ["p", ".x:is(p)", "p.y"]);
+		assert_eq!(selectors(".a, .b { > p {} & {} }"), [".a, .b", ":is(.a, .b) > p", ":is(.a, .b)"]);
+		assert_eq!(selectors(".a .b { .c & {} }"), [".a .b", ".c :is(.a .b)"]);
+		assert_eq!(selectors(".a { .b { & .c, .d {} } }"), [".a", ".a .b", ":is(.a .b) .c, :is(.a .b) .d"]);
+		assert_eq!(selectors(".a { &, & {} }"), [".a", ".a"]);
+	}
+
+	#[test]
+	fn nested_declarations() {
+		let blocks = create_stylesheet(".a { color: red; .b { color: blue } margin: 0; --x: { y }; }").blocks(&MediaEnvironment::screen(800.0, 600.0, 1.0));
+		let names = |block: &CSSBlock| block.rules.iter().map(|rule| rule.name.clone()).collect::<Vec<String>>();
+		assert_eq!((names(&blocks[0]), names(&blocks[1])), (vec![String::from("color"), String::from("margin"), String::from("--x")], vec![String::from("color")]));
+	}
+
+	#[test]
+	fn layered_blocks() {
+		let css = "@layer b, a; p {} @layer a { q {} @layer x { r {} } } @layer { s {} } @layer b.c { @media print { t {} } u {} }";
+		let mut layers = LayerOrder::new();
+		let blocks: Vec<(Option<String>, String)> = create_stylesheet(css).layered_blocks(&MediaEnvironment::screen(800.0, 600.0, 1.0), &mut layers).into_iter()
+			.map(|(layer, block)| (layers.name(layer).map(String::from), block.selector.clone())).collect();
+		assert_eq!(blocks, [
+			(None, String::from("p")),
+			(Some(String::from("a")), String::from("q")),
+			(Some(String::from("a.x")), String::from("r")),
+			(None, String::from("s")),
+			(Some(String::from("b.c")), String::from("u"))
+		]);
+		assert_ne!(create_stylesheet(css).layered_blocks(&MediaEnvironment::screen(800.0, 600.0, 1.0), &mut LayerOrder::new())[3].0, LayerOrder::UNLAYERED);
+	}
+
+	#[test]
+	fn imports_only_at_the_start() {
+		let stylesheet = create_stylesheet("@charset \"utf-8\"; @layer a; @import 'a.css'; a {} @import 'b.css';");
+		let imports: Vec<&str> = stylesheet.statements.iter().filter_map(|statement| match statement {
+			CSSStatement::Import(rule) => Some(rule.url.as_str()),
+			_ => None
+		}).collect();
+		assert_eq!(imports, ["a.css"]);
+	}
+}
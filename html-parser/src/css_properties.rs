@@ -0,0 +1,224 @@
+use super::css_calc::CalcType;
+use super::css_color;
+use super::css_parse::CSSRule;
+use super::css_shorthand;
+use super::css_value::CSSValue;
+use super::css_variables;
+use std::rc::Rc;
+
+/// A single value a property accepts.
+#[derive(Debug, Clone, Copy)]
+pub enum Term {
+	Length,
+	Percentage,
+	Number,
+	Integer,
+	Color,
+	Image,
+	String,
+	Keywords(&'static [&'static str])
+}
+impl Term {
+	fn matches(&self, value: &CSSValue) -> bool {
+		match (self, value) {
+			(Term::Length, CSSValue::Length(..)) => true,
+			(Term::Length, CSSValue::Number(value)) => *value == 0.0,
+			(Term::Length, CSSValue::Calc(calc)) | (Term::Percentage, CSSValue::Calc(calc)) => calc.kind() == CalcType::Length,
+			(Term::Percentage, CSSValue::Percentage(_)) => true,
+			(Term::Number, CSSValue::Number(_)) => true,
+			(Term::Number, CSSValue::Calc(calc)) => calc.kind() == CalcType::Number,
+			(Term::Integer, CSSValue::Number(value)) => value.fract() == 0.0,
+			(Term::Color, value) if css_color::from_value(value).is_some() => true,
+			(Term::Image, CSSValue::Url(_)) => true,
+			(Term::Image, CSSValue::Function(name, _)) => name.ends_with("gradient") || name == "image-set",
+			(Term::String, CSSValue::String(_)) => true,
+			(Term::Keywords(keywords), CSSValue::Keyword(keyword)) => keywords.contains(&keyword.as_str()),
+			_ => false
+		}
+	}
+}
+
+/// The grammar of a property's value, as far as it is checked.
+#[derive(Debug, Clone, Copy)]
+pub enum Syntax {
+	/// One value matching one of the terms.
+	One(&'static [Term]),
+	/// Between one and the given number of values, each matching one of the terms.
+	Many(&'static [Term], usize),
+	/// A comma separated list, each item being like `Many`.
+	List(&'static [Term], usize),
+	/// Anything, for properties whose grammar isn't checked.
+	Any
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Property {
+	pub name: &'static str,
+	pub inherited: bool,
+	pub initial: &'static str,
+	pub syntax: Syntax
+}
+
+const LENGTH_PERCENTAGE: &[Term] = &[Term::Length, Term::Percentage];
+const SIZE: &[Term] = &[Term::Length, Term::Percentage, Term::Keywords(&["auto", "min-content", "max-content", "fit-content"])];
+const MAX_SIZE: &[Term] = &[Term::Length, Term::Percentage, Term::Keywords(&["none", "min-content", "max-content", "fit-content"])];
+const MARGIN: &[Term] = &[Term::Length, Term::Percentage, Term::Keywords(&["auto"])];
+const LINE_WIDTH: &[Term] = &[Term::Length, Term::Keywords(&["thin", "medium", "thick"])];
+const LINE_STYLE: &[Term] = &[Term::Keywords(&["none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"])];
+const COLOR: &[Term] = &[Term::Color];
+const IMAGE: &[Term] = &[Term::Image, Term::Keywords(&["none"])];
+const ALIGNMENT: &[Term] = &[Term::Keywords(&["normal", "stretch", "center", "start", "end", "flex-start", "flex-end", "self-start", "self-end", "baseline", "first", "last", "safe", "unsafe", "left", "right", "auto"])];
+const DISTRIBUTION: &[Term] = &[Term::Keywords(&["normal", "center", "start", "end", "flex-start", "flex-end", "left", "right", "space-between", "space-around", "space-evenly", "stretch", "safe", "unsafe", "baseline", "first", "last"])];
+const GAP: &[Term] = &[Term::Length, Term::Percentage, Term::Keywords(&["normal"])];
+const OVERFLOW: &[Term] = &[Term::Keywords(&["visible", "hidden", "clip", "scroll", "auto"])];
+
+const fn property(name: &'static str, inherited: bool, initial: &'static str, syntax: Syntax) -> Property {
+	Property { name, inherited, initial, syntax }
+}
+
+/// The longhand properties the parser knows. Shorthands are listed in `css_shorthand`.
+pub const PROPERTIES: &[Property] = &[
+	property("width", false, "auto", Syntax::One(SIZE)),
+	property("height", false, "auto", Syntax::One(SIZE)),
+	property("min-width", false, "auto", Syntax::One(SIZE)),
+	property("min-height", false, "auto", Syntax::One(SIZE)),
+	property("max-width", false, "none", Syntax::One(MAX_SIZE)),
+	property("max-height", false, "none", Syntax::One(MAX_SIZE)),
+	property("margin-top", false, "0", Syntax::One(MARGIN)),
+	property("margin-right", false, "0", Syntax::One(MARGIN)),
+	property("margin-bottom", false, "0", Syntax::One(MARGIN)),
+	property("margin-left", false, "0", Syntax::One(MARGIN)),
+	property("padding-top", false, "0", Syntax::One(LENGTH_PERCENTAGE)),
+	property("padding-right", false, "0", Syntax::One(LENGTH_PERCENTAGE)),
+	property("padding-bottom", false, "0", Syntax::One(LENGTH_PERCENTAGE)),
+	property("padding-left", false, "0", Syntax::One(LENGTH_PERCENTAGE)),
+	property("top", false, "auto", Syntax::One(MARGIN)),
+	property("right", false, "auto", Syntax::One(MARGIN)),
+	property("bottom", false, "auto", Syntax::One(MARGIN)),
+	property("left", false, "auto", Syntax::One(MARGIN)),
+	property("border-top-width", false, "medium", Syntax::One(LINE_WIDTH)),
+	property("border-right-width", false, "medium", Syntax::One(LINE_WIDTH)),
+	property("border-bottom-width", false, "medium", Syntax::One(LINE_WIDTH)),
+	property("border-left-width", false, "medium", Syntax::One(LINE_WIDTH)),
+	property("border-top-style", false, "none", Syntax::One(LINE_STYLE)),
+	property("border-right-style", false, "none", Syntax::One(LINE_STYLE)),
+	property("border-bottom-style", false, "none", Syntax::One(LINE_STYLE)),
+	property("border-left-style", false, "none", Syntax::One(LINE_STYLE)),
+	property("border-top-color", false, "currentcolor", Syntax::One(COLOR)),
+	property("border-right-color", false, "currentcolor", Syntax::One(COLOR)),
+	property("border-bottom-color", false, "currentcolor", Syntax::One(COLOR)),
+	property("border-left-color", false, "currentcolor", Syntax::One(COLOR)),
+	property("border-top-left-radius", false, "0", Syntax::Many(LENGTH_PERCENTAGE, 2)),
+	property("border-top-right-radius", false, "0", Syntax::Many(LENGTH_PERCENTAGE, 2)),
+	property("border-bottom-right-radius", false, "0", Syntax::Many(LENGTH_PERCENTAGE, 2)),
+	property("border-bottom-left-radius", false, "0", Syntax::Many(LENGTH_PERCENTAGE, 2)),
+	property("border-image-source", false, "none", Syntax::One(IMAGE)),
+	property("border-image-slice", false, "100%", Syntax::Any),
+	property("border-image-width", false, "1", Syntax::Any),
+	property("border-image-outset", false, "0", Syntax::Any),
+	property("border-image-repeat", false, "stretch", Syntax::Many(&[Term::Keywords(&["stretch", "repeat", "round", "space"])], 2)),
+	property("border-collapse", true, "separate", Syntax::One(&[Term::Keywords(&["separate", "collapse"])])),
+	property("border-spacing", true, "0", Syntax::Many(&[Term::Length], 2)),
+	property("outline-width", false, "medium", Syntax::One(LINE_WIDTH)),
+	property("outline-style", false, "none", Syntax::One(&[Term::Keywords(&["auto", "none", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"])])),
+	property("outline-color", false, "currentcolor", Syntax::One(COLOR)),
+	property("outline-offset", false, "0", Syntax::One(&[Term::Length])),
+	property("color", true, "canvastext", Syntax::One(COLOR)),
+	property("opacity", false, "1", Syntax::One(&[Term::Number, Term::Percentage])),
+	property("background-color", false, "transparent", Syntax::One(COLOR)),
+	property("background-image", false, "none", Syntax::List(IMAGE, 1)),
+	property("background-position", false, "0% 0%", Syntax::List(&[Term::Length, Term::Percentage, Term::Keywords(&["left", "center", "right", "top", "bottom"])], 4)),
+	property("background-size", false, "auto", Syntax::List(&[Term::Length, Term::Percentage, Term::Keywords(&["auto", "cover", "contain"])], 2)),
+	property("background-repeat", false, "repeat", Syntax::List(&[Term::Keywords(&["repeat-x", "repeat-y", "repeat", "space", "round", "no-repeat"])], 2)),
+	property("background-attachment", false, "scroll", Syntax::List(&[Term::Keywords(&["scroll", "fixed", "local"])], 1)),
+	property("background-origin", false, "padding-box", Syntax::List(&[Term::Keywords(&["border-box", "padding-box", "content-box"])], 1)),
+	property("background-clip", false, "border-box", Syntax::List(&[Term::Keywords(&["border-box", "padding-box", "content-box", "text"])], 1)),
+	property("font-style", true, "normal", Syntax::Many(&[Term::Keywords(&["normal", "italic", "oblique"]), Term::Length], 2)),
+	property("font-variant", true, "normal", Syntax::Any),
+	property("font-weight", true, "normal", Syntax::One(&[Term::Number, Term::Keywords(&["normal", "bold", "bolder", "lighter"])])),
+	property("font-stretch", true, "normal", Syntax::One(&[Term::Percentage, Term::Keywords(&["normal", "ultra-condensed", "extra-condensed", "condensed", "semi-condensed", "semi-expanded", "expanded", "extra-expanded", "ultra-expanded"])])),
+	property("font-size", true, "medium", Syntax::One(&[Term::Length, Term::Percentage, Term::Keywords(&["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "xxx-large", "larger", "smaller"])])),
+	property("font-family", true, "serif", Syntax::Any),
+	property("line-height", true, "normal", Syntax::One(&[Term::Number, Term::Length, Term::Percentage, Term::Keywords(&["normal"])])),
+	property("letter-spacing", true, "normal", Syntax::One(&[Term::Length, Term::Keywords(&["normal"])])),
+	property("word-spacing", true, "normal", Syntax::One(&[Term::Length, Term::Percentage, Term::Keywords(&["normal"])])),
+	property("text-align", true, "start", Syntax::One(&[Term::Keywords(&["start", "end", "left", "right", "center", "justify", "match-parent"])])),
+	property("text-indent", true, "0", Syntax::One(LENGTH_PERCENTAGE)),
+	property("text-transform", true, "none", Syntax::One(&[Term::Keywords(&["none", "capitalize", "uppercase", "lowercase", "full-width"])])),
+	property("text-decoration", false, "none", Syntax::Any),
+	property("text-shadow", true, "none", Syntax::Any),
+	property("white-space", true, "normal", Syntax::One(&[Term::Keywords(&["normal", "pre", "nowrap", "pre-wrap", "pre-line", "break-spaces"])])),
+	property("vertical-align", false, "baseline", Syntax::One(&[Term::Length, Term::Percentage, Term::Keywords(&["baseline", "sub", "super", "text-top", "text-bottom", "middle", "top", "bottom"])])),
+	property("display", false, "inline", Syntax::Many(&[Term::Keywords(&["none", "contents", "block", "inline", "inline-block", "flow", "flow-root", "flex", "inline-flex", "grid", "inline-grid", "table", "inline-table", "table-row", "table-cell", "table-column", "table-row-group", "table-header-group", "table-footer-group", "table-column-group", "table-caption", "list-item", "run-in", "ruby"])], 3)),
+	property("position", false, "static", Syntax::One(&[Term::Keywords(&["static", "relative", "absolute", "fixed", "sticky"])])),
+	property("float", false, "none", Syntax::One(&[Term::Keywords(&["none", "left", "right", "inline-start", "inline-end"])])),
+	property("clear", false, "none", Syntax::One(&[Term::Keywords(&["none", "left", "right", "both", "inline-start", "inline-end"])])),
+	property("visibility", true, "visible", Syntax::One(&[Term::Keywords(&["visible", "hidden", "collapse"])])),
+	property("z-index", false, "auto", Syntax::One(&[Term::Integer, Term::Keywords(&["auto"])])),
+	property("overflow-x", false, "visible", Syntax::One(OVERFLOW)),
+	property("overflow-y", false, "visible", Syntax::One(OVERFLOW)),
+	property("box-sizing", false, "content-box", Syntax::One(&[Term::Keywords(&["content-box", "border-box"])])),
+	property("flex-grow", false, "0", Syntax::One(&[Term::Number])),
+	property("flex-shrink", false, "1", Syntax::One(&[Term::Number])),
+	property("flex-basis", false, "auto", Syntax::One(&[Term::Length, Term::Percentage, Term::Keywords(&["auto", "content", "min-content", "max-content", "fit-content"])])),
+	property("flex-direction", false, "row", Syntax::One(&[Term::Keywords(&["row", "row-reverse", "column", "column-reverse"])])),
+	property("flex-wrap", false, "nowrap", Syntax::One(&[Term::Keywords(&["nowrap", "wrap", "wrap-reverse"])])),
+	property("order", false, "0", Syntax::One(&[Term::Integer])),
+	property("justify-content", false, "normal", Syntax::Many(DISTRIBUTION, 2)),
+	property("align-content", false, "normal", Syntax::Many(DISTRIBUTION, 2)),
+	property("align-items", false, "normal", Syntax::Many(ALIGNMENT, 2)),
+	property("align-self", false, "auto", Syntax::Many(ALIGNMENT, 2)),
+	property("justify-items", false, "legacy", Syntax::Any),
+	property("justify-self", false, "auto", Syntax::Many(ALIGNMENT, 2)),
+	property("row-gap", false, "normal", Syntax::One(GAP)),
+	property("column-gap", false, "normal", Syntax::One(GAP)),
+	property("grid-template-columns", false, "none", Syntax::Any),
+	property("grid-template-rows", false, "none", Syntax::Any),
+	property("grid-template-areas", false, "none", Syntax::Any),
+	property("grid-column", false, "auto", Syntax::Any),
+	property("grid-row", false, "auto", Syntax::Any),
+	property("grid-area", false, "auto", Syntax::Any),
+	property("list-style-position", true, "outside", Syntax::One(&[Term::Keywords(&["inside", "outside"])])),
+	property("list-style-image", true, "none", Syntax::One(IMAGE)),
+	property("list-style-type", true, "disc", Syntax::One(&[Term::String, Term::Keywords(&["none", "disc", "circle", "square", "decimal", "decimal-leading-zero", "lower-roman", "upper-roman", "lower-alpha", "upper-alpha", "lower-latin", "upper-latin", "lower-greek"])])),
+	property("content", false, "normal", Syntax::Any),
+	property("cursor", true, "auto", Syntax::Any),
+	property("pointer-events", true, "auto", Syntax::One(&[Term::Keywords(&["auto", "none"])])),
+	property("user-select", false, "auto", Syntax::One(&[Term::Keywords(&["auto", "text", "none", "contain", "all"])])),
+	property("object-fit", false, "fill", Syntax::One(&[Term::Keywords(&["fill", "contain", "cover", "none", "scale-down"])])),
+	property("box-shadow", false, "none", Syntax::Any),
+	property("transform", false, "none", Syntax::Any),
+	property("transition", false, "all 0s ease 0s", Syntax::Any),
+	property("animation", false, "none", Syntax::Any),
+	property("filter", false, "none", Syntax::Any)
+];
+
+pub fn property_named(name: &str) -> Option<&'static Property> {
+	PROPERTIES.iter().find(|property| property.name == name)
+}
+
+/// Whether `name` is a longhand, a shorthand or a custom property.
+pub fn is_known(name: &str) -> bool {
+	property_named(name).is_some() || css_shorthand::is_shorthand(name) || css_variables::is_custom_property(name)
+}
+
+fn matches_all(values: &[CSSValue], terms: &[Term], max: usize) -> bool {
+	!values.is_empty() && values.len() <= max && values.iter().all(|value| terms.iter().any(|term| term.matches(value)))
+}
+
+/// Whether the value of a declaration fits its property's grammar. Values using `var()` and the CSS-wide keywords are
+/// always valid, as are declarations of unknown properties.
+pub fn is_valid(rule: &Rc<CSSRule>) -> bool {
+	if css_variables::is_custom_property(&rule.name) || css_variables::contains_var(&rule.components) { return true; }
+	if let [CSSValue::Keyword(keyword)] = &rule.values[..] {
+		if ["inherit", "initial", "unset", "revert", "revert-layer"].contains(&keyword.as_str()) { return true; }
+	}
+	if css_shorthand::is_shorthand(&rule.name) { return !css_shorthand::expand(rule).is_empty(); }
+
+	match property_named(&rule.name).map(|property| property.syntax) {
+		Some(Syntax::One(terms)) => matches_all(&rule.values, terms, 1),
+		Some(Syntax::Many(terms, max)) => matches_all(&rule.values, terms, max),
+		Some(Syntax::List(terms, max)) => rule.values.split(|value| *value == CSSValue::Comma).all(|item| matches_all(item, terms, max)),
+		Some(Syntax::Any) | None => true
+	}
+}
@@ -0,0 +1,339 @@
+use super::css_font_face::FontFaceRule;
+use super::css_parse::{self, ComponentValue, CSSBlock, CSSRule, CSSStatement, CSSStyleSheet, ImportRule, LayerRule, MediaRule, SupportsRule, trim};
+use super::css_tokenize::{self, CSSToken};
+use super::css_variables;
+
+/// How stylesheets are written back. `Pretty` puts each declaration on its own line, indented with tabs, `Minified`
+/// leaves out every character that isn't needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	Pretty,
+	Minified
+}
+
+/// Writes component values with whitespace collapsed to single spaces and none before commas or inside brackets.
+/// Minified values also drop the space after commas.
+pub fn format_values(values: &[ComponentValue], format: Format) -> String {
+	let mut formatted = String::new();
+	let mut space = false;
+	let mut after_separator = false;
+	for value in trim(values) {
+		match value {
+			ComponentValue::Token(CSSToken::Whitespace) => {
+				space = true;
+				continue;
+			},
+			ComponentValue::Token(CSSToken::Comma) => {
+				formatted.push_str(if format == Format::Pretty { ", " } else { "," });
+				space = false;
+				after_separator = true;
+				continue;
+			},
+			_ => {}
+		}
+
+		if space && !after_separator {
+			formatted.push(' ');
+		}
+		formatted.push_str(&match value {
+			ComponentValue::Function { name, arguments } => format!("{}({})", css_tokenize::serialize_identifier(name), format_values(arguments, format)),
+			ComponentValue::Block { open, contents } => {
+				let close = match open { '{' => '}', '[' => ']', _ => ')' };
+				format!("{}{}{}", open, format_values(contents, format), close)
+			},
+			value => value.to_css()
+		});
+		space = false;
+		after_separator = false;
+	}
+
+	formatted
+}
+
+/// Like `format_values`, with one space around the `>`, `+` and `~` combinators, or none when minified.
+fn format_selector(selector: &str, format: Format) -> String {
+	let values = css_parse::parse_component_values(selector);
+	let is_combinator = |value: Option<&ComponentValue>| matches!(value, Some(ComponentValue::Token(CSSToken::Delim('>' | '+' | '~'))));
+
+	let mut normalized: Vec<ComponentValue> = vec![];
+	for (idx, value) in values.iter().enumerate() {
+		if value.is_whitespace() && (is_combinator(values.get(idx + 1)) || (idx > 0 && is_combinator(values.get(idx - 1)))) { continue; }
+
+		let combinator = is_combinator(Some(value));
+		if combinator && format == Format::Pretty { normalized.push(ComponentValue::Token(CSSToken::Whitespace)); }
+		normalized.push(value.clone());
+		if combinator && format == Format::Pretty { normalized.push(ComponentValue::Token(CSSToken::Whitespace)); }
+	}
+
+	format_values(&normalized, format)
+}
+
+/// Rewrites the `name: value` of media features with no space before the colon, and one after it unless minified.
+fn normalize_features(values: &[ComponentValue], format: Format) -> Vec<ComponentValue> {
+	let mut normalized: Vec<ComponentValue> = vec![];
+	for (idx, value) in values.iter().enumerate() {
+		match value {
+			ComponentValue::Block { open: '(', contents } => normalized.push(ComponentValue::Block { open: '(', contents: normalize_features(contents, format) }),
+			ComponentValue::Token(CSSToken::Colon) => {
+				while normalized.last().is_some_and(|value| value.is_whitespace()) { normalized.pop(); }
+				normalized.push(value.clone());
+				if format == Format::Pretty { normalized.push(ComponentValue::Token(CSSToken::Whitespace)); }
+			},
+			value if value.is_whitespace() && idx > 0 && values[idx - 1] == ComponentValue::Token(CSSToken::Colon) => {},
+			value => normalized.push(value.clone())
+		}
+	}
+
+	normalized
+}
+
+pub fn format_media_queries(values: &[ComponentValue], format: Format) -> String {
+	format_values(&normalize_features(values, format), format)
+}
+
+fn indent(text: &str) -> String {
+	text.lines().map(|line| if line.is_empty() { String::new() } else { format!("\t{}", line) }).collect::<Vec<String>>().join("\n")
+}
+
+/// A block of declarations or of rules, `{}` when there are none. Pretty rules are separated by blank lines.
+fn block(head: &str, items: &[String], format: Format, declarations: bool) -> String {
+	match format {
+		Format::Pretty if items.is_empty() => format!("{} {{}}", head),
+		Format::Pretty => format!("{} {{\n{}\n}}", head, indent(&items.join(if declarations { "\n" } else { "\n\n" }))),
+		Format::Minified => format!("{}{{{}}}", head, items.join(if declarations { ";" } else { "" }))
+	}
+}
+
+impl CSSRule {
+	/// The declaration without its trailing `;`. Custom property values are kept as written.
+	pub fn to_css(&self, format: Format) -> String {
+		let value = if css_variables::is_custom_property(&self.name) { self.value.clone() } else { format_values(&self.components, format) };
+		let name = css_tokenize::serialize_identifier(&self.name);
+		match (format, self.important) {
+			(Format::Pretty, false) => format!("{}: {}", name, value),
+			(Format::Pretty, true) => format!("{}: {} !important", name, value),
+			(Format::Minified, false) => format!("{}:{}", name, value),
+			(Format::Minified, true) => format!("{}:{}!important", name, value)
+		}
+	}
+}
+
+impl CSSBlock {
+	/// The rule, followed by its nested rules as separate rules with their resolved selectors, and nested `@media` and
+	/// `@supports` rules around a rule with this selector.
+	pub fn to_css(&self, format: Format) -> String {
+		let declarations: Vec<String> = self.rules.iter().map(|rule| match format {
+			Format::Pretty => format!("{};", rule.to_css(format)),
+			Format::Minified => rule.to_css(format)
+		}).collect();
+
+		let mut rules = vec![block(&format_selector(&self.selector, format), &declarations, format, true)];
+		rules.extend(statements(&self.children, format));
+		rules.join(if format == Format::Pretty { "\n\n" } else { "" })
+	}
+}
+
+impl MediaRule {
+	pub fn to_css(&self, format: Format) -> String {
+		let queries = self.queries.to_css(format);
+		let head = if queries.is_empty() { String::from("@media") } else { format!("@media {}", queries) };
+		block(&head, &statements(&self.statements, format), format, false)
+	}
+}
+
+impl FontFaceRule {
+	pub fn to_css(&self, format: Format) -> String {
+		let declarations: Vec<String> = self.declarations.iter().map(|rule| match format {
+			Format::Pretty => format!("{};", rule.to_css(format)),
+			Format::Minified => rule.to_css(format)
+		}).collect();
+		block("@font-face", &declarations, format, true)
+	}
+}
+
+impl SupportsRule {
+	pub fn to_css(&self, format: Format) -> String {
+		block(&format!("@supports {}", self.query.to_css(format)), &statements(&self.statements, format), format, false)
+	}
+}
+
+impl LayerRule {
+	pub fn to_css(&self, format: Format) -> String {
+		let names = self.names.join(if format == Format::Pretty { ", " } else { "," });
+		let head = if names.is_empty() { String::from("@layer") } else { format!("@layer {}", names) };
+		match &self.statements {
+			Some(rules) => block(&head, &statements(rules, format), format, false),
+			None => format!("{};", head)
+		}
+	}
+}
+
+impl ImportRule {
+	/// The `@import` itself, not the stylesheet it loaded.
+	pub fn to_css(&self, format: Format) -> String {
+		let queries = self.queries.to_css(format);
+		let url = css_tokenize::serialize_string(&self.url);
+		if queries.is_empty() {
+			format!("@import {};", url)
+		} else {
+			format!("@import {} {};", url, queries)
+		}
+	}
+}
+
+fn statements(statements: &[CSSStatement], format: Format) -> Vec<String> {
+	statements.iter().map(|statement| match statement {
+		CSSStatement::Style(block) => block.to_css(format),
+		CSSStatement::Media(rule) => rule.to_css(format),
+		CSSStatement::Import(rule) => rule.to_css(format),
+		CSSStatement::FontFace(rule) => rule.to_css(format),
+		CSSStatement::Layer(rule) => rule.to_css(format),
+		CSSStatement::Supports(rule) => rule.to_css(format)
+	}).collect()
+}
+
+impl CSSStyleSheet {
+	/// Writes the stylesheet back. Rules the parser dropped, like unsupported at-rules, are not written, and neither
+	/// are the stylesheets loaded for `@import` rules.
+	pub fn to_css(&self, format: Format) -> String {
+		match format {
+			Format::Pretty => {
+				let statements = statements(&self.statements, format);
+				if statements.is_empty() { String::new() } else { format!("{}\n", statements.join("\n\n")) }
+			},
+			Format::Minified => statements(&self.statements, format).concat()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Format;
+	use super::super::css_parse::create_stylesheet;
+	use super::super::media_query::MediaEnvironment;
+	use super::super::selector::Selector;
+	use std::str::FromStr;
+
+	const STYLESHEET: &str = r#"@charset "utf-8";
+@layer reset, base;
+@import url("print.css") print and (min-width: 100px);
+@import 'theme.css' layer(theme) supports(display: grid);
+
+/* comment */
+html , body{margin:0 auto;color:RED!important}
+.a\:hover, #b\31 2 > p ~ [data-x="a \"quoted\" b"] { content: "\2014 \"" ; --custom :  { a: b } ; }
+
+@media screen and (max-width:600px) , print {
+	.c { width: calc( 100% - 2 * 10px ) }
+	@media (min-width: 300px) { .d { height: 10px } }
+}
+
+@font-face {
+	font-family: "My Font";
+	src: url(fonts/my.ttf) format("truetype"), local(My Font);
+	font-weight: 300 700;
+	unicode-range: U+0-7F, U+4??;
+}
+
+@layer base {
+	p { margin: 1px 2px }
+	@layer deep { em { color: rgb(0 0 0 / 50%) } }
+}
+@layer { b { color: blue } }
+
+@supports (display: grid) and (not (display: foo)) {
+	.grid { display: grid }
+}
+@supports selector(:has(> img)) or (color: red) { img { width: 1px } }
+
+.parent {
+	color: red;
+	& > .child { color: blue; .grand & { color: green } }
+	.sibling + & { color: black }
+	&:hover { color: white }
+	@media (min-width: 300px) { color: gray; .wide & { color: silver } }
+}
+"#;
+
+	/// Serializing, parsing the result and serializing again gives the same text.
+	fn assert_stable(css: &str, format: Format) -> String {
+		let first = create_stylesheet(css).to_css(format);
+		let second = create_stylesheet(&first).to_css(format);
+		assert_eq!(first, second, "unstable {:?} round trip of {:?}", format, css);
+		first
+	}
+
+	#[test]
+	fn round_trip_is_stable() {
+		for format in [Format::Pretty, Format::Minified] {
+			assert_stable(STYLESHEET, format);
+		}
+	}
+
+	#[test]
+	fn formats_agree() {
+		let pretty = create_stylesheet(STYLESHEET).to_css(Format::Pretty);
+		let minified = create_stylesheet(STYLESHEET).to_css(Format::Minified);
+		assert_eq!(create_stylesheet(&pretty).to_css(Format::Minified), minified);
+		assert_eq!(create_stylesheet(&minified).to_css(Format::Pretty), pretty);
+	}
+
+	#[test]
+	fn round_trip_keeps_the_rules() {
+		let environment = MediaEnvironment::screen(500.0, 500.0, 1.0);
+		let original = create_stylesheet(STYLESHEET);
+		for format in [Format::Pretty, Format::Minified] {
+			let parsed = create_stylesheet(&original.to_css(format));
+
+			let selectors = |stylesheet: &super::CSSStyleSheet| stylesheet.blocks(&environment).iter().map(|block| Selector::from_str(&block.selector).ok()).collect::<Vec<Option<Selector>>>();
+			assert_eq!(selectors(&parsed), selectors(&original));
+			let declarations = |stylesheet: &super::CSSStyleSheet| stylesheet.blocks(&environment).iter().flat_map(|block| block.rules.iter().map(|rule| rule.to_css(Format::Minified)).collect::<Vec<String>>()).collect::<Vec<String>>();
+			assert_eq!(declarations(&parsed), declarations(&original));
+
+			let families: Vec<String> = parsed.font_faces(&environment).into_iter().map(|face| face.family).collect();
+			assert_eq!(families, vec![String::from("My Font")]);
+		}
+	}
+
+	#[test]
+	fn escapes_survive() {
+		for format in [Format::Pretty, Format::Minified] {
+			let css = assert_stable(r#".a\:b, #\31 x, [title="say \"hi\""] { content: "\"\\" }"#, format);
+			let stylesheet = create_stylesheet(&css);
+			let block = &stylesheet.blocks(&MediaEnvironment::screen(1.0, 1.0, 1.0))[0];
+			assert_eq!(block.rules[0].value, r#""\"\\""#);
+		}
+	}
+
+	#[test]
+	fn minified_output() {
+		assert_eq!(create_stylesheet("a , b { color : red ; margin : 0 auto !important }").to_css(Format::Minified), "a,b{color:red;margin:0 auto!important}");
+		assert_eq!(create_stylesheet("@media screen and (min-width : 10px) { a { color: red } }").to_css(Format::Minified), "@media screen and (min-width:10px){a{color:red}}");
+	}
+
+	#[test]
+	fn pretty_output() {
+		assert_eq!(create_stylesheet("a{color:red;margin:0}").to_css(Format::Pretty), "a {\n\tcolor: red;\n\tmargin: 0;\n}\n");
+		assert_eq!(create_stylesheet(".p { color: red; & .c { color: blue } }").to_css(Format::Pretty), ".p {\n\tcolor: red;\n}\n\n.p .c {\n\tcolor: blue;\n}\n");
+	}
+
+	#[test]
+	fn nesting_output() {
+		let minified = |css: &str| create_stylesheet(css).to_css(Format::Minified);
+		assert_eq!(minified("p { .x& { color: red } }"), "p{}.x:is(p){color:red}");
+		assert_eq!(minified(".a .b { .c& { color: red } }"), ".a .b{}.c:is(.a .b){color:red}");
+		assert_eq!(minified(".a, #b { & p { color: red } }"), ".a,#b{}:is(.a,#b) p{color:red}");
+		assert_eq!(minified(".a { &.b { color: red } }"), ".a{}.a.b{color:red}");
+		assert_eq!(minified(".a { + & { color: red } }"), ".a{}.a+.a{color:red}");
+		assert_eq!(minified(".a { color: blue; @media print { color: red; .b { color: green } } }"), ".a{color:blue}@media print{.a{color:red}.a .b{color:green}}");
+		assert_eq!(minified(".a { @media print { color: red } .b { color: green } }"), ".a{}@media print{.a{color:red}}.a .b{color:green}");
+	}
+
+	#[test]
+	fn nested_conditional_rules_cascade_in_source_order() {
+		let stylesheet = create_stylesheet(".a { color: blue; @media (min-width: 300px) { color: red } .b { color: green } @supports (display: grid) { color: gray } }");
+		let blocks = |width: f32| stylesheet.blocks(&MediaEnvironment::screen(width, 500.0, 1.0)).iter().map(|block| format!("{}{{{}}}", block.selector, block.rules.iter().map(|rule| rule.to_css(Format::Minified)).collect::<Vec<String>>().join(";"))).collect::<Vec<String>>();
+		assert_eq!(blocks(500.0), [".a{color:blue}", ".a{color:red}", ".a .b{color:green}", ".a{color:gray}"]);
+		assert_eq!(blocks(100.0), [".a{color:blue}", ".a .b{color:green}", ".a{color:gray}"]);
+	}
+}
@@ -0,0 +1,476 @@
+use std::rc::Rc;
+use super::css_calc::{CalcNode, CalcType};
+use super::css_color;
+use super::css_parse::{self, ComponentValue, CSSRule};
+use super::css_tokenize::CSSToken;
+use super::css_value::LengthUnit;
+use super::css_variables::contains_var;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+const CORNERS: [&str; 4] = ["top-left", "top-right", "bottom-right", "bottom-left"];
+const CSS_WIDE_KEYWORDS: [&str; 5] = ["inherit", "initial", "unset", "revert", "revert-layer"];
+const LINE_STYLES: [&str; 10] = ["none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"];
+const LINE_WIDTHS: [&str; 3] = ["thin", "medium", "thick"];
+
+/// Shorthand properties and the longhands they set, in the order they are expanded.
+pub fn longhands(name: &str) -> Option<Vec<String>> {
+	let per_side = |format: &dyn Fn(&str) -> String| Some(SIDES.iter().map(|side| format(side)).collect());
+	match name {
+		"margin" | "padding" => per_side(&|side| format!("{}-{}", name, side)),
+		"inset" => per_side(&|side| String::from(side)),
+		"border-width" | "border-style" | "border-color" => {
+			let part = &name["border-".len()..];
+			per_side(&|side| format!("border-{}-{}", side, part))
+		},
+		"border-top" | "border-right" | "border-bottom" | "border-left" | "outline" => Some(["width", "style", "color"].iter().map(|part| format!("{}-{}", name, part)).collect()),
+		"border" => {
+			let mut longhands: Vec<String> = ["width", "style", "color"].iter().flat_map(|part| SIDES.iter().map(move |side| format!("border-{}-{}", side, part))).collect();
+			longhands.extend(BORDER_IMAGE.iter().map(|(name, _)| String::from(*name)));
+			Some(longhands)
+		},
+		"border-radius" => Some(CORNERS.iter().map(|corner| format!("border-{}-radius", corner)).collect()),
+		"background" => Some(BACKGROUND.iter().map(|(name, _)| String::from(*name)).collect()),
+		"font" => Some(FONT.iter().map(|(name, _)| String::from(*name)).collect()),
+		"flex" => Some(vec![String::from("flex-grow"), String::from("flex-shrink"), String::from("flex-basis")]),
+		"list-style" => Some(vec![String::from("list-style-position"), String::from("list-style-image"), String::from("list-style-type")]),
+		"overflow" => Some(vec![String::from("overflow-x"), String::from("overflow-y")]),
+		"gap" => Some(vec![String::from("row-gap"), String::from("column-gap")]),
+		_ => None
+	}
+}
+
+const BORDER_IMAGE: [(&str, &str); 5] = [
+	("border-image-source", "none"),
+	("border-image-slice", "100%"),
+	("border-image-width", "1"),
+	("border-image-outset", "0"),
+	("border-image-repeat", "stretch")
+];
+
+const BACKGROUND: [(&str, &str); 8] = [
+	("background-image", "none"),
+	("background-position", "0% 0%"),
+	("background-size", "auto"),
+	("background-repeat", "repeat"),
+	("background-attachment", "scroll"),
+	("background-origin", "padding-box"),
+	("background-clip", "border-box"),
+	("background-color", "transparent")
+];
+
+const FONT: [(&str, &str); 7] = [
+	("font-style", "normal"),
+	("font-variant", "normal"),
+	("font-weight", "normal"),
+	("font-stretch", "normal"),
+	("font-size", "medium"),
+	("line-height", "normal"),
+	("font-family", "serif")
+];
+
+fn keyword(value: &ComponentValue) -> Option<String> {
+	match value {
+		ComponentValue::Token(CSSToken::Ident(ident)) => Some(ident.to_ascii_lowercase()),
+		_ => None
+	}
+}
+
+fn is_keyword(value: &ComponentValue, keywords: &[&str]) -> bool {
+	keyword(value).is_some_and(|keyword| keywords.contains(&keyword.as_str()))
+}
+
+fn is_function(value: &ComponentValue, names: &[&str]) -> bool {
+	matches!(value, ComponentValue::Function { name, .. } if names.iter().any(|expected| name.eq_ignore_ascii_case(expected)))
+}
+
+fn is_calc(value: &ComponentValue, kind: CalcType) -> bool {
+	CalcNode::parse(value).is_some_and(|calc| calc.kind() == kind)
+}
+
+fn is_number(value: &ComponentValue) -> bool {
+	matches!(value, ComponentValue::Token(CSSToken::Number { .. })) || is_calc(value, CalcType::Number)
+}
+
+fn is_length_percentage(value: &ComponentValue) -> bool {
+	match value {
+		ComponentValue::Token(CSSToken::Dimension { unit, .. }) => LengthUnit::from_unit(unit).is_some(),
+		ComponentValue::Token(CSSToken::Percentage { .. }) => true,
+		ComponentValue::Token(CSSToken::Number { value, .. }) => *value == 0.0,
+		value => is_calc(value, CalcType::Length)
+	}
+}
+
+fn is_color(value: &ComponentValue) -> bool {
+	css_color::parse(value).is_some()
+}
+
+fn is_image(value: &ComponentValue) -> bool {
+	match value {
+		ComponentValue::Token(CSSToken::Url(_)) => true,
+		ComponentValue::Function { name, .. } => name.eq_ignore_ascii_case("url") || name.to_ascii_lowercase().ends_with("gradient") || name.eq_ignore_ascii_case("image-set"),
+		_ => false
+	}
+}
+
+fn text(values: &[&ComponentValue]) -> String {
+	values.iter().map(|value| value.to_css()).collect::<Vec<String>>().join(" ")
+}
+
+/// Spreads one to four values over top, right, bottom and left.
+fn sides(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let values: Vec<String> = values.iter().map(|value| value.to_css()).collect();
+	let [top, right, bottom, left] = match &values[..] {
+		[all] => [all, all, all, all],
+		[vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+		[top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+		[top, right, bottom, left] => [top, right, bottom, left],
+		_ => return None
+	};
+
+	Some(vec![top.clone(), right.clone(), bottom.clone(), left.clone()])
+}
+
+/// Fills the slots of a `a || b || c` grammar, each value going to the first empty slot whose test accepts it.
+fn any_order(values: &[&ComponentValue], tests: &[&dyn Fn(&ComponentValue) -> bool]) -> Option<Vec<Option<String>>> {
+	let mut slots: Vec<Option<String>> = vec![None; tests.len()];
+	for value in values {
+		let slot = (0..tests.len()).find(|idx| slots[*idx].is_none() && tests[*idx](value))?;
+		slots[slot] = Some(value.to_css());
+	}
+
+	Some(slots)
+}
+
+fn border_side(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let width = |value: &ComponentValue| is_keyword(value, &LINE_WIDTHS) || is_length_percentage(value);
+	let style = |value: &ComponentValue| is_keyword(value, &LINE_STYLES);
+	let slots = any_order(values, &[&width, &style, &is_color])?;
+	let initial = ["medium", "none", "currentcolor"];
+
+	Some(slots.into_iter().zip(initial).map(|(slot, initial)| slot.unwrap_or_else(|| String::from(initial))).collect())
+}
+
+fn border_radius(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let (horizontal, vertical) = match values.iter().position(|value| **value == ComponentValue::Token(CSSToken::Delim('/'))) {
+		Some(slash) => (&values[..slash], Some(&values[slash + 1..])),
+		None => (values, None)
+	};
+	if !horizontal.iter().chain(vertical.unwrap_or(&[]).iter()).all(|value| is_length_percentage(value)) { return None; }
+
+	let horizontal = sides(horizontal)?;
+	Some(match vertical {
+		Some(vertical) => horizontal.iter().zip(sides(vertical)?).map(|(horizontal, vertical)| format!("{} {}", horizontal, vertical)).collect(),
+		None => horizontal
+	})
+}
+
+fn background_layer(values: &[&ComponentValue], last: bool) -> Option<Vec<String>> {
+	const REPEAT: [&str; 6] = ["repeat-x", "repeat-y", "repeat", "space", "round", "no-repeat"];
+	const ATTACHMENT: [&str; 3] = ["scroll", "fixed", "local"];
+	const BOXES: [&str; 3] = ["border-box", "padding-box", "content-box"];
+	const POSITIONS: [&str; 5] = ["left", "center", "right", "top", "bottom"];
+
+	let mut layer: Vec<Option<String>> = vec![None; BACKGROUND.len()];
+	let mut boxes: Vec<String> = vec![];
+	let mut idx = 0;
+	while idx < values.len() {
+		let value = values[idx];
+		let is_position = |value: &ComponentValue| is_keyword(value, &POSITIONS) || is_length_percentage(value);
+
+		if is_position(value) && layer[1].is_none() {
+			let end = values[idx..].iter().position(|value| !is_position(value)).map_or(values.len(), |end| idx + end);
+			if end - idx > 4 { return None; }
+			layer[1] = Some(text(&values[idx..end]));
+			idx = end;
+
+			if values.get(idx) == Some(&&ComponentValue::Token(CSSToken::Delim('/'))) {
+				let size = |value: &ComponentValue| is_length_percentage(value) || is_keyword(value, &["auto"]);
+				let end = match values.get(idx + 1) {
+					Some(value) if is_keyword(value, &["cover", "contain"]) => idx + 2,
+					_ => values[idx + 1..].iter().position(|value| !size(value)).map_or(values.len(), |end| idx + 1 + end)
+				};
+				if end == idx + 1 || end - idx - 1 > 2 { return None; }
+				layer[2] = Some(text(&values[idx + 1..end]));
+				idx = end;
+			}
+			continue;
+		}
+
+		let slot = match value {
+			value if is_image(value) || is_keyword(value, &["none"]) => 0,
+			value if is_keyword(value, &REPEAT) => 3,
+			value if is_keyword(value, &ATTACHMENT) => 4,
+			value if is_keyword(value, &BOXES) => {
+				if boxes.len() == 2 { return None; }
+				boxes.push(value.to_css());
+				idx += 1;
+				continue;
+			},
+			value if last && is_color(value) => 7,
+			_ => return None
+		};
+
+		// Two repeat keywords are allowed, `repeat-x` and `repeat-y` must stand alone.
+		if slot == 3 && layer[3].is_some() && !is_keyword(value, &["repeat-x", "repeat-y"]) && !layer[3].as_ref().is_some_and(|first| first.contains(' ') || first == "repeat-x" || first == "repeat-y") {
+			layer[3] = Some(format!("{} {}", layer[3].take().unwrap(), value.to_css()));
+			idx += 1;
+			continue;
+		}
+		if layer[slot].is_some() { return None; }
+		layer[slot] = Some(value.to_css());
+		idx += 1;
+	}
+
+	match &boxes[..] {
+		[both] => {
+			layer[5] = Some(both.clone());
+			layer[6] = Some(both.clone());
+		},
+		[origin, clip] => {
+			layer[5] = Some(origin.clone());
+			layer[6] = Some(clip.clone());
+		},
+		_ => {}
+	}
+
+	Some(layer.into_iter().zip(BACKGROUND.iter()).map(|(value, (_, initial))| value.unwrap_or_else(|| String::from(*initial))).collect())
+}
+
+fn background(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let layers: Vec<&[&ComponentValue]> = values.split(|value| **value == ComponentValue::Token(CSSToken::Comma)).collect();
+	let mut expanded: Vec<Vec<String>> = vec![];
+	for (idx, layer) in layers.iter().enumerate() {
+		expanded.push(background_layer(layer, idx == layers.len() - 1)?);
+	}
+
+	// Every longhand except the color is a comma separated list with one entry per layer.
+	Some((0..BACKGROUND.len()).map(|longhand| match longhand {
+		7 => expanded.last().unwrap()[7].clone(),
+		_ => expanded.iter().map(|layer| layer[longhand].clone()).collect::<Vec<String>>().join(", ")
+	}).collect())
+}
+
+fn font(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	const STYLES: [&str; 2] = ["italic", "oblique"];
+	const WEIGHTS: [&str; 4] = ["bold", "bolder", "lighter", "normal"];
+	const STRETCHES: [&str; 9] = ["ultra-condensed", "extra-condensed", "condensed", "semi-condensed", "semi-expanded", "expanded", "extra-expanded", "ultra-expanded", "normal"];
+	const SIZES: [&str; 10] = ["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large", "xxx-large", "larger", "smaller"];
+
+	let mut font: Vec<Option<String>> = vec![None; FONT.len()];
+	let mut idx = 0;
+
+	// Up to four of style, variant, weight and stretch, where `normal` fills whichever is still unset.
+	while idx < values.len() && idx < 4 {
+		let value = values[idx];
+		let slot = match value {
+			value if is_keyword(value, &["normal"]) => (0..4).find(|slot| font[*slot].is_none())?,
+			value if is_keyword(value, &STYLES) => 0,
+			value if is_keyword(value, &["small-caps"]) => 1,
+			value if is_keyword(value, &WEIGHTS) => 2,
+			ComponentValue::Token(CSSToken::Number { value, .. }) if (1.0..=1000.0).contains(value) => 2,
+			value if is_keyword(value, &STRETCHES) => 3,
+			_ => break
+		};
+
+		if font[slot].is_some() { return None; }
+		font[slot] = Some(value.to_css());
+		idx += 1;
+	}
+
+	let size = values.get(idx)?;
+	if !(is_keyword(size, &SIZES) || is_length_percentage(size)) { return None; }
+	font[4] = Some(size.to_css());
+	idx += 1;
+
+	if values.get(idx) == Some(&&ComponentValue::Token(CSSToken::Delim('/'))) {
+		let line_height = values.get(idx + 1)?;
+		if !(is_keyword(line_height, &["normal"]) || is_number(line_height) || is_length_percentage(line_height)) { return None; }
+		font[5] = Some(line_height.to_css());
+		idx += 2;
+	}
+
+	// The family list keeps its commas, `text` would put spaces around them.
+	let family = &values[idx..];
+	if family.is_empty() { return None; }
+	font[6] = Some(family.split(|value| **value == ComponentValue::Token(CSSToken::Comma)).map(text).collect::<Vec<String>>().join(", "));
+
+	Some(font.into_iter().zip(FONT.iter()).map(|(value, (_, initial))| value.unwrap_or_else(|| String::from(*initial))).collect())
+}
+
+fn flex(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let basis = |value: &ComponentValue| is_length_percentage(value) && !matches!(value, ComponentValue::Token(CSSToken::Number { .. })) || is_keyword(value, &["auto", "content"]);
+	let parts: Vec<String> = match values {
+		[value] if is_keyword(value, &["none"]) => vec![String::from("0"), String::from("0"), String::from("auto")],
+		[value] if is_keyword(value, &["auto"]) => vec![String::from("1"), String::from("1"), String::from("auto")],
+		[grow] if is_number(grow) => vec![grow.to_css(), String::from("1"), String::from("0%")],
+		[value] if basis(value) => vec![String::from("1"), String::from("1"), value.to_css()],
+		[grow, shrink] if is_number(grow) && is_number(shrink) => vec![grow.to_css(), shrink.to_css(), String::from("0%")],
+		[grow, value] if is_number(grow) && basis(value) => vec![grow.to_css(), String::from("1"), value.to_css()],
+		[value, grow] if basis(value) && is_number(grow) => vec![grow.to_css(), String::from("1"), value.to_css()],
+		[grow, shrink, value] if is_number(grow) && is_number(shrink) && basis(value) => vec![grow.to_css(), shrink.to_css(), value.to_css()],
+		[value, grow, shrink] if basis(value) && is_number(grow) && is_number(shrink) => vec![grow.to_css(), shrink.to_css(), value.to_css()],
+		_ => return None
+	};
+
+	Some(parts)
+}
+
+fn list_style(values: &[&ComponentValue]) -> Option<Vec<String>> {
+	let nones = values.iter().filter(|value| is_keyword(value, &["none"])).count();
+	let rest: Vec<&ComponentValue> = values.iter().copied().filter(|value| !is_keyword(value, &["none"])).collect();
+
+	let position = |value: &ComponentValue| is_keyword(value, &["inside", "outside"]);
+	let list_type = |value: &ComponentValue| matches!(value, ComponentValue::Token(CSSToken::Ident(_)) | ComponentValue::Token(CSSToken::String(_))) || is_function(value, &["symbols"]);
+	let mut slots = any_order(&rest, &[&position, &is_image, &list_type])?;
+
+	// `none` sets whichever of image and type is not otherwise given, or both when it is the only one.
+	let unset: Vec<usize> = (1..3).filter(|slot| slots[*slot].is_none()).collect();
+	if nones > unset.len() { return None; }
+	if nones > 0 {
+		for slot in unset {
+			slots[slot] = Some(String::from("none"));
+		}
+	}
+
+	let initial = ["outside", "none", "disc"];
+	Some(slots.into_iter().zip(initial).map(|(slot, initial)| slot.unwrap_or_else(|| String::from(initial))).collect())
+}
+
+fn expand_values(name: &str, values: &[&ComponentValue]) -> Option<Vec<String>> {
+	match name {
+		"margin" | "inset" => if values.iter().all(|value| is_length_percentage(value) || is_keyword(value, &["auto"])) { sides(values) } else { None },
+		"padding" => if values.iter().all(|value| is_length_percentage(value)) { sides(values) } else { None },
+		"border-width" => if values.iter().all(|value| is_keyword(value, &LINE_WIDTHS) || is_length_percentage(value)) { sides(values) } else { None },
+		"border-style" => if values.iter().all(|value| is_keyword(value, &LINE_STYLES)) { sides(values) } else { None },
+		"border-color" => if values.iter().all(|value| is_color(value)) { sides(values) } else { None },
+		"border-top" | "border-right" | "border-bottom" | "border-left" | "outline" => border_side(values),
+		"border" => {
+			let side = border_side(values)?;
+			let mut expanded: Vec<String> = side.iter().flat_map(|part| std::iter::repeat_n(part.clone(), 4)).collect();
+			expanded.extend(BORDER_IMAGE.iter().map(|(_, initial)| String::from(*initial)));
+			Some(expanded)
+		},
+		"border-radius" => border_radius(values),
+		"background" => background(values),
+		"font" => font(values),
+		"flex" => flex(values),
+		"list-style" => list_style(values),
+		"overflow" | "gap" => match values {
+			[both] => Some(vec![both.to_css(), both.to_css()]),
+			[first, second] => Some(vec![first.to_css(), second.to_css()]),
+			_ => None
+		},
+		_ => None
+	}
+}
+
+fn longhand(name: String, value: &str, important: bool) -> Rc<CSSRule> {
+	Rc::new(CSSRule::new(name, css_parse::parse_component_values(value), important))
+}
+
+pub fn is_shorthand(name: &str) -> bool {
+	longhands(name).is_some()
+}
+
+/// Expands a shorthand declaration into its longhands, with omitted parts set to their initial values. Other
+/// declarations are returned as they are, and an invalid shorthand expands to nothing. Values using `var()` can only
+/// be expanded once substituted, so they are also returned unchanged.
+pub fn expand(rule: &Rc<CSSRule>) -> Vec<Rc<CSSRule>> {
+	let names = match longhands(&rule.name) {
+		Some(names) => names,
+		None => return vec![rule.clone()]
+	};
+	if contains_var(&rule.components) {
+		return vec![rule.clone()];
+	}
+
+	let values: Vec<&ComponentValue> = rule.components.iter().filter(|value| !value.is_whitespace()).collect();
+	if let [value] = &values[..] {
+		if is_keyword(value, &CSS_WIDE_KEYWORDS) {
+			return names.into_iter().map(|name| longhand(name, &value.to_css(), rule.important)).collect();
+		}
+	}
+	if values.iter().any(|value| is_keyword(value, &CSS_WIDE_KEYWORDS)) {
+		return vec![];
+	}
+
+	match expand_values(&rule.name, &values) {
+		Some(expanded) => names.into_iter().zip(expanded).map(|(name, value)| longhand(name, &value, rule.important)).collect(),
+		None => vec![]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The longhands of the declaration `css` as `name: value`, with `!` after important ones.
+	fn expanded(css: &str) -> Vec<String> {
+		let rule = css_parse::create_block(css).rules[0].clone();
+		expand(&rule).iter().map(|rule| format!("{}: {}{}", rule.name, rule.value, if rule.important { "!" } else { "" })).collect()
+	}
+
+	#[test]
+	fn box_sides() {
+		assert_eq!(expanded("margin: 1px"), ["margin-top: 1px", "margin-right: 1px", "margin-bottom: 1px", "margin-left: 1px"]);
+		assert_eq!(expanded("margin: 1px auto"), ["margin-top: 1px", "margin-right: auto", "margin-bottom: 1px", "margin-left: auto"]);
+		assert_eq!(expanded("padding: 1px 2% 3em !important"), ["padding-top: 1px!", "padding-right: 2%!", "padding-bottom: 3em!", "padding-left: 2%!"]);
+		assert_eq!(expanded("inset: 0 1px 2px 3px"), ["top: 0", "right: 1px", "bottom: 2px", "left: 3px"]);
+		assert_eq!(expanded("border-color: red blue"), ["border-top-color: red", "border-right-color: blue", "border-bottom-color: red", "border-left-color: blue"]);
+		assert!(expanded("padding: auto").is_empty());
+		assert!(expanded("margin: 1px 2px 3px 4px 5px").is_empty());
+	}
+
+	#[test]
+	fn borders() {
+		assert_eq!(expanded("border-top: red 2px"), ["border-top-width: 2px", "border-top-style: none", "border-top-color: red"]);
+		assert_eq!(expanded("outline: dashed"), ["outline-width: medium", "outline-style: dashed", "outline-color: currentcolor"]);
+		let border = expanded("border: 1px solid #000");
+		assert_eq!(border.len(), 17);
+		assert_eq!(&border[..5], ["border-top-width: 1px", "border-right-width: 1px", "border-bottom-width: 1px", "border-left-width: 1px", "border-top-style: solid"]);
+		assert_eq!(border[12], "border-image-source: none");
+		assert!(expanded("border: 1px 2px").is_empty());
+		assert_eq!(expanded("border-radius: 1px 2px / 3px"), ["border-top-left-radius: 1px 3px", "border-top-right-radius: 2px 3px", "border-bottom-right-radius: 1px 3px", "border-bottom-left-radius: 2px 3px"]);
+	}
+
+	#[test]
+	fn backgrounds() {
+		assert_eq!(expanded("background: red"), [
+			"background-image: none", "background-position: 0% 0%", "background-size: auto", "background-repeat: repeat", "background-attachment: scroll",
+			"background-origin: padding-box", "background-clip: border-box", "background-color: red"
+		]);
+		assert_eq!(expanded("background: url(a.png) center / cover no-repeat content-box, blue"), [
+			"background-image: url(\"a.png\"), none", "background-position: center, 0% 0%", "background-size: cover, auto", "background-repeat: no-repeat, repeat",
+			"background-attachment: scroll, scroll", "background-origin: content-box, padding-box", "background-clip: content-box, border-box", "background-color: blue"
+		]);
+		assert!(expanded("background: red, url(a.png)").is_empty());
+	}
+
+	#[test]
+	fn fonts() {
+		assert_eq!(expanded("font: italic bold 12px/1.5 \"Open Sans\", serif"), [
+			"font-style: italic", "font-variant: normal", "font-weight: bold", "font-stretch: normal", "font-size: 12px", "line-height: 1.5", "font-family: \"Open Sans\", serif"
+		]);
+		assert_eq!(expanded("font: normal 700 1em a b")[2..], ["font-weight: 700", "font-stretch: normal", "font-size: 1em", "line-height: normal", "font-family: a b"]);
+		assert!(expanded("font: bold 12px").is_empty());
+		assert!(expanded("font: italic italic 12px a").is_empty());
+	}
+
+	#[test]
+	fn flex_and_lists() {
+		assert_eq!(expanded("flex: 2"), ["flex-grow: 2", "flex-shrink: 1", "flex-basis: 0%"]);
+		assert_eq!(expanded("flex: none"), ["flex-grow: 0", "flex-shrink: 0", "flex-basis: auto"]);
+		assert_eq!(expanded("flex: 10px 2 3"), ["flex-grow: 2", "flex-shrink: 3", "flex-basis: 10px"]);
+		assert_eq!(expanded("list-style: none inside"), ["list-style-position: inside", "list-style-image: none", "list-style-type: none"]);
+		assert_eq!(expanded("list-style: square none"), ["list-style-position: outside", "list-style-image: none", "list-style-type: square"]);
+		assert_eq!(expanded("gap: 1px"), ["row-gap: 1px", "column-gap: 1px"]);
+	}
+
+	#[test]
+	fn keywords_and_variables() {
+		assert_eq!(expanded("gap: inherit"), ["row-gap: inherit", "column-gap: inherit"]);
+		assert!(expanded("margin: 1px inherit").is_empty());
+		assert_eq!(expanded("margin: var(--m)"), ["margin: var(--m)"]);
+		assert_eq!(expanded("color: red"), ["color: red"]);
+	}
+}
@@ -0,0 +1,176 @@
+use std::rc::Rc;
+use std::str::FromStr;
+use super::css_parse::{self, ComponentValue, CSSRule};
+use super::css_properties;
+use super::css_serialize::{self, Format};
+use super::css_tokenize::CSSToken;
+use super::css_variables;
+use super::media_query::MediaEnvironment;
+use super::selector::Selector;
+
+#[derive(Debug, Clone)]
+pub enum SupportsCondition {
+	/// `(property: value)`, supported if the engine understands the declaration.
+	Declaration(Rc<CSSRule>),
+	/// `selector(...)`, supported if the selector engine can parse it.
+	Selector(String),
+	Not(Box<SupportsCondition>),
+	And(Vec<SupportsCondition>),
+	Or(Vec<SupportsCondition>),
+	/// Syntax that parses as `<general-enclosed>`, which is never supported.
+	Unknown
+}
+
+fn ident(value: Option<&&ComponentValue>) -> Option<String> {
+	match value {
+		Some(ComponentValue::Token(CSSToken::Ident(ident))) => Some(ident.to_ascii_lowercase()),
+		_ => None
+	}
+}
+
+fn parse_in_parens(value: &ComponentValue) -> Option<SupportsCondition> {
+	match value {
+		ComponentValue::Block { open: '(', contents } => {
+			let significant: Vec<&ComponentValue> = contents.iter().filter(|value| !value.is_whitespace()).collect();
+			if let Some(condition) = parse_condition(&significant) {
+				return Some(condition);
+			}
+
+			let start = contents.iter().position(|value| !value.is_whitespace()).unwrap_or(contents.len());
+			Some(css_parse::parse_declaration(&contents[start..]).map_or(SupportsCondition::Unknown, |declaration| SupportsCondition::Declaration(Rc::new(declaration))))
+		},
+		ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("selector") => Some(SupportsCondition::Selector(css_parse::serialize(arguments).trim().to_string())),
+		ComponentValue::Function { .. } => Some(SupportsCondition::Unknown),
+		_ => None
+	}
+}
+
+/// Parses a whole condition, `and` and `or` may not be mixed without parentheses.
+fn parse_condition(values: &[&ComponentValue]) -> Option<SupportsCondition> {
+	if ident(values.first()).as_deref() == Some("not") {
+		return match values {
+			[_, value] => Some(SupportsCondition::Not(Box::new(parse_in_parens(value)?))),
+			_ => None
+		};
+	}
+
+	let mut conditions = vec![parse_in_parens(values.first()?)?];
+	let mut operator = None;
+	let mut rest = &values[1..];
+	while let [keyword, value, remaining @ ..] = rest {
+		let keyword = ident(Some(keyword))?;
+		if keyword != "and" && keyword != "or" { return None; }
+		if operator.is_some_and(|operator: &str| operator != keyword) { return None; }
+
+		operator = Some(if keyword == "and" { "and" } else { "or" });
+		conditions.push(parse_in_parens(value)?);
+		rest = remaining;
+	}
+	if !rest.is_empty() { return None; }
+
+	Some(match operator {
+		None => conditions.pop().unwrap(),
+		Some("and") => SupportsCondition::And(conditions),
+		Some(_) => SupportsCondition::Or(conditions)
+	})
+}
+
+/// Whether the parser knows the property and the value fits its grammar, which is what `@supports` is evaluated
+/// against unless the environment says otherwise. Values with `var()` can't be checked and are assumed to fit.
+pub fn is_supported_declaration(declaration: &Rc<CSSRule>) -> bool {
+	if css_variables::is_custom_property(&declaration.name) { return true; }
+	css_properties::is_known(&declaration.name) && (css_variables::contains_var(&declaration.components) || css_properties::is_valid(declaration))
+}
+
+impl SupportsCondition {
+	pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+		match self {
+			SupportsCondition::Declaration(declaration) => (environment.supports_declaration)(declaration),
+			SupportsCondition::Selector(selector) => Selector::from_str(selector).is_ok(),
+			SupportsCondition::Not(condition) => !condition.matches(environment),
+			SupportsCondition::And(conditions) => conditions.iter().all(|condition| condition.matches(environment)),
+			SupportsCondition::Or(conditions) => conditions.iter().any(|condition| condition.matches(environment)),
+			SupportsCondition::Unknown => false
+		}
+	}
+}
+
+/// The condition of an `@supports`.
+#[derive(Debug, Clone)]
+pub struct SupportsQuery {
+	pub condition: SupportsCondition,
+	/// What the condition was parsed from, as the parsed condition doesn't keep the original spelling.
+	source: Vec<ComponentValue>
+}
+impl SupportsQuery {
+	/// Returns `None` if `source` isn't a valid condition, which drops the rule.
+	pub fn parse(source: &[ComponentValue]) -> Option<SupportsQuery> {
+		let values: Vec<&ComponentValue> = source.iter().filter(|value| !value.is_whitespace()).collect();
+		Some(SupportsQuery {
+			condition: parse_condition(&values)?,
+			source: source.to_vec()
+		})
+	}
+
+	pub fn to_css(&self, format: Format) -> String {
+		css_serialize::format_media_queries(&self.source, format)
+	}
+
+	pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+		self.condition.matches(environment)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::parse_component_values;
+
+	/// Whether the condition `css` parses and holds on a screen, or `None` if it doesn't parse.
+	fn supports(css: &str) -> Option<bool> {
+		SupportsQuery::parse(&parse_component_values(css)).map(|query| query.matches(&MediaEnvironment::screen(800.0, 600.0, 1.0)))
+	}
+
+	#[test]
+	fn declarations() {
+		assert_eq!(supports("(display: block)"), Some(true));
+		assert_eq!(supports("( COLOR : red )"), Some(true));
+		assert_eq!(supports("(color: var(--x))"), Some(true));
+		assert_eq!(supports("(--anything: at all)"), Some(true));
+		assert_eq!(supports("(color: 10px)"), Some(false));
+		assert_eq!(supports("(made-up: 1)"), Some(false));
+		assert_eq!(supports("(color)"), Some(false));
+		assert_eq!(supports("display: block"), None);
+	}
+
+	#[test]
+	fn operators() {
+		assert_eq!(supports("not (made-up: 1)"), Some(true));
+		assert_eq!(supports("(color: red) and (display: block)"), Some(true));
+		assert_eq!(supports("(color: red) and (made-up: 1)"), Some(false));
+		assert_eq!(supports("(made-up: 1) or (color: red)"), Some(true));
+		assert_eq!(supports("(made-up: 1) OR (made-up: 2) or (color: 1px)"), Some(false));
+		assert_eq!(supports("((made-up: 1) or (color: red)) and (not (color: 1px))"), Some(true));
+		assert_eq!(supports("(color: red) and (color: red) or (color: red)"), None);
+		assert_eq!(supports("not (color: red) and (color: red)"), None);
+		assert_eq!(supports("(color: red) (color: red)"), None);
+	}
+
+	/// Syntax that doesn't parse as a condition but is enclosed in parentheses or a function is false, not invalid.
+	#[test]
+	fn general_enclosed() {
+		assert_eq!(supports("(not a condition)"), Some(false));
+		assert_eq!(supports("font-tech(color-colrv1)"), Some(false));
+		assert_eq!(supports("not unknown(1)"), Some(true));
+		assert_eq!(supports("(unknown) or (color: red)"), Some(true));
+	}
+
+	#[test]
+	fn selectors() {
+		assert_eq!(supports("selector(a > b:first-child)"), Some(true));
+		assert_eq!(supports("selector(:has(> img))"), Some(true));
+		assert_eq!(supports("selector(a >)"), Some(false));
+		assert_eq!(supports("selector(a:hover)"), Some(false));
+		assert_eq!(supports("not selector(::made-up)"), Some(true));
+	}
+}
@@ -0,0 +1,528 @@
+use super::source_position::{SourcePosition, SourceSpan};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CSSToken {
+	Ident(String),
+	Function(String),
+	AtKeyword(String),
+	/// The hash's name and whether it is of type "id", that is it would also start an identifier.
+	Hash(String, bool),
+	String(String),
+	BadString,
+	Url(String),
+	BadUrl,
+	Delim(char),
+	/// Numeric tokens keep their source representation so they serialize back unchanged.
+	Number { value: f32, integer: bool, repr: String },
+	Percentage { value: f32, repr: String },
+	Dimension { value: f32, integer: bool, repr: String, unit: String },
+	Whitespace,
+	CDO,
+	CDC,
+	Colon,
+	Semicolon,
+	Comma,
+	OpenSquare,
+	CloseSquare,
+	OpenParen,
+	CloseParen,
+	OpenCurly,
+	CloseCurly
+}
+impl CSSToken {
+	pub fn to_css(&self) -> String {
+		match self {
+			CSSToken::Ident(name) => serialize_identifier(name),
+			CSSToken::Function(name) => format!("{}(", serialize_identifier(name)),
+			CSSToken::AtKeyword(name) => format!("@{}", serialize_identifier(name)),
+			CSSToken::Hash(name, _) => format!("#{}", serialize_name(name)),
+			CSSToken::String(value) => serialize_string(value),
+			CSSToken::BadString => String::from("\""),
+			CSSToken::Url(url) => serialize_url(url),
+			CSSToken::BadUrl => String::from("url()"),
+			CSSToken::Delim(ch) => ch.to_string(),
+			CSSToken::Number { repr, .. } => repr.clone(),
+			CSSToken::Percentage { repr, .. } => format!("{}%", repr),
+			CSSToken::Dimension { repr, unit, .. } => format!("{}{}", repr, serialize_unit(unit)),
+			CSSToken::Whitespace => String::from(" "),
+			CSSToken::CDO => String::from("<!--"),
+			CSSToken::CDC => String::from("-->"),
+			CSSToken::Colon => String::from(":"),
+			CSSToken::Semicolon => String::from(";"),
+			CSSToken::Comma => String::from(","),
+			CSSToken::OpenSquare => String::from("["),
+			CSSToken::CloseSquare => String::from("]"),
+			CSSToken::OpenParen => String::from("("),
+			CSSToken::CloseParen => String::from(")"),
+			CSSToken::OpenCurly => String::from("{"),
+			CSSToken::CloseCurly => String::from("}")
+		}
+	}
+}
+
+fn is_name_start(ch: char) -> bool {
+	ch.is_ascii_alphabetic() || ch == '_' || !ch.is_ascii()
+}
+
+fn is_name(ch: char) -> bool {
+	is_name_start(ch) || ch.is_ascii_digit() || ch == '-'
+}
+
+fn is_whitespace(ch: char) -> bool {
+	ch == '\n' || ch == '\t' || ch == ' '
+}
+
+fn is_non_printable(ch: char) -> bool {
+	matches!(ch, '\u{0}'..='\u{8}' | '\u{b}' | '\u{e}'..='\u{1f}' | '\u{7f}')
+}
+
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+	first == Some('\\') && second != Some('\n')
+}
+
+fn would_start_identifier(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+	match first {
+		Some('-') => second.is_some_and(|ch| is_name_start(ch) || ch == '-') || is_valid_escape(second, third),
+		Some('\\') => is_valid_escape(first, second),
+		Some(ch) => is_name_start(ch),
+		None => false
+	}
+}
+
+fn would_start_number(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+	let digit = |ch: Option<char>| ch.is_some_and(|ch| ch.is_ascii_digit());
+	match first {
+		Some('+') | Some('-') => digit(second) || (second == Some('.') && digit(third)),
+		Some('.') => digit(second),
+		ch => digit(ch)
+	}
+}
+
+/// Tokenizes a stylesheet following CSS Syntax Level 3. Comments are dropped.
+pub struct CSSTokenizer {
+	input: Vec<char>,
+	position: usize,
+	/// The source position of `input[tracked.0]`, advanced as spans are asked for.
+	tracked: (usize, SourcePosition)
+}
+impl CSSTokenizer {
+	pub fn new(css: &str) -> CSSTokenizer {
+		let css = css.replace("\r\n", "\n").replace(['\r', '\u{c}'], "\n").replace('\u{0}', "\u{fffd}");
+		CSSTokenizer {
+			input: css.chars().collect(),
+			position: 0,
+			tracked: (0, SourcePosition::new())
+		}
+	}
+
+	fn peek(&self, offset: usize) -> Option<char> {
+		self.input.get(self.position + offset).copied()
+	}
+
+	fn next_char(&mut self) -> Option<char> {
+		let ch = self.peek(0);
+		if ch.is_some() { self.position += 1; }
+		ch
+	}
+
+	fn consume_comments(&mut self) {
+		while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+			self.position += 2;
+			while self.peek(0).is_some() && !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+				self.position += 1;
+			}
+			self.position = (self.position + 2).min(self.input.len());
+		}
+	}
+
+	fn consume_escape(&mut self) -> char {
+		match self.next_char() {
+			Some(ch) if ch.is_ascii_hexdigit() => {
+				let mut hex = ch.to_string();
+				while hex.len() < 6 && self.peek(0).is_some_and(|ch| ch.is_ascii_hexdigit()) {
+					hex.push(self.next_char().unwrap());
+				}
+				if self.peek(0).is_some_and(is_whitespace) { self.position += 1; }
+
+				let code = u32::from_str_radix(&hex, 16).unwrap();
+				if code == 0 { '\u{fffd}' } else { char::from_u32(code).unwrap_or('\u{fffd}') }
+			},
+			Some(ch) => ch,
+			None => '\u{fffd}'
+		}
+	}
+
+	fn consume_name(&mut self) -> String {
+		let mut name = String::new();
+		loop {
+			match self.peek(0) {
+				Some(ch) if is_name(ch) => {
+					name.push(ch);
+					self.position += 1;
+				},
+				first if is_valid_escape(first, self.peek(1)) => {
+					self.position += 1;
+					name.push(self.consume_escape());
+				},
+				_ => return name
+			}
+		}
+	}
+
+	fn consume_digits(&mut self, repr: &mut String) {
+		while self.peek(0).is_some_and(|ch| ch.is_ascii_digit()) {
+			repr.push(self.next_char().unwrap());
+		}
+	}
+
+	fn consume_number(&mut self) -> (f32, bool, String) {
+		let mut repr = String::new();
+		let mut integer = true;
+
+		if let Some(sign @ ('+' | '-')) = self.peek(0) {
+			repr.push(sign);
+			self.position += 1;
+		}
+		self.consume_digits(&mut repr);
+
+		if self.peek(0) == Some('.') && self.peek(1).is_some_and(|ch| ch.is_ascii_digit()) {
+			repr.push(self.next_char().unwrap());
+			self.consume_digits(&mut repr);
+			integer = false;
+		}
+
+		let exponent_digit = |ch: Option<char>| ch.is_some_and(|ch| ch.is_ascii_digit());
+		if let Some(e @ ('e' | 'E')) = self.peek(0) {
+			let signed = matches!(self.peek(1), Some('+') | Some('-'));
+			if exponent_digit(self.peek(1)) || (signed && exponent_digit(self.peek(2))) {
+				repr.push(e);
+				self.position += 1;
+				if signed { repr.push(self.next_char().unwrap()); }
+				self.consume_digits(&mut repr);
+				integer = false;
+			}
+		}
+
+		(repr.parse().unwrap_or(0.0), integer, repr)
+	}
+
+	fn consume_numeric(&mut self) -> CSSToken {
+		let (value, integer, repr) = self.consume_number();
+
+		if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+			let unit = self.consume_name();
+			CSSToken::Dimension { value, integer, repr, unit }
+		} else if self.peek(0) == Some('%') {
+			self.position += 1;
+			CSSToken::Percentage { value, repr }
+		} else {
+			CSSToken::Number { value, integer, repr }
+		}
+	}
+
+	fn consume_bad_url(&mut self) {
+		loop {
+			match self.next_char() {
+				Some(')') | None => return,
+				first if is_valid_escape(first, self.peek(0)) => { self.consume_escape(); },
+				_ => {}
+			}
+		}
+	}
+
+	fn consume_url(&mut self) -> CSSToken {
+		let mut url = String::new();
+		while self.peek(0).is_some_and(is_whitespace) { self.position += 1; }
+
+		loop {
+			match self.next_char() {
+				Some(')') | None => return CSSToken::Url(url),
+				Some(ch) if is_whitespace(ch) => {
+					while self.peek(0).is_some_and(is_whitespace) { self.position += 1; }
+					if let Some(')') | None = self.peek(0) {
+						self.position = (self.position + 1).min(self.input.len());
+						return CSSToken::Url(url);
+					}
+					self.consume_bad_url();
+					return CSSToken::BadUrl;
+				},
+				Some('"') | Some('\'') | Some('(') => {
+					self.consume_bad_url();
+					return CSSToken::BadUrl;
+				},
+				Some(ch) if is_non_printable(ch) => {
+					self.consume_bad_url();
+					return CSSToken::BadUrl;
+				},
+				Some('\\') => {
+					if is_valid_escape(Some('\\'), self.peek(0)) {
+						url.push(self.consume_escape());
+					} else {
+						self.consume_bad_url();
+						return CSSToken::BadUrl;
+					}
+				},
+				Some(ch) => url.push(ch)
+			}
+		}
+	}
+
+	fn consume_ident_like(&mut self) -> CSSToken {
+		let name = self.consume_name();
+
+		if name.eq_ignore_ascii_case("url") && self.peek(0) == Some('(') {
+			self.position += 1;
+			while self.peek(0).is_some_and(is_whitespace) && self.peek(1).is_some_and(is_whitespace) {
+				self.position += 1;
+			}
+
+			let quoted = |ch: Option<char>| ch == Some('"') || ch == Some('\'');
+			if quoted(self.peek(0)) || (self.peek(0).is_some_and(is_whitespace) && quoted(self.peek(1))) {
+				CSSToken::Function(name)
+			} else {
+				self.consume_url()
+			}
+		} else if self.peek(0) == Some('(') {
+			self.position += 1;
+			CSSToken::Function(name)
+		} else {
+			CSSToken::Ident(name)
+		}
+	}
+
+	fn consume_string(&mut self, ending: char) -> CSSToken {
+		let mut string = String::new();
+		loop {
+			match self.peek(0) {
+				Some('\n') => return CSSToken::BadString,
+				_ => match self.next_char() {
+					Some(ch) if ch == ending => return CSSToken::String(string),
+					None => return CSSToken::String(string),
+					Some('\\') => match self.peek(0) {
+						None => {},
+						Some('\n') => self.position += 1,
+						Some(_) => string.push(self.consume_escape())
+					},
+					Some(ch) => string.push(ch)
+				}
+			}
+		}
+	}
+
+	fn source_position(&mut self, idx: usize) -> SourcePosition {
+		while self.tracked.0 < idx {
+			self.tracked.1.advance(self.input[self.tracked.0]);
+			self.tracked.0 += 1;
+		}
+		self.tracked.1
+	}
+
+	/// Like `next_token`, with the span of the token. Offsets are into the input after newlines are normalized, so a
+	/// `\r\n` counts as one character.
+	pub fn next_token_spanned(&mut self) -> Option<(CSSToken, SourceSpan)> {
+		self.consume_comments();
+		let start = self.source_position(self.position);
+		let token = self.next_token()?;
+		let end = self.source_position(self.position);
+		Some((token, SourceSpan::new(start, end)))
+	}
+
+	pub fn next_token(&mut self) -> Option<CSSToken> {
+		self.consume_comments();
+		let ch = self.next_char()?;
+
+		Some(match ch {
+			ch if is_whitespace(ch) => {
+				while self.peek(0).is_some_and(is_whitespace) { self.position += 1; }
+				CSSToken::Whitespace
+			},
+			'"' | '\'' => self.consume_string(ch),
+			'#' if self.peek(0).is_some_and(is_name) || is_valid_escape(self.peek(0), self.peek(1)) => {
+				let id = would_start_identifier(self.peek(0), self.peek(1), self.peek(2));
+				CSSToken::Hash(self.consume_name(), id)
+			},
+			'(' => CSSToken::OpenParen,
+			')' => CSSToken::CloseParen,
+			'[' => CSSToken::OpenSquare,
+			']' => CSSToken::CloseSquare,
+			'{' => CSSToken::OpenCurly,
+			'}' => CSSToken::CloseCurly,
+			',' => CSSToken::Comma,
+			':' => CSSToken::Colon,
+			';' => CSSToken::Semicolon,
+			'+' | '-' | '.' if would_start_number(Some(ch), self.peek(0), self.peek(1)) => {
+				self.position -= 1;
+				self.consume_numeric()
+			},
+			'-' if self.peek(0) == Some('-') && self.peek(1) == Some('>') => {
+				self.position += 2;
+				CSSToken::CDC
+			},
+			'-' if would_start_identifier(Some(ch), self.peek(0), self.peek(1)) => {
+				self.position -= 1;
+				self.consume_ident_like()
+			},
+			'<' if self.peek(0) == Some('!') && self.peek(1) == Some('-') && self.peek(2) == Some('-') => {
+				self.position += 3;
+				CSSToken::CDO
+			},
+			'@' if would_start_identifier(self.peek(0), self.peek(1), self.peek(2)) => CSSToken::AtKeyword(self.consume_name()),
+			'\\' if is_valid_escape(Some(ch), self.peek(0)) => {
+				self.position -= 1;
+				self.consume_ident_like()
+			},
+			ch if ch.is_ascii_digit() => {
+				self.position -= 1;
+				self.consume_numeric()
+			},
+			ch if is_name_start(ch) => {
+				self.position -= 1;
+				self.consume_ident_like()
+			},
+			ch => CSSToken::Delim(ch)
+		})
+	}
+}
+
+pub fn tokenize(css: &str) -> Vec<CSSToken> {
+	let mut tokenizer = CSSTokenizer::new(css);
+	let mut tokens = vec![];
+	while let Some(token) = tokenizer.next_token() {
+		tokens.push(token);
+	}
+
+	tokens
+}
+
+pub fn tokenize_spanned(css: &str) -> Vec<(CSSToken, SourceSpan)> {
+	let mut tokenizer = CSSTokenizer::new(css);
+	let mut tokens = vec![];
+	while let Some(token) = tokenizer.next_token_spanned() {
+		tokens.push(token);
+	}
+
+	tokens
+}
+
+fn escape_code_point(ch: char) -> String {
+	format!("\\{:x} ", ch as u32)
+}
+
+fn serialize_name(name: &str) -> String {
+	name.chars().map(|ch| match ch {
+		'\u{0}' => String::from("\u{fffd}"),
+		'\u{1}'..='\u{1f}' | '\u{7f}' => escape_code_point(ch),
+		ch if is_name(ch) => ch.to_string(),
+		ch => format!("\\{}", ch)
+	}).collect()
+}
+
+/// Escapes `ident` so it tokenizes back to a single identifier.
+pub fn serialize_identifier(ident: &str) -> String {
+	let chars: Vec<char> = ident.chars().collect();
+	if chars == ['-'] { return String::from("\\-"); }
+
+	chars.iter().enumerate().map(|(idx, &ch)| match ch {
+		ch if ch.is_ascii_digit() && (idx == 0 || (idx == 1 && chars[0] == '-')) => escape_code_point(ch),
+		ch => serialize_name(&ch.to_string())
+	}).collect()
+}
+
+fn serialize_unit(unit: &str) -> String {
+	// A unit like `e3` would otherwise be read back as an exponent.
+	let mut chars = unit.chars();
+	match (chars.next(), chars.next()) {
+		(Some(e @ ('e' | 'E')), Some(next)) if next.is_ascii_digit() || next == '-' || next == '+' => format!("{}{}", escape_code_point(e), serialize_name(&unit[1..])),
+		_ => serialize_identifier(unit)
+	}
+}
+
+pub fn serialize_string(string: &str) -> String {
+	let mut serialized = String::from("\"");
+	for ch in string.chars() {
+		match ch {
+			'\u{0}' => serialized.push('\u{fffd}'),
+			'\u{1}'..='\u{1f}' | '\u{7f}' => serialized.push_str(&escape_code_point(ch)),
+			'"' | '\\' => {
+				serialized.push('\\');
+				serialized.push(ch);
+			},
+			ch => serialized.push(ch)
+		}
+	}
+
+	serialized.push('"');
+	serialized
+}
+
+pub fn serialize_url(url: &str) -> String {
+	format!("url({})", serialize_string(url))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ident(name: &str) -> CSSToken {
+		CSSToken::Ident(String::from(name))
+	}
+
+	fn number(value: f32, integer: bool, repr: &str) -> CSSToken {
+		CSSToken::Number { value, integer, repr: String::from(repr) }
+	}
+
+	#[test]
+	fn punctuation_and_comments() {
+		assert_eq!(tokenize("a/* b */{ c:d; }"), [
+			ident("a"), CSSToken::OpenCurly, CSSToken::Whitespace, ident("c"), CSSToken::Colon, ident("d"), CSSToken::Semicolon, CSSToken::Whitespace, CSSToken::CloseCurly
+		]);
+		assert_eq!(tokenize("<!-- [ ( , ) ] -->"), [
+			CSSToken::CDO, CSSToken::Whitespace, CSSToken::OpenSquare, CSSToken::Whitespace, CSSToken::OpenParen, CSSToken::Whitespace, CSSToken::Comma,
+			CSSToken::Whitespace, CSSToken::CloseParen, CSSToken::Whitespace, CSSToken::CloseSquare, CSSToken::Whitespace, CSSToken::CDC
+		]);
+		assert_eq!(tokenize("a /* unclosed"), [ident("a"), CSSToken::Whitespace]);
+	}
+
+	#[test]
+	fn names() {
+		assert_eq!(tokenize("-moz-x --custom \\31 a"), [ident("-moz-x"), CSSToken::Whitespace, ident("--custom"), CSSToken::Whitespace, ident("1a")]);
+		assert_eq!(tokenize("@media rgb("), [CSSToken::AtKeyword(String::from("media")), CSSToken::Whitespace, CSSToken::Function(String::from("rgb"))]);
+		assert_eq!(tokenize("#id #1a"), [CSSToken::Hash(String::from("id"), true), CSSToken::Whitespace, CSSToken::Hash(String::from("1a"), false)]);
+		assert_eq!(tokenize("# @ -"), [CSSToken::Delim('#'), CSSToken::Whitespace, CSSToken::Delim('@'), CSSToken::Whitespace, CSSToken::Delim('-')]);
+	}
+
+	#[test]
+	fn numbers() {
+		assert_eq!(tokenize("12 +.5 -3e2"), [number(12.0, true, "12"), CSSToken::Whitespace, number(0.5, false, "+.5"), CSSToken::Whitespace, number(-300.0, false, "-3e2")]);
+		assert_eq!(tokenize("50%"), [CSSToken::Percentage { value: 50.0, repr: String::from("50") }]);
+		assert_eq!(tokenize("1.5em"), [CSSToken::Dimension { value: 1.5, integer: false, repr: String::from("1.5"), unit: String::from("em") }]);
+		assert_eq!(tokenize("1e3x"), [CSSToken::Dimension { value: 1000.0, integer: false, repr: String::from("1e3"), unit: String::from("x") }]);
+	}
+
+	#[test]
+	fn strings_and_urls() {
+		assert_eq!(tokenize(r#""a\"b" 'c\
+d'"#), [CSSToken::String(String::from("a\"b")), CSSToken::Whitespace, CSSToken::String(String::from("cd"))]);
+		assert_eq!(tokenize("\"a\nb"), [CSSToken::BadString, CSSToken::Whitespace, ident("b")]);
+		assert_eq!(tokenize("url( a.png ) url(\"b.png\")"), [
+			CSSToken::Url(String::from("a.png")), CSSToken::Whitespace, CSSToken::Function(String::from("url")), CSSToken::String(String::from("b.png")), CSSToken::CloseParen
+		]);
+		assert_eq!(tokenize("url(a b)"), [CSSToken::BadUrl]);
+	}
+
+	/// `\r\n` is normalized to one newline before tokenizing, so it is one character of the offsets.
+	#[test]
+	fn spans() {
+		let spans: Vec<(usize, usize, usize)> = tokenize_spanned("a {\r\n  b: 1px }").iter().map(|(_, span)| (span.start.line, span.start.column, span.end.offset)).collect();
+		assert_eq!(spans, [(1, 1, 1), (1, 2, 2), (1, 3, 3), (1, 4, 6), (2, 3, 7), (2, 4, 8), (2, 5, 9), (2, 6, 12), (2, 9, 13), (2, 10, 14)]);
+	}
+
+	#[test]
+	fn serialization_round_trips() {
+		for css in ["a\\:b", "\\31 x", "-\\-", "1\\65 3", "\"\\\"\\\\\"", "url(\"a b\")", "#\\#x"] {
+			let tokens = tokenize(css);
+			let serialized: String = tokens.iter().map(CSSToken::to_css).collect();
+			assert_eq!(tokenize(&serialized), tokens, "{}", css);
+		}
+	}
+}
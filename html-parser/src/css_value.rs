@@ -0,0 +1,220 @@
+use super::css_calc::CalcNode;
+use super::css_color::{self, SpecifiedColor};
+use super::css_parse::ComponentValue;
+use super::css_tokenize::CSSToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LengthUnit {
+	Px,
+	Cm,
+	Mm,
+	Q,
+	In,
+	Pt,
+	Pc,
+	Em,
+	Rem,
+	Ex,
+	Ch,
+	Vw,
+	Vh,
+	Vmin,
+	Vmax
+}
+impl LengthUnit {
+	pub fn from_unit(unit: &str) -> Option<LengthUnit> {
+		Some(match unit.to_ascii_lowercase().as_str() {
+			"px" => LengthUnit::Px,
+			"cm" => LengthUnit::Cm,
+			"mm" => LengthUnit::Mm,
+			"q" => LengthUnit::Q,
+			"in" => LengthUnit::In,
+			"pt" => LengthUnit::Pt,
+			"pc" => LengthUnit::Pc,
+			"em" => LengthUnit::Em,
+			"rem" => LengthUnit::Rem,
+			"ex" => LengthUnit::Ex,
+			"ch" => LengthUnit::Ch,
+			"vw" => LengthUnit::Vw,
+			"vh" => LengthUnit::Vh,
+			"vmin" => LengthUnit::Vmin,
+			"vmax" => LengthUnit::Vmax,
+			_ => return None
+		})
+	}
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LengthUnit::Px => "px",
+			LengthUnit::Cm => "cm",
+			LengthUnit::Mm => "mm",
+			LengthUnit::Q => "q",
+			LengthUnit::In => "in",
+			LengthUnit::Pt => "pt",
+			LengthUnit::Pc => "pc",
+			LengthUnit::Em => "em",
+			LengthUnit::Rem => "rem",
+			LengthUnit::Ex => "ex",
+			LengthUnit::Ch => "ch",
+			LengthUnit::Vw => "vw",
+			LengthUnit::Vh => "vh",
+			LengthUnit::Vmin => "vmin",
+			LengthUnit::Vmax => "vmax"
+		}
+	}
+
+	/// Converts an absolute length to pixels. Font and viewport relative units return `None`.
+	pub fn to_px(&self, value: f32) -> Option<f32> {
+		match self {
+			LengthUnit::Px => Some(value),
+			LengthUnit::Cm => Some(value * 96.0 / 2.54),
+			LengthUnit::Mm => Some(value * 96.0 / 25.4),
+			LengthUnit::Q => Some(value * 96.0 / 101.6),
+			LengthUnit::In => Some(value * 96.0),
+			LengthUnit::Pt => Some(value * 96.0 / 72.0),
+			LengthUnit::Pc => Some(value * 16.0),
+			_ => None
+		}
+	}
+}
+
+/// An sRGB color with channels in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+	pub r: f32,
+	pub g: f32,
+	pub b: f32,
+	pub a: f32
+}
+impl Color {
+	pub fn new(r: f32, g: f32, b: f32, a: f32) -> Color {
+		Color {
+			r, g, b, a
+		}
+	}
+
+	/// Parses the digits of a 3, 4, 6 or 8 digit hex color.
+	pub fn from_hex(hex: &str) -> Option<Color> {
+		if !hex.chars().all(|ch| ch.is_ascii_hexdigit()) { return None; }
+
+		let digits: Vec<u8> = hex.chars().map(|ch| ch.to_digit(16).unwrap() as u8).collect();
+		let channels: Vec<f32> = match digits.len() {
+			3 | 4 => digits.iter().map(|digit| (digit * 17) as f32 / 255.0).collect(),
+			6 | 8 => digits.chunks(2).map(|pair| (pair[0] * 16 + pair[1]) as f32 / 255.0).collect(),
+			_ => return None
+		};
+
+		Some(Color::new(channels[0], channels[1], channels[2], channels.get(3).copied().unwrap_or(1.0)))
+	}
+}
+
+/// A typed component of a declaration's value. Whitespace is dropped, keywords and function names are lowercased.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CSSValue {
+	Length(f32, LengthUnit),
+	Percentage(f32),
+	Number(f32),
+	/// A dimension whose unit is not a length, such as `deg` or `s`.
+	Dimension(f32, String),
+	Color(Color),
+	Keyword(String),
+	String(String),
+	Url(String),
+	/// A `calc()`, `min()`, `max()` or `clamp()` expression, resolved at layout time.
+	Calc(CalcNode),
+	Function(String, Vec<CSSValue>),
+	Comma,
+	Delim(char),
+	/// A token no property accepts, like a `#hash` that isn't a color, kept so the declaration is rejected.
+	Invalid(String)
+}
+impl CSSValue {
+	pub fn from_components(components: &[ComponentValue]) -> Vec<CSSValue> {
+		components.iter().filter_map(CSSValue::from_component).collect()
+	}
+
+	fn from_component(component: &ComponentValue) -> Option<CSSValue> {
+		Some(match component {
+			ComponentValue::Token(token) => match token {
+				CSSToken::Ident(ident) if ident.starts_with("--") => CSSValue::Keyword(ident.clone()),
+				CSSToken::Ident(ident) => CSSValue::Keyword(ident.to_ascii_lowercase()),
+				CSSToken::Hash(hash, _) => match Color::from_hex(hash) {
+					Some(color) => CSSValue::Color(color),
+					None => CSSValue::Invalid(token.to_css())
+				},
+				CSSToken::String(string) => CSSValue::String(string.clone()),
+				CSSToken::Url(url) => CSSValue::Url(url.clone()),
+				CSSToken::Number { value, .. } => CSSValue::Number(*value),
+				CSSToken::Percentage { value, .. } => CSSValue::Percentage(*value),
+				CSSToken::Dimension { value, unit, .. } => match LengthUnit::from_unit(unit) {
+					Some(unit) => CSSValue::Length(*value, unit),
+					None => CSSValue::Dimension(*value, unit.to_ascii_lowercase())
+				},
+				CSSToken::Comma => CSSValue::Comma,
+				CSSToken::Delim(ch) => CSSValue::Delim(*ch),
+				_ => return None
+			},
+			ComponentValue::Function { name, arguments } => {
+				if let Some(calc) = CalcNode::parse(component) {
+					return Some(CSSValue::Calc(calc));
+				}
+				if let Some(SpecifiedColor::Color(color)) = css_color::parse(component) {
+					return Some(CSSValue::Color(color));
+				}
+
+				let name = name.to_ascii_lowercase();
+				let arguments = CSSValue::from_components(arguments);
+				match (name.as_str(), &arguments[..]) {
+					("url", [CSSValue::String(url)]) => CSSValue::Url(url.clone()),
+					_ => CSSValue::Function(name, arguments)
+				}
+			},
+			ComponentValue::Block { .. } => return None
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::parse_component_values;
+
+	fn values(css: &str) -> Vec<CSSValue> {
+		CSSValue::from_components(&parse_component_values(css))
+	}
+
+	#[test]
+	fn typed_values() {
+		assert_eq!(values("10PX 2.5em 50% 0 45deg"), [
+			CSSValue::Length(10.0, LengthUnit::Px),
+			CSSValue::Length(2.5, LengthUnit::Em),
+			CSSValue::Percentage(50.0),
+			CSSValue::Number(0.0),
+			CSSValue::Dimension(45.0, String::from("deg"))
+		]);
+		assert_eq!(values("Bold, --Token 'a' url(x.png) url('y.png')"), [
+			CSSValue::Keyword(String::from("bold")),
+			CSSValue::Comma,
+			CSSValue::Keyword(String::from("--Token")),
+			CSSValue::String(String::from("a")),
+			CSSValue::Url(String::from("x.png")),
+			CSSValue::Url(String::from("y.png"))
+		]);
+		assert_eq!(values("Repeat(2, 1fr)"), [CSSValue::Function(String::from("repeat"), vec![CSSValue::Number(2.0), CSSValue::Comma, CSSValue::Dimension(1.0, String::from("fr"))])]);
+	}
+
+	#[test]
+	fn hex_colors() {
+		assert_eq!(values("#fff"), [CSSValue::Color(Color::new(1.0, 1.0, 1.0, 1.0))]);
+		assert_eq!(values("#00000080"), [CSSValue::Color(Color::new(0.0, 0.0, 0.0, 128.0 / 255.0))]);
+		assert_eq!(values("#ggg"), [CSSValue::Invalid(String::from("#ggg"))]);
+		assert_eq!(values("1px solid #12345"), [CSSValue::Length(1.0, LengthUnit::Px), CSSValue::Keyword(String::from("solid")), CSSValue::Invalid(String::from("#12345"))]);
+	}
+
+	#[test]
+	fn lengths_in_pixels() {
+		assert_eq!(LengthUnit::In.to_px(1.0), Some(96.0));
+		assert_eq!(LengthUnit::Pt.to_px(72.0), Some(96.0));
+		assert_eq!(LengthUnit::Em.to_px(1.0), None);
+	}
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use super::css_parse::{ComponentValue, CSSRule, trim};
+use super::css_tokenize::CSSToken;
+
+pub fn is_custom_property(name: &str) -> bool {
+	name.starts_with("--")
+}
+
+pub fn contains_var(values: &[ComponentValue]) -> bool {
+	values.iter().any(|value| match value {
+		ComponentValue::Function { name, arguments } => name.eq_ignore_ascii_case("var") || contains_var(arguments),
+		ComponentValue::Block { contents, .. } => contains_var(contents),
+		ComponentValue::Token(_) => false
+	})
+}
+
+/// Splits the arguments of `var()` into the custom property name and the fallback, if there is one.
+fn var_arguments(arguments: &[ComponentValue]) -> Option<(&str, Option<&[ComponentValue]>)> {
+	let arguments = trim(arguments);
+	let name = match arguments.first() {
+		Some(ComponentValue::Token(CSSToken::Ident(name))) if is_custom_property(name) => name.as_str(),
+		_ => return None
+	};
+
+	let rest = trim(&arguments[1..]);
+	match rest.first() {
+		None => Some((name, None)),
+		Some(ComponentValue::Token(CSSToken::Comma)) => Some((name, Some(trim(&rest[1..])))),
+		Some(_) => None
+	}
+}
+
+/// Replaces every `var()` in `values`. Returns `None` if a reference can't be resolved and has no fallback, which
+/// makes the whole value invalid.
+fn substitute(values: &[ComponentValue], lookup: &mut dyn FnMut(&str) -> Option<Rc<Vec<ComponentValue>>>) -> Option<Vec<ComponentValue>> {
+	let mut substituted = vec![];
+	for value in values {
+		match value {
+			ComponentValue::Function { name, arguments } if name.eq_ignore_ascii_case("var") => {
+				let (name, fallback) = var_arguments(arguments)?;
+				match (lookup(name), fallback) {
+					(Some(value), _) => substituted.extend(value.iter().cloned()),
+					(None, Some(fallback)) => substituted.extend(substitute(fallback, lookup)?),
+					(None, None) => return None
+				}
+			},
+			ComponentValue::Function { name, arguments } => substituted.push(ComponentValue::Function {
+				name: name.clone(),
+				arguments: substitute(arguments, lookup)?
+			}),
+			ComponentValue::Block { open, contents } => substituted.push(ComponentValue::Block {
+				open: *open,
+				contents: substitute(contents, lookup)?
+			}),
+			value => substituted.push(value.clone())
+		}
+	}
+
+	Some(substituted)
+}
+
+/// Resolves the `var()` references between the custom properties set on one element.
+struct Resolver<'a> {
+	inherited: &'a CustomProperties,
+	specified: HashMap<String, Option<Vec<ComponentValue>>>,
+	resolved: HashMap<String, Option<Rc<Vec<ComponentValue>>>>,
+	resolving: Vec<String>
+}
+impl<'a> Resolver<'a> {
+	fn resolve(&mut self, name: &str) -> Option<Rc<Vec<ComponentValue>>> {
+		if let Some(resolved) = self.resolved.get(name) {
+			return resolved.clone();
+		}
+		let specified = match self.specified.get(name) {
+			Some(specified) => specified.clone(),
+			None => return self.inherited.values.get(name).cloned()
+		};
+
+		// Every property in a cycle is invalid at computed-value time, not just the one that closes it.
+		if let Some(start) = self.resolving.iter().position(|resolving| resolving == name) {
+			for name in self.resolving[start..].iter() {
+				self.resolved.insert(name.clone(), None);
+			}
+			return None;
+		}
+
+		self.resolving.push(String::from(name));
+		let value = specified.and_then(|specified| substitute(&specified, &mut |name| self.resolve(name)));
+		self.resolving.pop();
+
+		let value = match self.resolved.get(name) {
+			Some(None) => None,
+			_ => value.map(Rc::new)
+		};
+		self.resolved.insert(String::from(name), value.clone());
+		value
+	}
+}
+
+/// The computed custom properties of an element. Custom properties are always inherited, and a property that is
+/// invalid at computed-value time is left out, which is its initial value.
+#[derive(Debug, Clone, Default)]
+pub struct CustomProperties {
+	values: HashMap<String, Rc<Vec<ComponentValue>>>
+}
+impl CustomProperties {
+	pub fn get(&self, name: &str) -> Option<&[ComponentValue]> {
+		self.values.get(name).map(|value| value.as_slice())
+	}
+
+	/// Computes the custom properties of an element from those of its parent and the custom property declarations
+	/// that won the cascade on the element.
+	pub fn compute(inherited: &CustomProperties, declarations: &[Rc<CSSRule>]) -> CustomProperties {
+		let mut specified = HashMap::new();
+		for declaration in declarations.iter().filter(|declaration| is_custom_property(&declaration.name)) {
+			let value = trim(&declaration.components);
+			let keyword = match value {
+				[ComponentValue::Token(CSSToken::Ident(keyword))] => keyword.to_ascii_lowercase(),
+				_ => String::new()
+			};
+
+			let value = match keyword.as_str() {
+				"initial" => None,
+				"inherit" | "unset" | "revert" | "revert-layer" => inherited.values.get(&declaration.name).map(|value| value.to_vec()),
+				_ => Some(value.to_vec())
+			};
+			specified.insert(declaration.name.clone(), value);
+		}
+
+		let names: Vec<String> = specified.keys().cloned().collect();
+		let mut resolver = Resolver {
+			inherited, specified,
+			resolved: HashMap::new(),
+			resolving: vec![]
+		};
+		let mut values = inherited.values.clone();
+		for name in names {
+			match resolver.resolve(&name) {
+				Some(value) => values.insert(name, value),
+				None => values.remove(&name)
+			};
+		}
+
+		CustomProperties { values }
+	}
+
+	/// Substitutes the `var()` references of a declaration. A declaration that is invalid at computed-value time
+	/// becomes `unset`.
+	pub fn substitute(&self, rule: &Rc<CSSRule>) -> Rc<CSSRule> {
+		if !contains_var(&rule.components) {
+			return rule.clone();
+		}
+
+		let value = substitute(&rule.components, &mut |name| self.values.get(name).cloned())
+			.unwrap_or_else(|| vec![ComponentValue::Token(CSSToken::Ident(String::from("unset")))]);
+		Rc::new(CSSRule::new(rule.name.clone(), value, rule.important))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::{create_block, serialize};
+
+	/// The custom properties of an element with the declarations `css`, whose parent's are `inherited`.
+	fn computed(inherited: &CustomProperties, css: &str) -> CustomProperties {
+		CustomProperties::compute(inherited, &create_block(css).rules)
+	}
+
+	fn value(properties: &CustomProperties, name: &str) -> Option<String> {
+		properties.get(name).map(serialize)
+	}
+
+	/// The value of the last declaration of `css` after substituting the custom properties declared before it.
+	fn substituted(css: &str) -> String {
+		let rules = create_block(css).rules;
+		let (declaration, custom) = rules.split_last().unwrap();
+		CustomProperties::compute(&CustomProperties::default(), custom).substitute(declaration).value.clone()
+	}
+
+	#[test]
+	fn substitution() {
+		assert_eq!(substituted("--a: 10px; margin: var(--a)"), "10px");
+		assert_eq!(substituted("--a: 1px; --b: var(--a) 2px; margin: var(--b) calc(var(--a) * 3)"), "1px 2px calc(1px * 3)");
+		assert_eq!(substituted("--Case: red; color: var(--Case)"), "red");
+		assert_eq!(substituted("--case: red; color: var(--CASE, blue)"), "blue");
+		assert_eq!(substituted("color: var(--missing, var(--also-missing, green))"), "green");
+		assert_eq!(substituted("color: var(--missing)"), "unset");
+		assert_eq!(substituted("--a: red; color: var(--a blue)"), "unset");
+		assert_eq!(substituted("--empty: ; margin: 1px var(--empty)"), "1px");
+	}
+
+	#[test]
+	fn inheritance() {
+		let parent = computed(&CustomProperties::default(), "--a: 1px; --b: 2px; --c: 3px");
+		let child = computed(&parent, "--b: initial; --c: inherit; --d: var(--a)");
+		assert_eq!(value(&child, "--a").as_deref(), Some("1px"));
+		assert_eq!(value(&child, "--b"), None);
+		assert_eq!(value(&child, "--c").as_deref(), Some("3px"));
+		assert_eq!(value(&child, "--d").as_deref(), Some("1px"));
+	}
+
+	/// Every property in a cycle is invalid, while properties that only refer to one fall back or are invalid too.
+	#[test]
+	fn cycles() {
+		let parent = computed(&CustomProperties::default(), "--a: parent");
+		let properties = computed(&parent, "--a: var(--b); --b: var(--c); --c: var(--a); --d: var(--a, fallback); --e: var(--e); --f: var(--b)");
+		for name in ["--a", "--b", "--c", "--e", "--f"] {
+			assert_eq!(value(&properties, name), None, "{}", name);
+		}
+		assert_eq!(value(&properties, "--d").as_deref(), Some("fallback"));
+
+		let properties = computed(&CustomProperties::default(), "--a: var(--b, 1px); --b: var(--a, 2px); --c: 3px; --d: var(--c) var(--c)");
+		assert_eq!((value(&properties, "--a"), value(&properties, "--b")), (None, None));
+		assert_eq!(value(&properties, "--d").as_deref(), Some("3px 3px"));
+	}
+}
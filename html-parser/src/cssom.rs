@@ -0,0 +1,198 @@
+use std::fmt;
+use std::rc::Rc;
+use super::css_parse::{self, CSSBlock, CSSRule, CSSStatement, CSSStyleSheet, DeclarationListItem, MediaRule, Rule, SupportsRule};
+use super::css_properties;
+use super::css_serialize::Format;
+use super::css_variables;
+
+/// Why a CSSOM call was rejected, named after the DOM exceptions it would throw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CSSOMError {
+	/// The index is past the end of the rule list.
+	IndexSize,
+	/// The text isn't exactly one valid rule.
+	Syntax,
+	/// The rule can't go at that position, like an `@import` after a style rule.
+	HierarchyRequest
+}
+
+impl fmt::Display for CSSOMError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CSSOMError::IndexSize => write!(f, "rule index out of range"),
+			CSSOMError::Syntax => write!(f, "not a single valid rule"),
+			CSSOMError::HierarchyRequest => write!(f, "rule not allowed at this position")
+		}
+	}
+}
+
+/// Parses `rule` as the one statement it must contain.
+fn parse_statement(rule: &str, top_level: bool) -> Result<CSSStatement, CSSOMError> {
+	let rules = css_parse::parse_rule_list(&css_parse::parse_component_values(rule), top_level);
+	if rules.len() != 1 { return Err(CSSOMError::Syntax); }
+	if !top_level && matches!(&rules[0], Rule::At(rule) if rule.name.eq_ignore_ascii_case("import")) {
+		return Err(CSSOMError::HierarchyRequest);
+	}
+
+	css_parse::statements(&rules, top_level).pop().ok_or(CSSOMError::Syntax)
+}
+
+fn insert_statement(statements: &mut Vec<CSSStatement>, rule: &str, index: usize, top_level: bool) -> Result<usize, CSSOMError> {
+	if index > statements.len() { return Err(CSSOMError::IndexSize); }
+	let statement = parse_statement(rule, top_level)?;
+
+	// Imports have to stay in front of every other rule.
+	let is_import = |statement: &CSSStatement| matches!(statement, CSSStatement::Import(_));
+	let imports = statements.iter().take_while(|statement| is_import(statement)).count();
+	if (is_import(&statement) && index > imports) || (!is_import(&statement) && index < imports) {
+		return Err(CSSOMError::HierarchyRequest);
+	}
+
+	statements.insert(index, statement);
+	Ok(index)
+}
+
+fn delete_statement(statements: &mut Vec<CSSStatement>, index: usize) -> Result<(), CSSOMError> {
+	if index >= statements.len() { return Err(CSSOMError::IndexSize); }
+	statements.remove(index);
+	Ok(())
+}
+
+impl CSSStyleSheet {
+	/// Parses `rule` and inserts it before the rule at `index`, returning `index`.
+	pub fn insert_rule(&mut self, rule: &str, index: usize) -> Result<usize, CSSOMError> {
+		insert_statement(&mut self.statements, rule, index, true)
+	}
+
+	pub fn delete_rule(&mut self, index: usize) -> Result<(), CSSOMError> {
+		delete_statement(&mut self.statements, index)
+	}
+
+	pub fn css_text(&self) -> String {
+		self.to_css(Format::Pretty)
+	}
+}
+
+impl MediaRule {
+	pub fn insert_rule(&mut self, rule: &str, index: usize) -> Result<usize, CSSOMError> {
+		insert_statement(&mut self.statements, rule, index, false)
+	}
+
+	pub fn delete_rule(&mut self, index: usize) -> Result<(), CSSOMError> {
+		delete_statement(&mut self.statements, index)
+	}
+
+	pub fn css_text(&self) -> String {
+		self.to_css(Format::Pretty)
+	}
+}
+
+impl SupportsRule {
+	pub fn insert_rule(&mut self, rule: &str, index: usize) -> Result<usize, CSSOMError> {
+		insert_statement(&mut self.statements, rule, index, false)
+	}
+
+	pub fn delete_rule(&mut self, index: usize) -> Result<(), CSSOMError> {
+		delete_statement(&mut self.statements, index)
+	}
+
+	pub fn css_text(&self) -> String {
+		self.to_css(Format::Pretty)
+	}
+}
+
+impl CSSStatement {
+	/// The style rule, copied first if it is shared, for example with a cascade that was already built.
+	pub fn as_style_mut(&mut self) -> Option<&mut CSSBlock> {
+		match self {
+			CSSStatement::Style(block) => Some(Rc::make_mut(block)),
+			_ => None
+		}
+	}
+
+	pub fn as_media_mut(&mut self) -> Option<&mut MediaRule> {
+		match self {
+			CSSStatement::Media(rule) => Some(rule),
+			_ => None
+		}
+	}
+
+	pub fn as_supports_mut(&mut self) -> Option<&mut SupportsRule> {
+		match self {
+			CSSStatement::Supports(rule) => Some(rule),
+			_ => None
+		}
+	}
+
+	pub fn css_text(&self) -> String {
+		match self {
+			CSSStatement::Style(block) => block.to_css(Format::Pretty),
+			CSSStatement::Media(rule) => rule.to_css(Format::Pretty),
+			CSSStatement::Import(rule) => rule.to_css(Format::Pretty),
+			CSSStatement::FontFace(rule) => rule.to_css(Format::Pretty),
+			CSSStatement::Layer(rule) => rule.to_css(Format::Pretty),
+			CSSStatement::Supports(rule) => rule.to_css(Format::Pretty)
+		}
+	}
+}
+
+fn property_name(name: &str) -> String {
+	if css_variables::is_custom_property(name) { String::from(name) } else { name.to_ascii_lowercase() }
+}
+
+/// The declarations of a style rule or a `style` attribute. Later declarations of a property win, so lookups use the
+/// last one.
+impl CSSBlock {
+	fn declaration(&self, name: &str) -> Option<&Rc<CSSRule>> {
+		let name = property_name(name);
+		self.rules.iter().rev().find(|rule| rule.name == name)
+	}
+
+	pub fn get_property_value(&self, name: &str) -> Option<String> {
+		self.declaration(name).map(|rule| rule.value.clone())
+	}
+
+	pub fn get_property_priority(&self, name: &str) -> bool {
+		self.declaration(name).is_some_and(|rule| rule.important)
+	}
+
+	/// Sets a declaration, replacing every existing one of the property. An empty value removes the property, and
+	/// unknown properties or invalid values leave the block as it is and return false.
+	pub fn set_property(&mut self, name: &str, value: &str, important: bool) -> bool {
+		let rule = Rc::new(CSSRule::new(property_name(name), css_parse::parse_component_values(value), important));
+		if rule.value.is_empty() {
+			self.remove_property(name);
+			return true;
+		}
+		if !css_properties::is_known(&rule.name) || !css_properties::is_valid(&rule) { return false; }
+
+		match self.rules.iter().position(|existing| existing.name == rule.name) {
+			Some(idx) => {
+				self.rules.retain(|existing| existing.name != rule.name);
+				self.rules.insert(idx, rule);
+			},
+			None => self.rules.push(rule)
+		}
+		true
+	}
+
+	/// Removes every declaration of the property, returning the value that was in effect.
+	pub fn remove_property(&mut self, name: &str) -> Option<String> {
+		let value = self.get_property_value(name)?;
+		let name = property_name(name);
+		self.rules.retain(|rule| rule.name != name);
+		Some(value)
+	}
+
+	/// The declarations alone, as in a `style` attribute.
+	pub fn css_text(&self) -> String {
+		self.rules.iter().map(|rule| format!("{};", rule.to_css(Format::Pretty))).collect::<Vec<String>>().join(" ")
+	}
+
+	pub fn set_css_text(&mut self, css: &str) {
+		self.rules = css_parse::parse_declaration_list(&css_parse::parse_component_values(css)).into_iter().filter_map(|item| match item {
+			DeclarationListItem::Declaration(declaration) => Some(Rc::new(declaration)),
+			DeclarationListItem::At(_) | DeclarationListItem::Rule(_) => None
+		}).collect();
+	}
+}
@@ -0,0 +1,449 @@
+use tree;
+use super::tag_parse::HTMLDoctype;
+use super::element_index::ElementIndex;
+use super::query;
+use super::selector::{MatchingContext, Selector, SelectorParseError};
+use super::tree_generation_2::{HTMLElement, HTMLNode, Namespace};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::str::FromStr;
+
+const QUIRKY_PUBLIC_ID_PREFIXES: [&str; 55] = [
+	"+//silmaril//dtd html pro v0r11 19970101//",
+	"-//as//dtd html 3.0 aswedit + extensions//",
+	"-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+	"-//ietf//dtd html 2.0 level 1//",
+	"-//ietf//dtd html 2.0 level 2//",
+	"-//ietf//dtd html 2.0 strict level 1//",
+	"-//ietf//dtd html 2.0 strict level 2//",
+	"-//ietf//dtd html 2.0 strict//",
+	"-//ietf//dtd html 2.0//",
+	"-//ietf//dtd html 2.1e//",
+	"-//ietf//dtd html 3.0//",
+	"-//ietf//dtd html 3.2 final//",
+	"-//ietf//dtd html 3.2//",
+	"-//ietf//dtd html 3//",
+	"-//ietf//dtd html level 0//",
+	"-//ietf//dtd html level 1//",
+	"-//ietf//dtd html level 2//",
+	"-//ietf//dtd html level 3//",
+	"-//ietf//dtd html strict level 0//",
+	"-//ietf//dtd html strict level 1//",
+	"-//ietf//dtd html strict level 2//",
+	"-//ietf//dtd html strict level 3//",
+	"-//ietf//dtd html strict//",
+	"-//ietf//dtd html//",
+	"-//metrius//dtd metrius presentational//",
+	"-//microsoft//dtd internet explorer 2.0 html strict//",
+	"-//microsoft//dtd internet explorer 2.0 html//",
+	"-//microsoft//dtd internet explorer 2.0 tables//",
+	"-//microsoft//dtd internet explorer 3.0 html strict//",
+	"-//microsoft//dtd internet explorer 3.0 html//",
+	"-//microsoft//dtd internet explorer 3.0 tables//",
+	"-//netscape comm. corp.//dtd html//",
+	"-//netscape comm. corp.//dtd strict html//",
+	"-//o'reilly and associates//dtd html 2.0//",
+	"-//o'reilly and associates//dtd html extended 1.0//",
+	"-//o'reilly and associates//dtd html extended relaxed 1.0//",
+	"-//sq//dtd html 2.0 hotmetal + extensions//",
+	"-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+	"-//softquad//dtd hotmetal pro 4.0::19970916::extensions to html 4.0//",
+	"-//spyglass//dtd html 2.0 extended//",
+	"-//sun microsystems corp.//dtd hotjava html//",
+	"-//sun microsystems corp.//dtd hotjava strict html//",
+	"-//w3c//dtd html 3 1995-03-24//",
+	"-//w3c//dtd html 3.2 draft//",
+	"-//w3c//dtd html 3.2 final//",
+	"-//w3c//dtd html 3.2//",
+	"-//w3c//dtd html 3.2s draft//",
+	"-//w3c//dtd html 4.0 frameset//",
+	"-//w3c//dtd html 4.0 transitional//",
+	"-//w3c//dtd html experimental 19960712//",
+	"-//w3c//dtd html experimental 970421//",
+	"-//w3c//dtd w3 html//",
+	"-//w3o//dtd w3 html 3.0//",
+	"-//webtechs//dtd mozilla html 2.0//",
+	"-//webtechs//dtd mozilla html//"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksMode {
+	Quirks,
+	LimitedQuirks,
+	NoQuirks
+}
+
+#[derive(Debug, Clone)]
+pub struct DocumentType {
+	name: String,
+	public_id: String,
+	system_id: String
+}
+impl DocumentType {
+	pub fn name(&self) -> &String {
+		&self.name
+	}
+
+	pub fn public_id(&self) -> &String {
+		&self.public_id
+	}
+
+	pub fn system_id(&self) -> &String {
+		&self.system_id
+	}
+}
+
+/// Id and class lookups go through an index, so changes to connected elements must be made through the
+/// document's mutation methods rather than by borrowing the nodes directly.
+#[derive(Debug)]
+pub struct Document {
+	root: Rc<tree::TreeNode<HTMLNode>>,
+	doctype: Option<DocumentType>,
+	mode: QuirksMode,
+	index: RefCell<ElementIndex>,
+	version: Cell<usize>
+}
+impl Document {
+	pub(crate) fn new(root: Rc<tree::TreeNode<HTMLNode>>, doctype: Option<HTMLDoctype>) -> Document {
+		let mode = match &doctype {
+			Some(doctype) => quirks_mode(doctype),
+			None => QuirksMode::Quirks
+		};
+
+		Document {
+			index: RefCell::new(ElementIndex::new(&root)),
+			root,
+			doctype: doctype.map(|doctype| DocumentType {
+				name: doctype.name.unwrap_or_default(),
+				public_id: doctype.public_id.unwrap_or_default(),
+				system_id: doctype.system_id.unwrap_or_default()
+			}),
+			mode,
+			version: Cell::new(0)
+		}
+	}
+
+	/// Counts the changes made through the document's mutation methods, so anything derived from the tree can tell
+	/// when it is stale.
+	pub fn version(&self) -> usize {
+		self.version.get()
+	}
+
+	fn changed(&self) {
+		self.version.set(self.version.get() + 1);
+	}
+
+	pub fn root(&self) -> Rc<tree::TreeNode<HTMLNode>> {
+		self.root.clone()
+	}
+
+	pub fn doctype(&self) -> Option<&DocumentType> {
+		self.doctype.as_ref()
+	}
+
+	pub fn mode(&self) -> QuirksMode {
+		self.mode
+	}
+
+	pub fn document_element(&self) -> Option<Rc<tree::TreeNode<HTMLNode>>> {
+		let mut idx = 0;
+		while let Some(child) = self.root.get_child(idx) {
+			if let HTMLNode::Element(_) = &*child.value.borrow() { return Some(child.clone()); }
+			idx += 1;
+		}
+
+		None
+	}
+
+	/// The text of the document's `<title>`, with whitespace stripped and collapsed.
+	pub fn title(&self) -> Option<String> {
+		let svg_root = self.document_element().is_some_and(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) => el.namespace() == Namespace::SVG && el.tagname() == "svg",
+			_ => false
+		});
+
+		let title = self.root().find(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) if svg_root => el.namespace() == Namespace::SVG && el.tagname() == "title",
+			HTMLNode::Element(el) => el.is_html_tag("title"),
+			_ => false
+		}).into_iter().next()?;
+
+		let text = title.map_linear(|node| match &*node.value.borrow() {
+			HTMLNode::Text(text, _) => text.clone(),
+			_ => String::new()
+		}).join("");
+
+		Some(text.split_ascii_whitespace().collect::<Vec<&str>>().join(" "))
+	}
+
+	/// The `href` of the first `<base>` element that has one.
+	pub fn base_url(&self) -> Option<String> {
+		let base = self.root().find(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) => el.is_html_tag("base") && el.has_attribute("href"),
+			_ => false
+		}).into_iter().next()?;
+
+		let value = base.value.borrow();
+		match &*value {
+			HTMLNode::Element(el) => el.get_attribute("href").map(String::from),
+			_ => None
+		}
+	}
+
+	/// The innermost node whose source span contains the byte `offset`, descending into template contents.
+	pub fn node_at_offset(&self, offset: usize) -> Option<Rc<tree::TreeNode<HTMLNode>>> {
+		let mut found = None;
+		let mut parent = self.root();
+
+		'descend: loop {
+			let mut idx = 0;
+			while let Some(child) = parent.get_child(idx) {
+				let (contains, contents) = match &*child.value.borrow() {
+					HTMLNode::Element(el) => (el.span().contains(offset), el.template_contents()),
+					node => (node.span().is_some_and(|span| span.contains(offset)), None)
+				};
+
+				if contains {
+					found = Some(child.clone());
+					parent = contents.unwrap_or(child);
+					continue 'descend;
+				}
+				idx += 1;
+			}
+
+			return found;
+		}
+	}
+
+	pub fn lang(&self) -> Option<String> {
+		let element = self.document_element()?;
+		let value = element.value.borrow();
+		match &*value {
+			HTMLNode::Element(el) => el.get_attribute("lang").map(String::from),
+			_ => None
+		}
+	}
+
+	pub fn get_element_by_id(&self, id: &str) -> Option<Rc<tree::TreeNode<HTMLNode>>> {
+		self.index.borrow().with_id(id).into_iter().next()
+	}
+
+	pub fn get_elements_by_tag_name(&self, name: &str) -> Vec<Rc<tree::TreeNode<HTMLNode>>> {
+		query::get_elements_by_tag_name(&self.root, name)
+	}
+
+	pub fn get_elements_by_class_name(&self, names: &str) -> Vec<Rc<tree::TreeNode<HTMLNode>>> {
+		let names: Vec<&str> = names.split_ascii_whitespace().collect();
+		if names.is_empty() { return vec![]; }
+		self.index.borrow().with_classes(&names)
+	}
+
+	pub fn query_selector(&self, selectors: &str) -> Result<Option<Rc<tree::TreeNode<HTMLNode>>>, SelectorParseError> {
+		Ok(self.query_selector_all(selectors)?.into_iter().next())
+	}
+
+	/// Selectors whose subjects all require an id or a class are only matched against the elements the index has for
+	/// them, the others against every element.
+	pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<Rc<tree::TreeNode<HTMLNode>>>, SelectorParseError> {
+		let selector = Selector::from_str(selectors)?;
+		let context = MatchingContext::new();
+		let candidates = self.index.borrow().candidates(&selector);
+		Ok(match candidates {
+			Some(candidates) => candidates.into_iter().filter(|node| selector.matches_with(node, &context)).collect(),
+			None => query::select_all(&self.root, &selector, &context)
+		})
+	}
+
+	fn is_connected(&self, node: &Rc<tree::TreeNode<HTMLNode>>) -> bool {
+		let mut current = node.clone();
+		while let Some(parent) = current.get_parent() {
+			current = parent;
+		}
+
+		Rc::ptr_eq(&current, &self.root)
+	}
+
+	/// Runs `cb` on the element held by `node` and reindexes it afterwards. Returns `None` if `node` is not an element.
+	pub fn update_element<A, R>(&self, node: &Rc<tree::TreeNode<HTMLNode>>, cb: A) -> Option<R> where A: FnOnce(&mut HTMLElement) -> R {
+		let connected = self.is_connected(node);
+		let before = ElementIndex::keys_of(node)?;
+
+		let result = match &mut *node.value.borrow_mut() {
+			HTMLNode::Element(el) => cb(el),
+			_ => panic!("Unreachable")
+		};
+		self.changed();
+
+		if connected {
+			let mut index = self.index.borrow_mut();
+			index.remove_element(node, before);
+			index.add_element(node, ElementIndex::keys_of(node).unwrap());
+		}
+
+		Some(result)
+	}
+
+	pub fn set_attribute(&self, node: &Rc<tree::TreeNode<HTMLNode>>, name: &str, value: &str) {
+		self.update_element(node, |el| el.set_attribute(name, value));
+	}
+
+	pub fn remove_attribute(&self, node: &Rc<tree::TreeNode<HTMLNode>>, name: &str) -> Option<String> {
+		self.update_element(node, |el| el.remove_attribute(name))?
+	}
+
+	/// Inserts `child` into `parent` before `reference`, or at the end when `reference` is `None`. `child` is first
+	/// removed from wherever it currently is. Returns false if `reference` is not a child of `parent`, or if `child` is
+	/// `parent` or one of its ancestors, as moving it would make a cycle.
+	pub fn insert_before(&self, parent: &Rc<tree::TreeNode<HTMLNode>>, child: &Rc<tree::TreeNode<HTMLNode>>, reference: Option<&Rc<tree::TreeNode<HTMLNode>>>) -> bool {
+		let mut ancestor = Some(parent.clone());
+		while let Some(current) = ancestor {
+			if Rc::ptr_eq(&current, child) { return false; }
+			ancestor = current.get_parent();
+		}
+		if reference.is_some_and(|reference| parent.index_of(reference).is_none()) { return false; }
+		if reference.is_some_and(|reference| Rc::ptr_eq(reference, child)) { return true; }
+
+		if let Some(old_parent) = child.get_parent() {
+			self.remove_child(&old_parent, child);
+		}
+
+		let idx = match reference {
+			Some(reference) => parent.index_of(reference).unwrap(),
+			None => parent.child_count()
+		};
+		parent.clone().insert_child(idx, child.clone());
+		self.changed();
+
+		if self.is_connected(parent) {
+			self.index.borrow_mut().add_subtree(child);
+		}
+		true
+	}
+
+	pub fn append_child(&self, parent: &Rc<tree::TreeNode<HTMLNode>>, child: &Rc<tree::TreeNode<HTMLNode>>) -> bool {
+		self.insert_before(parent, child, None)
+	}
+
+	pub fn remove_child(&self, parent: &Rc<tree::TreeNode<HTMLNode>>, child: &Rc<tree::TreeNode<HTMLNode>>) -> Option<Rc<tree::TreeNode<HTMLNode>>> {
+		let connected = self.is_connected(parent);
+		let child = parent.remove_child(child)?;
+		self.changed();
+
+		if connected {
+			self.index.borrow_mut().remove_subtree(&child);
+		}
+		Some(child)
+	}
+}
+
+fn quirks_mode(doctype: &HTMLDoctype) -> QuirksMode {
+	let public_id = doctype.public_id.as_ref().map(|id| id.to_ascii_lowercase());
+	let system_id = doctype.system_id.as_ref().map(|id| id.to_ascii_lowercase());
+	let public_starts_with = |prefix: &str| public_id.as_ref().is_some_and(|id| id.starts_with(prefix));
+
+	if doctype.force_quirks || doctype.name.as_deref() != Some("html") {
+		return QuirksMode::Quirks;
+	}
+	if let Some(id) = &public_id {
+		if id == "-//w3o//dtd w3 html strict 3.0//en//" || id == "-/w3c/dtd html 4.0 transitional/en" || id == "html" {
+			return QuirksMode::Quirks;
+		}
+		if QUIRKY_PUBLIC_ID_PREFIXES.iter().any(|prefix| id.starts_with(prefix)) {
+			return QuirksMode::Quirks;
+		}
+	}
+	if system_id.as_deref() == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd") {
+		return QuirksMode::Quirks;
+	}
+
+	let html_401_legacy = public_starts_with("-//w3c//dtd html 4.01 frameset//") || public_starts_with("-//w3c//dtd html 4.01 transitional//");
+	if html_401_legacy && system_id.is_none() {
+		return QuirksMode::Quirks;
+	}
+	if public_starts_with("-//w3c//dtd xhtml 1.0 frameset//") || public_starts_with("-//w3c//dtd xhtml 1.0 transitional//") || html_401_legacy {
+		return QuirksMode::LimitedQuirks;
+	}
+
+	QuirksMode::NoQuirks
+}
+
+#[cfg(test)]
+mod tests {
+	use std::rc::Rc;
+	use super::super::parse_html_new;
+
+	#[test]
+	fn insert_before_rejects_ancestors() {
+		let doc = parse_html_new("<div id=a><div id=b></div></div>");
+		let a = doc.get_element_by_id("a").unwrap();
+		let b = doc.get_element_by_id("b").unwrap();
+
+		assert!(!doc.append_child(&b, &a));
+		assert!(!doc.append_child(&a, &a));
+		assert!(!doc.insert_before(&b, &doc.root(), None));
+		assert!(Rc::ptr_eq(&b.get_parent().unwrap(), &a));
+		assert_eq!(doc.get_element_by_id("a").map(|node| Rc::ptr_eq(&node, &a)), Some(true));
+	}
+
+	#[test]
+	fn insert_before_moves_nodes() {
+		let doc = parse_html_new("<div id=a><p id=p></p></div><div id=b></div>");
+		let b = doc.get_element_by_id("b").unwrap();
+		let p = doc.get_element_by_id("p").unwrap();
+
+		assert!(doc.append_child(&b, &p));
+		assert_eq!(b.child_count(), 1);
+		assert_eq!(doc.get_element_by_id("a").unwrap().child_count(), 0);
+		assert!(doc.get_element_by_id("p").is_some());
+	}
+
+	#[test]
+	fn insert_before_rejects_references_of_other_parents() {
+		let doc = parse_html_new("<div id=a><p id=p></p></div><p id=x></p>");
+		let a = doc.get_element_by_id("a").unwrap();
+		let x = doc.get_element_by_id("x").unwrap();
+
+		assert!(!doc.insert_before(&a, &x, Some(&x)));
+		assert!(Rc::ptr_eq(&x.get_parent().unwrap(), &doc.root()));
+		assert!(doc.insert_before(&a, &x, doc.get_element_by_id("p").as_ref()));
+		assert!(Rc::ptr_eq(&a.get_child(0).unwrap(), &x));
+	}
+
+	/// The ids of the elements `selectors` matches, which must be what a search of the whole tree finds.
+	fn ids(doc: &super::Document, selectors: &str) -> Vec<String> {
+		let nodes = doc.query_selector_all(selectors).unwrap();
+		let searched = super::query::query_selector_all(&doc.root(), selectors).unwrap();
+		assert!(nodes.len() == searched.len() && nodes.iter().zip(&searched).all(|(a, b)| Rc::ptr_eq(a, b)), "{}", selectors);
+		nodes.iter().filter_map(|node| match &*node.value.borrow() {
+			super::HTMLNode::Element(el) => el.id().map(String::from),
+			_ => None
+		}).collect()
+	}
+
+	#[test]
+	fn indexed_queries_follow_mutations() {
+		let doc = parse_html_new("<div id=a class='x y'><p id=b class=x></p></div><section id=c><span id=d class=y></span></section>");
+		assert_eq!(ids(&doc, ".x"), ["a", "b"]);
+		assert_eq!(ids(&doc, "div > .x, #d"), ["b", "d"]);
+		assert_eq!(ids(&doc, "section .y"), ["d"]);
+
+		let b = doc.get_element_by_id("b").unwrap();
+		doc.set_attribute(&b, "class", "y");
+		doc.update_element(&doc.get_element_by_id("d").unwrap(), |el| el.set_attribute("id", "e"));
+		assert_eq!(ids(&doc, ".x"), ["a"]);
+		assert_eq!(ids(&doc, ".y"), ["a", "b", "e"]);
+		assert!(ids(&doc, "#d").is_empty());
+		assert_eq!(ids(&doc, "#e.y"), ["e"]);
+
+		let c = doc.get_element_by_id("c").unwrap();
+		assert!(doc.insert_before(&c, &b, None));
+		assert_eq!(ids(&doc, "section .y"), ["e", "b"]);
+		assert_eq!(ids(&doc, "div .y"), Vec::<String>::new());
+
+		doc.remove_child(&c, &b).unwrap();
+		assert_eq!(ids(&doc, ".y"), ["a", "e"]);
+		assert!(doc.query_selector("#b").unwrap().is_none());
+		assert!(doc.insert_before(&doc.get_element_by_id("a").unwrap(), &b, None));
+		assert_eq!(doc.query_selector(".y > .y").unwrap().map(|node| Rc::ptr_eq(&node, &b)), Some(true));
+	}
+}
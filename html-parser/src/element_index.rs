@@ -0,0 +1,129 @@
+use super::selector::{Selector, SelectorComponent};
+use super::tree_generation_2::{HTMLElement, HTMLNode};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+type Node = tree::TreeNode<HTMLNode>;
+
+/// `id -> elements` and `class -> elements` lookups for the elements connected to a document. Template contents are
+/// not connected, so they are never indexed.
+#[derive(Debug, Default)]
+pub(crate) struct ElementIndex {
+	ids: HashMap<String, Vec<Rc<Node>>>,
+	classes: HashMap<String, Vec<Rc<Node>>>
+}
+impl ElementIndex {
+	pub(crate) fn new(root: &Rc<Node>) -> ElementIndex {
+		let mut index = ElementIndex::default();
+		index.add_subtree(root);
+		index
+	}
+
+	fn keys(el: &HTMLElement) -> (Option<String>, Vec<String>) {
+		(el.id().map(String::from), el.class_list().tokens().into_iter().map(String::from).collect())
+	}
+
+	pub(crate) fn add_element(&mut self, node: &Rc<Node>, keys: (Option<String>, Vec<String>)) {
+		let (id, classes) = keys;
+		if let Some(id) = id {
+			self.ids.entry(id).or_default().push(node.clone());
+		}
+		for class in classes {
+			self.classes.entry(class).or_default().push(node.clone());
+		}
+	}
+
+	pub(crate) fn remove_element(&mut self, node: &Rc<Node>, keys: (Option<String>, Vec<String>)) {
+		let (id, classes) = keys;
+		if let Some(id) = id {
+			remove_from(&mut self.ids, &id, node);
+		}
+		for class in classes {
+			remove_from(&mut self.classes, &class, node);
+		}
+	}
+
+	/// The id and classes of `node`, if it is an element.
+	pub(crate) fn keys_of(node: &Rc<Node>) -> Option<(Option<String>, Vec<String>)> {
+		match &*node.value.borrow() {
+			HTMLNode::Element(el) => Some(ElementIndex::keys(el)),
+			_ => None
+		}
+	}
+
+	pub(crate) fn add_subtree(&mut self, root: &Rc<Node>) {
+		for node in root.clone().find(|_| true) {
+			if let Some(keys) = ElementIndex::keys_of(&node) { self.add_element(&node, keys); }
+		}
+	}
+
+	pub(crate) fn remove_subtree(&mut self, root: &Rc<Node>) {
+		for node in root.clone().find(|_| true) {
+			if let Some(keys) = ElementIndex::keys_of(&node) { self.remove_element(&node, keys); }
+		}
+	}
+
+	pub(crate) fn with_id(&self, id: &str) -> Vec<Rc<Node>> {
+		tree_ordered(self.ids.get(id).cloned().unwrap_or_default())
+	}
+
+	/// Elements that have every one of `names`, checked against the smallest class bucket.
+	pub(crate) fn with_classes(&self, names: &[&str]) -> Vec<Rc<Node>> {
+		let smallest = names.iter().map(|name| self.classes.get(*name)).min_by_key(|nodes| nodes.map_or(0, |nodes| nodes.len()));
+		let candidates = match smallest {
+			Some(Some(nodes)) => nodes.clone(),
+			_ => return vec![]
+		};
+
+		tree_ordered(candidates.into_iter().filter(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) => {
+				let class_list = el.class_list();
+				names.iter().all(|name| class_list.contains(name))
+			},
+			_ => false
+		}).collect())
+	}
+
+	/// The elements that have the id or class the subject of each selector in `selector` requires, in tree order.
+	/// `None` if one of them requires neither, so any element could match.
+	pub(crate) fn candidates(&self, selector: &Selector) -> Option<Vec<Rc<Node>>> {
+		let mut seen = HashSet::new();
+		let mut candidates = vec![];
+		for selector in selector.selectors() {
+			let nodes = match selector.subject().index_key()? {
+				SelectorComponent::Id(id) => self.ids.get(id),
+				SelectorComponent::Class(class) => self.classes.get(class),
+				_ => panic!("Unreachable")
+			};
+			for node in nodes.into_iter().flatten() {
+				if seen.insert(Rc::as_ptr(node)) { candidates.push(node.clone()); }
+			}
+		}
+		Some(tree_ordered(candidates))
+	}
+}
+
+fn remove_from(map: &mut HashMap<String, Vec<Rc<Node>>>, key: &str, node: &Rc<Node>) {
+	if let Some(nodes) = map.get_mut(key) {
+		nodes.retain(|existing| !Rc::ptr_eq(existing, node));
+		if nodes.is_empty() { map.remove(key); }
+	}
+}
+
+/// Child indices from the root down to `node`, which sort in tree order.
+fn tree_path(node: &Rc<Node>) -> Vec<usize> {
+	let mut path = vec![];
+	let mut current = node.clone();
+	while let Some(parent) = current.get_parent() {
+		path.push(parent.index_of(&current).unwrap());
+		current = parent;
+	}
+
+	path.reverse();
+	path
+}
+
+fn tree_ordered(mut nodes: Vec<Rc<Node>>) -> Vec<Rc<Node>> {
+	if nodes.len() > 1 { nodes.sort_by_cached_key(tree_path); }
+	nodes
+}
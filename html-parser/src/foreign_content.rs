@@ -0,0 +1,95 @@
+use super::tree_generation_2::Namespace;
+
+const SVG_TAG_NAMES: [(&str, &str); 37] = [
+	("altglyph", "altGlyph"), ("altglyphdef", "altGlyphDef"), ("altglyphitem", "altGlyphItem"),
+	("animatecolor", "animateColor"), ("animatemotion", "animateMotion"), ("animatetransform", "animateTransform"),
+	("clippath", "clipPath"), ("feblend", "feBlend"), ("fecolormatrix", "feColorMatrix"),
+	("fecomponenttransfer", "feComponentTransfer"), ("fecomposite", "feComposite"), ("feconvolvematrix", "feConvolveMatrix"),
+	("fediffuselighting", "feDiffuseLighting"), ("fedisplacementmap", "feDisplacementMap"), ("fedistantlight", "feDistantLight"),
+	("fedropshadow", "feDropShadow"), ("feflood", "feFlood"), ("fefunca", "feFuncA"),
+	("fefuncb", "feFuncB"), ("fefuncg", "feFuncG"), ("fefuncr", "feFuncR"),
+	("fegaussianblur", "feGaussianBlur"), ("feimage", "feImage"), ("femerge", "feMerge"),
+	("femergenode", "feMergeNode"), ("femorphology", "feMorphology"), ("feoffset", "feOffset"),
+	("fepointlight", "fePointLight"), ("fespecularlighting", "feSpecularLighting"), ("fespotlight", "feSpotLight"),
+	("fetile", "feTile"), ("feturbulence", "feTurbulence"), ("foreignobject", "foreignObject"),
+	("glyphref", "glyphRef"), ("lineargradient", "linearGradient"), ("radialgradient", "radialGradient"),
+	("textpath", "textPath")
+];
+
+const SVG_ATTRIBUTE_NAMES: [(&str, &str); 58] = [
+	("attributename", "attributeName"), ("attributetype", "attributeType"), ("basefrequency", "baseFrequency"),
+	("baseprofile", "baseProfile"), ("calcmode", "calcMode"), ("clippathunits", "clipPathUnits"),
+	("diffuseconstant", "diffuseConstant"), ("edgemode", "edgeMode"), ("filterunits", "filterUnits"),
+	("glyphref", "glyphRef"), ("gradienttransform", "gradientTransform"), ("gradientunits", "gradientUnits"),
+	("kernelmatrix", "kernelMatrix"), ("kernelunitlength", "kernelUnitLength"), ("keypoints", "keyPoints"),
+	("keysplines", "keySplines"), ("keytimes", "keyTimes"), ("lengthadjust", "lengthAdjust"),
+	("limitingconeangle", "limitingConeAngle"), ("markerheight", "markerHeight"), ("markerunits", "markerUnits"),
+	("markerwidth", "markerWidth"), ("maskcontentunits", "maskContentUnits"), ("maskunits", "maskUnits"),
+	("numoctaves", "numOctaves"), ("pathlength", "pathLength"), ("patterncontentunits", "patternContentUnits"),
+	("patterntransform", "patternTransform"), ("patternunits", "patternUnits"), ("pointsatx", "pointsAtX"),
+	("pointsaty", "pointsAtY"), ("pointsatz", "pointsAtZ"), ("preservealpha", "preserveAlpha"),
+	("preserveaspectratio", "preserveAspectRatio"), ("primitiveunits", "primitiveUnits"), ("refx", "refX"),
+	("refy", "refY"), ("repeatcount", "repeatCount"), ("repeatdur", "repeatDur"),
+	("requiredextensions", "requiredExtensions"), ("requiredfeatures", "requiredFeatures"), ("specularconstant", "specularConstant"),
+	("specularexponent", "specularExponent"), ("spreadmethod", "spreadMethod"), ("startoffset", "startOffset"),
+	("stddeviation", "stdDeviation"), ("stitchtiles", "stitchTiles"), ("surfacescale", "surfaceScale"),
+	("systemlanguage", "systemLanguage"), ("tablevalues", "tableValues"), ("targetx", "targetX"),
+	("targety", "targetY"), ("textlength", "textLength"), ("viewbox", "viewBox"),
+	("viewtarget", "viewTarget"), ("xchannelselector", "xChannelSelector"), ("ychannelselector", "yChannelSelector"),
+	("zoomandpan", "zoomAndPan")
+];
+
+const FOREIGN_ATTRIBUTES: [(&str, Option<&str>, &str, Namespace); 11] = [
+	("xlink:actuate", Some("xlink"), "actuate", Namespace::XLink),
+	("xlink:arcrole", Some("xlink"), "arcrole", Namespace::XLink),
+	("xlink:href", Some("xlink"), "href", Namespace::XLink),
+	("xlink:role", Some("xlink"), "role", Namespace::XLink),
+	("xlink:show", Some("xlink"), "show", Namespace::XLink),
+	("xlink:title", Some("xlink"), "title", Namespace::XLink),
+	("xlink:type", Some("xlink"), "type", Namespace::XLink),
+	("xml:lang", Some("xml"), "lang", Namespace::XML),
+	("xml:space", Some("xml"), "space", Namespace::XML),
+	("xmlns", None, "xmlns", Namespace::XMLNS),
+	("xmlns:xlink", Some("xmlns"), "xlink", Namespace::XMLNS)
+];
+
+const BREAKOUT_TAGS: [&str; 44] = [
+	"b", "big", "blockquote", "body", "br", "center", "code", "dd", "div", "dl", "dt", "em", "embed",
+	"h1", "h2", "h3", "h4", "h5", "h6", "head", "hr", "i", "img", "li", "listing", "menu", "meta",
+	"nobr", "ol", "p", "pre", "ruby", "s", "small", "span", "strong", "strike", "sub", "sup", "table",
+	"tt", "u", "ul", "var"
+];
+
+pub fn adjust_svg_tag_name(name: &str) -> String {
+	match SVG_TAG_NAMES.iter().find(|(lower, _)| *lower == name) {
+		Some((_, adjusted)) => String::from(*adjusted),
+		None => String::from(name)
+	}
+}
+
+pub fn adjust_svg_attribute_name(name: &str) -> String {
+	match SVG_ATTRIBUTE_NAMES.iter().find(|(lower, _)| *lower == name) {
+		Some((_, adjusted)) => String::from(*adjusted),
+		None => String::from(name)
+	}
+}
+
+pub fn adjust_mathml_attribute_name(name: &str) -> String {
+	if name == "definitionurl" { String::from("definitionURL") } else { String::from(name) }
+}
+
+/// Returns the prefix, local name and namespace of a namespaced attribute on a foreign element.
+pub fn adjust_foreign_attribute(name: &str) -> Option<(Option<String>, String, Namespace)> {
+	FOREIGN_ATTRIBUTES.iter().find(|(qualified, _, _, _)| *qualified == name).map(|(_, prefix, local, namespace)| {
+		(prefix.map(String::from), String::from(*local), *namespace)
+	})
+}
+
+/// Whether an HTML start tag appearing inside foreign content closes the foreign elements around it.
+pub fn is_breakout_tag(name: &str, attrs: &[(String, String)]) -> bool {
+	if name == "font" {
+		return attrs.iter().any(|(name, _)| name == "color" || name == "face" || name == "size");
+	}
+
+	BREAKOUT_TAGS.contains(&name)
+}
@@ -1,10 +1,30 @@
 use std::rc::Rc;
 
 pub mod tree_generation_2;
-use tree;
+pub mod document;
+pub mod attributes;
+mod element_index;
+pub mod source_position;
 mod tag_parse;
+mod foreign_content;
 pub mod tree_generation;
+pub mod css_tokenize;
 pub mod css_parse;
+pub mod css_value;
+pub mod css_calc;
+pub mod css_color;
+pub mod css_shorthand;
+pub mod css_variables;
+pub mod css_font_face;
+pub mod css_layers;
+pub mod css_properties;
+pub mod css_lint;
+pub mod css_serialize;
+pub mod cssom;
+pub mod media_query;
+pub mod css_supports;
+pub mod selector;
+pub mod query;
 
 pub fn parse_html<R>(html: &str, generator: &mut impl tree_generation::RenderGenerator<R>) -> Rc<tree_generation::HTMLNode<R>> {
     let mut x = tag_parse::TagParser::new();
@@ -12,14 +32,20 @@ pub fn parse_html<R>(html: &str, generator: &mut impl tree_generation::RenderGen
     tree_generation::create_elements(x.get_nodes(), generator)
 }
 
-pub fn parse_html_new(html: &str) -> Rc<tree::TreeNode<tree_generation_2::HTMLNode>> {
+pub fn parse_html_new(html: &str) -> document::Document {
     let mut x = tag_parse::TagParser::new();
     x.parse(html);
-    tree_generation_2::create_node_tree(x.get_nodes())
+    tree_generation_2::create_document(x.get_nodes())
 }
 
-pub fn parse_css(css: &str) -> Vec<Rc<css_parse::CSSBlock>> {
-    css_parse::create_blocks(css)
+pub fn parse_stylesheet(css: &str) -> css_parse::CSSStyleSheet {
+    css_parse::create_stylesheet(css)
+}
+
+/// A stylesheet with the diagnostics of its source, from a single parse.
+pub fn parse_css(css: &str) -> (css_parse::CSSStyleSheet, Vec<css_lint::Diagnostic>) {
+    let (values, spans, unclosed) = css_parse::parse_component_values_spanned(css);
+    (css_parse::stylesheet_of(&values), css_lint::lint_values(&values, &spans, unclosed))
 }
 
 pub fn parse_css_inner(css: &str) -> css_parse::CSSBlock {
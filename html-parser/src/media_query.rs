@@ -0,0 +1,481 @@
+use std::rc::Rc;
+use super::css_parse::{ComponentValue, CSSRule};
+use super::css_serialize::{self, Format};
+use super::css_supports;
+use super::css_tokenize::CSSToken;
+use super::css_value::LengthUnit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+	Light,
+	Dark
+}
+
+/// What media queries and `@supports` conditions are evaluated against. Lengths are in CSS pixels, `resolution` in
+/// dppx.
+#[derive(Debug, Clone)]
+pub struct MediaEnvironment {
+	pub media_type: String,
+	pub width: f32,
+	pub height: f32,
+	pub resolution: f32,
+	pub color_scheme: ColorScheme,
+	/// Whether a declaration in an `@supports` condition is understood.
+	pub supports_declaration: fn(&Rc<CSSRule>) -> bool
+}
+impl MediaEnvironment {
+	pub fn screen(width: f32, height: f32, resolution: f32) -> MediaEnvironment {
+		MediaEnvironment {
+			media_type: String::from("screen"),
+			width, height, resolution,
+			color_scheme: ColorScheme::Light,
+			supports_declaration: css_supports::is_supported_declaration
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOp {
+	Lt,
+	Le,
+	Eq,
+	Ge,
+	Gt
+}
+impl RangeOp {
+	/// The operator with its operands swapped, so `10px < width` becomes `width > 10px`.
+	fn flip(self) -> RangeOp {
+		match self {
+			RangeOp::Lt => RangeOp::Gt,
+			RangeOp::Le => RangeOp::Ge,
+			RangeOp::Eq => RangeOp::Eq,
+			RangeOp::Ge => RangeOp::Le,
+			RangeOp::Gt => RangeOp::Lt
+		}
+	}
+
+	fn compare(self, left: f32, right: f32) -> bool {
+		match self {
+			RangeOp::Lt => left < right,
+			RangeOp::Le => left <= right,
+			RangeOp::Eq => left == right,
+			RangeOp::Ge => left >= right,
+			RangeOp::Gt => left > right
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+	/// Lengths are converted to pixels, with `em` and `rem` taken as 16px.
+	Length(f32),
+	Number(f32),
+	Ratio(f32),
+	/// Dots per `px`.
+	Resolution(f32),
+	Ident(String)
+}
+impl MediaValue {
+	fn as_number(&self) -> Option<f32> {
+		match self {
+			MediaValue::Length(value) | MediaValue::Number(value) | MediaValue::Ratio(value) | MediaValue::Resolution(value) => Some(*value),
+			MediaValue::Ident(_) => None
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+	Boolean(String),
+	Discrete(String, MediaValue),
+	/// A feature compared against one or two values, `min-`/`max-` prefixes are turned into ranges.
+	Range(String, Vec<(RangeOp, MediaValue)>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+	Feature(MediaFeature),
+	Not(Box<MediaCondition>),
+	And(Vec<MediaCondition>),
+	Or(Vec<MediaCondition>),
+	/// Syntax that parses as `<general-enclosed>`, which always evaluates to unknown.
+	Unknown
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+	negated: bool,
+	media_type: Option<String>,
+	condition: Option<MediaCondition>
+}
+
+/// A comma separated list of media queries, which matches if any query does. An empty list always matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList {
+	queries: Vec<MediaQuery>,
+	/// What the list was parsed from, as the parsed queries don't keep units or unknown syntax.
+	source: Vec<ComponentValue>
+}
+
+const RANGE_FEATURES: [&str; 4] = ["width", "height", "aspect-ratio", "resolution"];
+const DISCRETE_FEATURES: [&str; 2] = ["orientation", "prefers-color-scheme"];
+
+fn ident(value: Option<&&ComponentValue>) -> Option<String> {
+	match value {
+		Some(ComponentValue::Token(CSSToken::Ident(ident))) => Some(ident.to_ascii_lowercase()),
+		_ => None
+	}
+}
+
+fn parse_value(values: &[&ComponentValue]) -> Option<MediaValue> {
+	Some(match values {
+		[ComponentValue::Token(CSSToken::Number { value, .. })] => MediaValue::Number(*value),
+		[ComponentValue::Token(CSSToken::Dimension { value, unit, .. })] => match unit.to_ascii_lowercase().as_str() {
+			"dppx" | "x" => MediaValue::Resolution(*value),
+			"dpi" => MediaValue::Resolution(*value / 96.0),
+			"dpcm" => MediaValue::Resolution(*value * 2.54 / 96.0),
+			"em" | "rem" => MediaValue::Length(*value * 16.0),
+			unit => MediaValue::Length(LengthUnit::from_unit(unit)?.to_px(*value)?)
+		},
+		[ComponentValue::Token(CSSToken::Ident(ident))] => MediaValue::Ident(ident.to_ascii_lowercase()),
+		[ComponentValue::Token(CSSToken::Number { value: left, .. }), ComponentValue::Token(CSSToken::Delim('/')), ComponentValue::Token(CSSToken::Number { value: right, .. })] => MediaValue::Ratio(left / right),
+		_ => return None
+	})
+}
+
+/// Splits `values` around range operators, returning the operands and the operators between them.
+fn split_range<'a>(values: &[&'a ComponentValue]) -> (Vec<Vec<&'a ComponentValue>>, Vec<RangeOp>) {
+	let mut operands = vec![vec![]];
+	let mut ops = vec![];
+	let mut idx = 0;
+
+	while idx < values.len() {
+		let op = match values[idx] {
+			ComponentValue::Token(CSSToken::Delim(ch @ ('<' | '>' | '='))) => *ch,
+			value => {
+				operands.last_mut().unwrap().push(value);
+				idx += 1;
+				continue;
+			}
+		};
+
+		let or_equal = op != '=' && values.get(idx + 1) == Some(&&ComponentValue::Token(CSSToken::Delim('=')));
+		ops.push(match (op, or_equal) {
+			('<', false) => RangeOp::Lt,
+			('<', true) => RangeOp::Le,
+			('>', false) => RangeOp::Gt,
+			('>', true) => RangeOp::Ge,
+			_ => RangeOp::Eq
+		});
+		operands.push(vec![]);
+		idx += if or_equal { 2 } else { 1 };
+	}
+
+	(operands, ops)
+}
+
+fn parse_feature(values: &[&ComponentValue]) -> Option<MediaFeature> {
+	if let [name] = values {
+		return Some(MediaFeature::Boolean(ident(Some(name))?));
+	}
+
+	if let [name, ComponentValue::Token(CSSToken::Colon), value @ ..] = values {
+		let name = ident(Some(name))?;
+		let value = parse_value(value)?;
+		let (prefix, feature) = match name.split_once('-') {
+			Some((prefix @ ("min" | "max"), feature)) => (Some(prefix), String::from(feature)),
+			_ => (None, name)
+		};
+
+		return match prefix {
+			_ if DISCRETE_FEATURES.contains(&feature.as_str()) && prefix.is_none() => Some(MediaFeature::Discrete(feature, value)),
+			Some("min") => Some(MediaFeature::Range(feature, vec![(RangeOp::Ge, value)])),
+			Some(_) => Some(MediaFeature::Range(feature, vec![(RangeOp::Le, value)])),
+			None => Some(MediaFeature::Range(feature, vec![(RangeOp::Eq, value)]))
+		};
+	}
+
+	let (operands, ops) = split_range(values);
+	match (&operands[..], &ops[..]) {
+		([left, right], [op]) => {
+			let name = |operand: &Vec<&ComponentValue>| if operand.len() == 1 { ident(operand.first()) } else { None };
+			match (name(left), parse_value(right)) {
+				(Some(name), Some(value)) if !matches!(value, MediaValue::Ident(_)) => Some(MediaFeature::Range(name, vec![(*op, value)])),
+				_ => Some(MediaFeature::Range(name(right)?, vec![(op.flip(), parse_value(left)?)]))
+			}
+		},
+		([low, name, high], [low_op, high_op]) if name.len() == 1 => {
+			let lt = |op: &RangeOp| *op == RangeOp::Lt || *op == RangeOp::Le;
+			let gt = |op: &RangeOp| *op == RangeOp::Gt || *op == RangeOp::Ge;
+			if !((lt(low_op) && lt(high_op)) || (gt(low_op) && gt(high_op))) { return None; }
+			Some(MediaFeature::Range(ident(name.first())?, vec![(low_op.flip(), parse_value(low)?), (*high_op, parse_value(high)?)]))
+		},
+		_ => None
+	}
+}
+
+fn parse_in_parens(value: &ComponentValue) -> Option<MediaCondition> {
+	match value {
+		ComponentValue::Block { open: '(', contents } => {
+			let contents: Vec<&ComponentValue> = contents.iter().filter(|value| !value.is_whitespace()).collect();
+			if let Some(condition) = parse_condition(&contents, true) {
+				return Some(condition);
+			}
+
+			Some(parse_feature(&contents).map_or(MediaCondition::Unknown, MediaCondition::Feature))
+		},
+		ComponentValue::Function { .. } => Some(MediaCondition::Unknown),
+		_ => None
+	}
+}
+
+/// Parses a whole condition, `and` and `or` may not be mixed without parentheses.
+fn parse_condition(values: &[&ComponentValue], allow_or: bool) -> Option<MediaCondition> {
+	if ident(values.first()).as_deref() == Some("not") {
+		return match values {
+			[_, value] => Some(MediaCondition::Not(Box::new(parse_in_parens(value)?))),
+			_ => None
+		};
+	}
+
+	let mut conditions = vec![parse_in_parens(values.first()?)?];
+	let mut operator = None;
+	let mut rest = &values[1..];
+	while let [keyword, value, remaining @ ..] = rest {
+		let keyword = ident(Some(keyword))?;
+		if keyword != "and" && !(allow_or && keyword == "or") { return None; }
+		if operator.is_some_and(|operator: &str| operator != keyword) { return None; }
+
+		operator = Some(if keyword == "and" { "and" } else { "or" });
+		conditions.push(parse_in_parens(value)?);
+		rest = remaining;
+	}
+	if !rest.is_empty() { return None; }
+
+	Some(match operator {
+		None => conditions.pop().unwrap(),
+		Some("and") => MediaCondition::And(conditions),
+		Some(_) => MediaCondition::Or(conditions)
+	})
+}
+
+impl MediaQuery {
+	/// A query that never matches, which is what invalid queries become.
+	fn not_all() -> MediaQuery {
+		MediaQuery {
+			negated: true,
+			media_type: None,
+			condition: None
+		}
+	}
+
+	fn parse(values: &[&ComponentValue]) -> MediaQuery {
+		let first = ident(values.first());
+		if values.is_empty() || first.as_deref().is_some_and(|first| ["and", "or", "layer"].contains(&first)) {
+			return MediaQuery::not_all();
+		}
+
+		if first.is_none() || (first.as_deref() == Some("not") && ident(values.get(1)).is_none()) {
+			return match parse_condition(values, true) {
+				Some(condition) => MediaQuery { negated: false, media_type: None, condition: Some(condition) },
+				None => MediaQuery::not_all()
+			};
+		}
+
+		let (negated, rest) = match first.as_deref() {
+			Some("not") => (true, &values[1..]),
+			Some("only") => (false, &values[1..]),
+			_ => (false, values)
+		};
+
+		let media_type = match ident(rest.first()) {
+			Some(media_type) if !["not", "only", "and", "or"].contains(&media_type.as_str()) => media_type,
+			_ => return MediaQuery::not_all()
+		};
+
+		let condition = match &rest[1..] {
+			[] => None,
+			[and, condition @ ..] if ident(Some(and)).as_deref() == Some("and") => match parse_condition(condition, false) {
+				Some(condition) => Some(condition),
+				None => return MediaQuery::not_all()
+			},
+			_ => return MediaQuery::not_all()
+		};
+
+		MediaQuery {
+			negated,
+			media_type: if media_type == "all" { None } else { Some(media_type) },
+			condition
+		}
+	}
+
+	fn matches(&self, environment: &MediaEnvironment) -> bool {
+		let type_matches = self.media_type.as_ref().is_none_or(|media_type| *media_type == environment.media_type);
+		let condition_matches = match &self.condition {
+			Some(condition) => evaluate(condition, environment) == Some(true),
+			None => true
+		};
+
+		(type_matches && condition_matches) != self.negated
+	}
+}
+
+impl MediaQueryList {
+	pub fn parse(source: &[ComponentValue]) -> MediaQueryList {
+		let values: Vec<&ComponentValue> = source.iter().filter(|value| !value.is_whitespace()).collect();
+		let queries = if values.is_empty() { vec![] } else {
+			values.split(|value| **value == ComponentValue::Token(CSSToken::Comma)).map(MediaQuery::parse).collect()
+		};
+
+		MediaQueryList {
+			queries,
+			source: source.to_vec()
+		}
+	}
+
+	pub fn to_css(&self, format: Format) -> String {
+		css_serialize::format_media_queries(&self.source, format)
+	}
+
+	pub fn matches(&self, environment: &MediaEnvironment) -> bool {
+		self.queries.is_empty() || self.queries.iter().any(|query| query.matches(environment))
+	}
+}
+
+/// Three-valued evaluation, `None` is unknown.
+fn evaluate(condition: &MediaCondition, environment: &MediaEnvironment) -> Option<bool> {
+	match condition {
+		MediaCondition::Feature(feature) => evaluate_feature(feature, environment),
+		MediaCondition::Not(condition) => evaluate(condition, environment).map(|result| !result),
+		MediaCondition::And(conditions) => {
+			let results: Vec<Option<bool>> = conditions.iter().map(|condition| evaluate(condition, environment)).collect();
+			if results.contains(&Some(false)) { Some(false) } else if results.contains(&None) { None } else { Some(true) }
+		},
+		MediaCondition::Or(conditions) => {
+			let results: Vec<Option<bool>> = conditions.iter().map(|condition| evaluate(condition, environment)).collect();
+			if results.contains(&Some(true)) { Some(true) } else if results.contains(&None) { None } else { Some(false) }
+		},
+		MediaCondition::Unknown => None
+	}
+}
+
+fn evaluate_feature(feature: &MediaFeature, environment: &MediaEnvironment) -> Option<bool> {
+	let value_of = |name: &str| -> Option<MediaValue> {
+		Some(match name {
+			"width" => MediaValue::Length(environment.width),
+			"height" => MediaValue::Length(environment.height),
+			"aspect-ratio" => MediaValue::Ratio(environment.width / environment.height),
+			"resolution" => MediaValue::Resolution(environment.resolution),
+			"orientation" => MediaValue::Ident(String::from(if environment.height >= environment.width { "portrait" } else { "landscape" })),
+			"prefers-color-scheme" => MediaValue::Ident(String::from(match environment.color_scheme {
+				ColorScheme::Light => "light",
+				ColorScheme::Dark => "dark"
+			})),
+			_ => return None
+		})
+	};
+
+	match feature {
+		MediaFeature::Boolean(name) => Some(match value_of(name)? {
+			MediaValue::Ident(_) => true,
+			value => value.as_number() != Some(0.0)
+		}),
+		MediaFeature::Discrete(name, value) => Some(value_of(name)? == *value),
+		MediaFeature::Range(name, comparisons) => {
+			if !RANGE_FEATURES.contains(&name.as_str()) { return None; }
+			let actual = value_of(name)?.as_number()?;
+			let mut result = true;
+			for (op, value) in comparisons {
+				let expected = match (name.as_str(), value) {
+					("width" | "height", MediaValue::Length(value)) => *value,
+					("width" | "height", MediaValue::Number(value)) if *value == 0.0 => 0.0,
+					("aspect-ratio", MediaValue::Ratio(value) | MediaValue::Number(value)) => *value,
+					("resolution", MediaValue::Resolution(value)) => *value,
+					_ => return None
+				};
+				result &= op.compare(actual, expected);
+			}
+
+			Some(result)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::css_parse::parse_component_values;
+
+	fn list(queries: &str) -> MediaQueryList {
+		MediaQueryList::parse(&parse_component_values(queries))
+	}
+
+	/// Whether `queries` match a light screen of `width` by 600px at 1dppx.
+	fn matches(queries: &str, width: f32) -> bool {
+		list(queries).matches(&MediaEnvironment::screen(width, 600.0, 1.0))
+	}
+
+	#[test]
+	fn media_types() {
+		assert!(matches("", 800.0));
+		assert!(matches("screen", 800.0));
+		assert!(matches("ALL", 800.0));
+		assert!(matches("only screen", 800.0));
+		assert!(!matches("print", 800.0));
+		assert!(matches("not print", 800.0));
+		assert!(matches("print, screen", 800.0));
+		assert!(!matches("not screen and (min-width: 100px)", 800.0));
+	}
+
+	#[test]
+	fn min_and_max_features() {
+		assert!(matches("(min-width: 800px)", 800.0));
+		assert!(!matches("(min-width: 801px)", 800.0));
+		assert!(matches("(max-width: 50em)", 800.0));
+		assert!(!matches("(max-width: 49em)", 800.0));
+		assert!(matches("(width: 800px)", 800.0));
+		assert!(matches("screen and (min-width: 500px) and (max-width: 900px)", 800.0));
+		assert!(matches("(min-aspect-ratio: 4/3)", 800.0));
+		assert!(!matches("(min-aspect-ratio: 16/9)", 800.0));
+		assert!(matches("(min-resolution: 96dpi)", 800.0));
+		assert!(matches("(orientation: landscape)", 800.0));
+		assert!(matches("(orientation: portrait)", 600.0));
+		assert!(matches("(prefers-color-scheme: light)", 800.0));
+	}
+
+	#[test]
+	fn range_syntax() {
+		assert!(matches("(width <= 800px)", 800.0));
+		assert!(!matches("(width < 800px)", 800.0));
+		assert!(matches("(width > 500px)", 800.0));
+		assert!(matches("(500px < width)", 800.0));
+		assert!(!matches("(900px <= width)", 800.0));
+		assert!(matches("(400px <= width <= 800px)", 800.0));
+		assert!(!matches("(400px <= width < 800px)", 800.0));
+		assert!(matches("(1000px > width > 700px)", 800.0));
+		assert!(!matches("(400px < width > 700px)", 800.0));
+		assert_eq!(list("(max-width: 600px)").queries, list("(width <= 600px)").queries);
+	}
+
+	#[test]
+	fn conditions() {
+		assert!(matches("(min-width: 900px) or (orientation: landscape)", 800.0));
+		assert!(matches("not (min-width: 900px)", 800.0));
+		assert!(matches("(not (min-width: 900px)) and (width)", 800.0));
+		assert!(!matches("(width: 0)", 800.0));
+		assert!(matches("(height)", 800.0));
+	}
+
+	/// Unknown features and syntax are unknown, which is false unless negated by `not` around the whole condition.
+	#[test]
+	fn unknown_and_invalid_queries() {
+		assert!(!matches("(hover: hover)", 800.0));
+		assert!(!matches("not (hover: hover)", 800.0));
+		assert!(matches("(hover: hover) or (width > 0px)", 800.0));
+		assert!(!matches("(width > 0px) and (something else)", 800.0));
+		assert!(!matches("screen and (min-width: 1px) or (max-width: 2px)", 800.0));
+		assert!(!matches("and screen", 800.0));
+		assert!(matches("and screen, screen", 800.0));
+		assert!(!matches("(min-width: red)", 800.0));
+	}
+}
@@ -0,0 +1,40 @@
+use tree;
+use super::selector::{MatchingContext, Selector, SelectorParseError};
+use super::tree_generation_2::{HTMLElement, HTMLNode, Namespace};
+use std::rc::Rc;
+use std::str::FromStr;
+
+type Node = tree::TreeNode<HTMLNode>;
+
+/// Every element below `root`, excluding `root` itself, in tree order.
+fn descendant_elements<A>(root: &Rc<Node>, cb: A) -> Vec<Rc<Node>> where A: Fn(&HTMLElement) -> bool {
+	root.clone().find(|node| {
+		!Rc::ptr_eq(&node, root) && match &*node.value.borrow() {
+			HTMLNode::Element(el) => cb(el),
+			_ => false
+		}
+	})
+}
+
+/// `*` matches every element, HTML elements are matched against the lowercased name.
+pub fn get_elements_by_tag_name(root: &Rc<Node>, name: &str) -> Vec<Rc<Node>> {
+	let lowercase = name.to_ascii_lowercase();
+	descendant_elements(root, |el| match el.namespace() {
+		_ if name == "*" => true,
+		Namespace::HTML => *el.tagname() == lowercase,
+		_ => *el.tagname() == name
+	})
+}
+
+pub fn query_selector(root: &Rc<Node>, selectors: &str) -> Result<Option<Rc<Node>>, SelectorParseError> {
+	Ok(query_selector_all(root, selectors)?.into_iter().next())
+}
+
+pub fn query_selector_all(root: &Rc<Node>, selectors: &str) -> Result<Vec<Rc<Node>>, SelectorParseError> {
+	Ok(select_all(root, &Selector::from_str(selectors)?, &MatchingContext::new()))
+}
+
+/// The elements below `root` that `selector` matches, in tree order.
+pub(crate) fn select_all(root: &Rc<Node>, selector: &Selector, context: &MatchingContext) -> Vec<Rc<Node>> {
+	root.clone().find(|node| !Rc::ptr_eq(&node, root) && selector.matches_with(&node, context))
+}
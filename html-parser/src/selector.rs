@@ -0,0 +1,1054 @@
+use tree;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::str::FromStr;
+use super::css_tokenize::{self, CSSToken};
+use super::source_position::{SourcePosition, SourceSpan};
+use super::tree_generation_2::{HTMLElement, HTMLNode, Namespace};
+
+type Node = tree::TreeNode<HTMLNode>;
+
+/// Selector specificity as (ids, classes, types), compared in that order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+impl std::ops::Add for Specificity {
+	type Output = Specificity;
+	fn add(self, other: Specificity) -> Specificity {
+		Specificity(self.0 + other.0, self.1 + other.1, self.2 + other.2)
+	}
+}
+
+/// How an attribute selector compares the value of the attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+	/// `[attr]`
+	Exists,
+	/// `[attr=value]`
+	Equals,
+	/// `[attr~=value]`, one of the whitespace separated words.
+	Includes,
+	/// `[attr|=value]`, exactly `value` or starting with `value-`.
+	DashMatch,
+	/// `[attr^=value]`
+	Prefix,
+	/// `[attr$=value]`
+	Suffix,
+	/// `[attr*=value]`
+	Substring
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseSensitivity {
+	/// Without a flag, which HTML makes case-insensitive for a fixed list of attributes.
+	Default,
+	/// The `i` flag.
+	Insensitive,
+	/// The `s` flag.
+	Sensitive
+}
+
+/// The attributes HTML compares case-insensitively in attribute selectors without a flag.
+const CASE_INSENSITIVE_ATTRIBUTES: [&str; 46] = [
+	"accept", "accept-charset", "align", "alink", "axis", "bgcolor", "charset", "checked", "clear", "codetype", "color",
+	"compact", "declare", "defer", "dir", "direction", "disabled", "enctype", "face", "frame", "hreflang", "http-equiv",
+	"lang", "language", "link", "media", "method", "multiple", "nohref", "noresize", "noshade", "nowrap", "readonly",
+	"rel", "rev", "rules", "scope", "scrolling", "selected", "shape", "target", "text", "type", "valign", "valuetype",
+	"vlink"
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeSelector {
+	pub name: String,
+	/// `[*|name]`, which matches the attribute in any namespace rather than only without one.
+	pub any_namespace: bool,
+	pub operator: AttributeOperator,
+	pub value: String,
+	pub case: CaseSensitivity
+}
+impl AttributeSelector {
+	fn matches_value(&self, value: &str, insensitive: bool) -> bool {
+		let (value, expected) = if insensitive {
+			(value.to_ascii_lowercase(), self.value.to_ascii_lowercase())
+		} else {
+			(String::from(value), self.value.clone())
+		};
+
+		match self.operator {
+			AttributeOperator::Exists => true,
+			AttributeOperator::Equals => value == expected,
+			AttributeOperator::Includes => !expected.is_empty() && !expected.contains(|ch: char| ch.is_ascii_whitespace()) && value.split_ascii_whitespace().any(|word| word == expected),
+			AttributeOperator::DashMatch => value == expected || value.strip_prefix(expected.as_str()).is_some_and(|rest| rest.starts_with('-')),
+			AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+			AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+			AttributeOperator::Substring => !expected.is_empty() && value.contains(&expected)
+		}
+	}
+
+	/// Attribute names of HTML elements match case-insensitively.
+	fn matches(&self, element: &HTMLElement) -> bool {
+		let html = element.namespace() == Namespace::HTML;
+		element.attributes().iter()
+			.filter(|attr| (self.any_namespace || attr.namespace().is_none()) && if html { attr.local_name().eq_ignore_ascii_case(&self.name) } else { attr.local_name() == self.name })
+			.any(|attr| {
+				let insensitive = match self.case {
+					CaseSensitivity::Insensitive => true,
+					CaseSensitivity::Sensitive => false,
+					CaseSensitivity::Default => html && attr.namespace().is_none() && CASE_INSENSITIVE_ATTRIBUTES.contains(&attr.local_name())
+				};
+				self.matches_value(attr.value(), insensitive)
+			})
+	}
+}
+
+/// `An+B`, which matches the 1-based indices `A*n + B` for any `n >= 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnPlusB(pub i32, pub i32);
+impl AnPlusB {
+	/// Parses `odd`, `even`, `3`, `-n+3`, `2n - 1` and the like. Whitespace is only allowed around the sign of B.
+	pub fn parse(text: &str) -> Option<AnPlusB> {
+		let text = text.trim().to_ascii_lowercase();
+		match text.as_str() {
+			"odd" => return Some(AnPlusB(2, 1)),
+			"even" => return Some(AnPlusB(2, 0)),
+			_ => {}
+		}
+
+		let integer = |digits: &str, signed: bool| -> Option<i32> {
+			let unsigned = if signed { digits.strip_prefix(['+', '-']).unwrap_or(digits) } else { digits };
+			if unsigned.is_empty() || !unsigned.chars().all(|ch| ch.is_ascii_digit()) { return None; }
+			digits.parse().ok()
+		};
+
+		let (a, rest) = match text.split_once('n') {
+			Some((a, rest)) => (match a {
+				"" | "+" => 1,
+				"-" => -1,
+				a => integer(a, true)?
+			}, rest.trim_start()),
+			None => return Some(AnPlusB(0, integer(&text, true)?))
+		};
+		if rest.is_empty() { return Some(AnPlusB(a, 0)); }
+
+		let (negative, b) = match rest.strip_prefix('+') {
+			Some(b) => (false, b),
+			None => (true, rest.strip_prefix('-')?)
+		};
+		let b = integer(b.trim_start(), false)?;
+		Some(AnPlusB(a, if negative { -b } else { b }))
+	}
+
+	pub fn matches(&self, index: usize) -> bool {
+		let offset = index as i32 - self.1;
+		match self.0 {
+			0 => offset == 0,
+			a => offset % a == 0 && offset / a >= 0
+		}
+	}
+}
+
+/// An argument of `:has()`, like `> img`, which is matched relative to the element with the `:has()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelativeSelector {
+	pub combinator: Combinator,
+	pub selector: ComplexSelector
+}
+impl RelativeSelector {
+	/// Matches left to right from `anchor`, so every step only depends on the element it is at and is cached. Checking
+	/// every element of a tree then searches it once for each compound, instead of searching each subtree again.
+	fn matches(self: &Rc<Self>, anchor: &Rc<Node>, context: &MatchingContext) -> bool {
+		self.reaches(0, anchor, context)
+	}
+
+	/// Whether the combinator before compound `idx` leads from `node` to an element that matches the rest of the
+	/// selector from `idx` on.
+	fn reaches(self: &Rc<Self>, idx: usize, node: &Rc<Node>, context: &MatchingContext) -> bool {
+		let key = HasKey(self.clone(), idx, node.clone());
+		let cached = context.has.borrow().get(&key).copied();
+		if let Some(matched) = cached { return matched; }
+
+		let combinator = if idx == 0 { self.combinator } else { self.selector.combinators[idx - 1] };
+		let matched = match combinator {
+			Combinator::Descendant => element_children(node).iter().any(|child| self.matches_from(idx, child, context) || self.reaches(idx, child, context)),
+			Combinator::Child => element_children(node).iter().any(|child| self.matches_from(idx, child, context)),
+			Combinator::NextSibling => element_siblings(node, true).first().is_some_and(|sibling| self.matches_from(idx, sibling, context)),
+			Combinator::SubsequentSibling => element_siblings(node, true).first().is_some_and(|sibling| self.matches_from(idx, sibling, context) || self.reaches(idx, sibling, context))
+		};
+		context.has.borrow_mut().insert(key, matched);
+		matched
+	}
+
+	/// Whether `node` matches compound `idx` and the compounds after it can be reached from it.
+	fn matches_from(self: &Rc<Self>, idx: usize, node: &Rc<Node>, context: &MatchingContext) -> bool {
+		let matched = match &*node.value.borrow() {
+			HTMLNode::Element(el) => self.selector.compounds[idx].matches(node, el, context),
+			_ => false
+		};
+		matched && (idx + 1 == self.selector.compounds.len() || self.reaches(idx + 1, node, context))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectorComponent {
+	/// `*`
+	Universal,
+	TagName(String),
+	Id(String),
+	Class(String),
+	Attribute(AttributeSelector),
+	Root,
+	/// Elements without element children or text.
+	Empty,
+	FirstChild,
+	LastChild,
+	OnlyChild,
+	/// `:nth-child(An+B of S)`, where `of S` only counts the siblings that match `S`.
+	NthChild(AnPlusB, Option<Selector>),
+	NthLastChild(AnPlusB, Option<Selector>),
+	NthOfType(AnPlusB),
+	NthLastOfType(AnPlusB),
+	Not(Selector),
+	/// `:is()`, which drops the selectors in it that don't parse instead of failing.
+	Is(Selector),
+	/// Like `:is()`, without specificity.
+	Where(Selector),
+	Has(Vec<Rc<RelativeSelector>>)
+}
+impl SelectorComponent {
+	fn matches(&self, node: &Rc<Node>, element: &HTMLElement, context: &MatchingContext) -> bool {
+		let same_type = |sibling: &Rc<Node>| match &*sibling.value.borrow() {
+			HTMLNode::Element(el) => el.tagname() == element.tagname() && el.namespace() == element.namespace(),
+			_ => false
+		};
+		let index = |following: bool, counted: &dyn Fn(&Rc<Node>) -> bool| element_siblings(node, following).iter().filter(|sibling| counted(sibling)).count() + 1;
+
+		match self {
+			SelectorComponent::Universal => true,
+			SelectorComponent::TagName(name) if element.namespace() == Namespace::HTML => element.tagname().eq_ignore_ascii_case(name),
+			SelectorComponent::TagName(name) => element.is_tag(name),
+			SelectorComponent::Id(id) => element.id() == Some(id.as_str()),
+			SelectorComponent::Class(name) => element.class_list().contains(name),
+			SelectorComponent::Attribute(attribute) => attribute.matches(element),
+			SelectorComponent::Root => node.get_parent().is_some_and(|parent| matches!(&*parent.value.borrow(), HTMLNode::Root)),
+			SelectorComponent::Empty => (0..node.child_count()).filter_map(|idx| node.get_child(idx)).all(|child| match &*child.value.borrow() {
+				HTMLNode::Element(_) => false,
+				HTMLNode::Text(text, _) => text.is_empty(),
+				_ => true
+			}),
+			SelectorComponent::FirstChild => element_siblings(node, false).is_empty(),
+			SelectorComponent::LastChild => element_siblings(node, true).is_empty(),
+			SelectorComponent::OnlyChild => element_siblings(node, false).is_empty() && element_siblings(node, true).is_empty(),
+			SelectorComponent::NthChild(nth, selector) | SelectorComponent::NthLastChild(nth, selector) => {
+				let following = matches!(self, SelectorComponent::NthLastChild(..));
+				match selector {
+					Some(selector) => selector.matches_with(node, context) && nth.matches(index(following, &|sibling| selector.matches_with(sibling, context))),
+					None => nth.matches(index(following, &|_| true))
+				}
+			},
+			SelectorComponent::NthOfType(nth) => nth.matches(index(false, &same_type)),
+			SelectorComponent::NthLastOfType(nth) => nth.matches(index(true, &same_type)),
+			SelectorComponent::Not(selector) => !selector.matches_with(node, context),
+			SelectorComponent::Is(selector) | SelectorComponent::Where(selector) => selector.matches_with(node, context),
+			SelectorComponent::Has(selectors) => selectors.iter().any(|selector| selector.matches(node, context))
+		}
+	}
+
+	/// Pseudo-classes count as classes, except that the logical ones take the specificity of their most specific
+	/// argument.
+	fn specificity(&self) -> Specificity {
+		match self {
+			SelectorComponent::Universal | SelectorComponent::Where(_) => Specificity(0, 0, 0),
+			SelectorComponent::TagName(_) => Specificity(0, 0, 1),
+			SelectorComponent::Id(_) => Specificity(1, 0, 0),
+			SelectorComponent::NthChild(_, Some(selector)) | SelectorComponent::NthLastChild(_, Some(selector)) => Specificity(0, 1, 0) + selector.specificity(),
+			SelectorComponent::Not(selector) | SelectorComponent::Is(selector) => selector.specificity(),
+			SelectorComponent::Has(selectors) => selectors.iter().map(|selector| selector.selector.specificity()).max().unwrap_or_default(),
+			_ => Specificity(0, 1, 0)
+		}
+	}
+}
+
+/// Simple selectors that must all match the same element, like `input.large[type=text]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundSelector {
+	components: Vec<SelectorComponent>
+}
+impl CompoundSelector {
+	pub fn new(components: Vec<SelectorComponent>) -> CompoundSelector {
+		CompoundSelector {
+			components
+		}
+	}
+
+	pub fn components(&self) -> &[SelectorComponent] {
+		&self.components
+	}
+
+	fn matches(&self, node: &Rc<Node>, element: &HTMLElement, context: &MatchingContext) -> bool {
+		self.components.iter().all(|component| component.matches(node, element, context))
+	}
+
+	pub fn specificity(&self) -> Specificity {
+		self.components.iter().fold(Specificity::default(), |total, component| total + component.specificity())
+	}
+
+	/// An id the compound requires, or else a class, so only elements that have it need to be checked.
+	pub fn index_key(&self) -> Option<&SelectorComponent> {
+		let id = self.components.iter().find(|component| matches!(component, SelectorComponent::Id(_)));
+		id.or_else(|| self.components.iter().find(|component| matches!(component, SelectorComponent::Class(_))))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+	/// `a b`
+	Descendant,
+	/// `a > b`
+	Child,
+	/// `a + b`
+	NextSibling,
+	/// `a ~ b`
+	SubsequentSibling
+}
+
+fn is_element(node: &Rc<Node>) -> bool {
+	matches!(&*node.value.borrow(), HTMLNode::Element(_))
+}
+
+fn element_parent(node: &Rc<Node>) -> Option<Rc<Node>> {
+	node.get_parent().filter(is_element)
+}
+
+fn element_children(node: &Rc<Node>) -> Vec<Rc<Node>> {
+	(0..node.child_count()).filter_map(|idx| node.get_child(idx)).filter(is_element).collect()
+}
+
+/// The element siblings before `node`, or after it when `following`, closest first.
+fn element_siblings(node: &Rc<Node>, following: bool) -> Vec<Rc<Node>> {
+	let parent = match node.get_parent() {
+		Some(parent) => parent,
+		None => return vec![]
+	};
+	let idx = parent.index_of(node).unwrap();
+	let indices: Vec<usize> = if following { (idx + 1..parent.child_count()).collect() } else { (0..idx).rev().collect() };
+	indices.into_iter().filter_map(|idx| parent.get_child(idx)).filter(is_element).collect()
+}
+
+/// Whether an element that `combinator` leads to from `node`, towards its ancestors or previous siblings, passes `cb`.
+fn combined<A>(node: &Rc<Node>, combinator: Combinator, cb: A) -> bool where A: Fn(&Rc<Node>) -> bool {
+	match combinator {
+		Combinator::Descendant => {
+			let mut ancestor = element_parent(node);
+			while let Some(current) = ancestor {
+				if cb(&current) { return true; }
+				ancestor = element_parent(&current);
+			}
+			false
+		},
+		Combinator::Child => element_parent(node).is_some_and(|parent| cb(&parent)),
+		Combinator::NextSibling => element_siblings(node, false).first().is_some_and(cb),
+		Combinator::SubsequentSibling => element_siblings(node, false).iter().any(cb)
+	}
+}
+
+/// Compound selectors joined by combinators, like `nav > ul li`. The last compound is the subject, the element the
+/// selector matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComplexSelector {
+	compounds: Vec<CompoundSelector>,
+	/// The combinator between each compound and the next, one fewer than there are compounds.
+	combinators: Vec<Combinator>
+}
+impl ComplexSelector {
+	pub fn new(compounds: Vec<CompoundSelector>, combinators: Vec<Combinator>) -> ComplexSelector {
+		ComplexSelector {
+			compounds, combinators
+		}
+	}
+
+	pub fn compounds(&self) -> &[CompoundSelector] {
+		&self.compounds
+	}
+
+	pub fn combinators(&self) -> &[Combinator] {
+		&self.combinators
+	}
+
+	/// The last compound, which the matched element itself must match.
+	pub fn subject(&self) -> &CompoundSelector {
+		self.compounds.last().unwrap()
+	}
+
+	/// Matches right to left, from compound `idx` at `node` towards its ancestors and previous siblings.
+	fn matches_from(&self, idx: usize, node: &Rc<Node>, context: &MatchingContext) -> bool {
+		let matched = match &*node.value.borrow() {
+			HTMLNode::Element(el) => self.compounds[idx].matches(node, el, context),
+			_ => false
+		};
+		matched && (idx == 0 || combined(node, self.combinators[idx - 1], |other| self.matches_from(idx - 1, other, context)))
+	}
+
+	pub fn matches_with(&self, node: &Rc<Node>, context: &MatchingContext) -> bool {
+		self.matches_from(self.compounds.len() - 1, node, context)
+	}
+
+	pub fn matches(&self, node: &Rc<Node>) -> bool {
+		self.matches_with(node, &MatchingContext::new())
+	}
+
+	pub fn specificity(&self) -> Specificity {
+		self.compounds.iter().fold(Specificity::default(), |total, compound| total + compound.specificity())
+	}
+}
+
+/// A step of a `:has()` match: compound `.1` of the selector, reached from the element. Both are compared by address
+/// and held by the key, so neither can be freed and its address reused by another while the result is cached.
+#[derive(Debug)]
+struct HasKey(Rc<RelativeSelector>, usize, Rc<Node>);
+impl PartialEq for HasKey {
+	fn eq(&self, other: &HasKey) -> bool {
+		Rc::ptr_eq(&self.0, &other.0) && self.1 == other.1 && Rc::ptr_eq(&self.2, &other.2)
+	}
+}
+impl Eq for HasKey {}
+impl Hash for HasKey {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		Rc::as_ptr(&self.0).hash(state);
+		self.1.hash(state);
+		Rc::as_ptr(&self.2).hash(state);
+	}
+}
+
+/// State shared by the matches of one pass over a tree, which must not change during it. `:has()` results are kept for
+/// each element, so its subtree is only searched once.
+#[derive(Debug, Default)]
+pub struct MatchingContext {
+	has: RefCell<HashMap<HasKey, bool>>
+}
+impl MatchingContext {
+	pub fn new() -> MatchingContext {
+		MatchingContext::default()
+	}
+}
+
+/// A comma separated selector list, which matches the elements matched by any of its selectors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+	selectors: Vec<ComplexSelector>
+}
+impl Selector {
+	pub fn new(selectors: Vec<ComplexSelector>) -> Selector {
+		Selector {
+			selectors
+		}
+	}
+
+	pub fn selectors(&self) -> &[ComplexSelector] {
+		&self.selectors
+	}
+
+	pub fn matches_with(&self, node: &Rc<Node>, context: &MatchingContext) -> bool {
+		self.selectors.iter().any(|selector| selector.matches_with(node, context))
+	}
+
+	pub fn matches(&self, node: &Rc<Node>) -> bool {
+		self.matches_with(node, &MatchingContext::new())
+	}
+
+	/// The specificity of the most specific selector in the list that matches `node`, `None` if none does.
+	pub fn match_specificity(&self, node: &Rc<Node>, context: &MatchingContext) -> Option<Specificity> {
+		self.selectors.iter().filter(|selector| selector.matches_with(node, context)).map(ComplexSelector::specificity).max()
+	}
+
+	/// The specificity of the most specific selector in the list.
+	pub fn specificity(&self) -> Specificity {
+		self.selectors.iter().map(ComplexSelector::specificity).max().unwrap_or_default()
+	}
+}
+
+/// Why a selector is invalid, and where in it parsing stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorParseError {
+	pub position: SourcePosition,
+	pub reason: &'static str
+}
+impl fmt::Display for SelectorParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} at line {}, column {}", self.reason, self.position.line, self.position.column)
+	}
+}
+
+type SpannedTokens = Vec<(CSSToken, SourceSpan)>;
+
+/// Splits `tokens` at the commas outside of any parentheses or brackets.
+fn split_commas(tokens: SpannedTokens) -> Vec<SpannedTokens> {
+	let mut parts = vec![vec![]];
+	let mut depth = 0usize;
+	for (token, span) in tokens {
+		match token {
+			CSSToken::Function(_) | CSSToken::OpenParen | CSSToken::OpenSquare => depth += 1,
+			CSSToken::CloseParen | CSSToken::CloseSquare => depth = depth.saturating_sub(1),
+			CSSToken::Comma if depth == 0 => {
+				parts.push(vec![]);
+				continue;
+			},
+			_ => {}
+		}
+		parts.last_mut().unwrap().push((token, span));
+	}
+
+	parts
+}
+
+struct Parser {
+	tokens: SpannedTokens,
+	idx: usize,
+	/// Where errors past the last token are reported, the closing parenthesis for the arguments of a pseudo-class.
+	end: SourcePosition,
+	/// `:has()` can't be nested.
+	in_has: bool
+}
+impl Parser {
+	fn new(tokens: SpannedTokens) -> Parser {
+		let end = tokens.last().map_or_else(SourcePosition::new, |(_, span)| span.end);
+		Parser {
+			tokens, idx: 0, end, in_has: false
+		}
+	}
+
+	fn nested(&self, tokens: SpannedTokens, end: SourcePosition, in_has: bool) -> Parser {
+		Parser {
+			tokens, idx: 0, end, in_has: self.in_has || in_has
+		}
+	}
+
+	fn peek_at(&self, offset: usize) -> Option<&CSSToken> {
+		self.tokens.get(self.idx + offset).map(|(token, _)| token)
+	}
+
+	fn peek(&self) -> Option<&CSSToken> {
+		self.peek_at(0)
+	}
+
+	fn skip_whitespace(&mut self) {
+		while self.peek() == Some(&CSSToken::Whitespace) { self.idx += 1; }
+	}
+
+	/// An error at the start of the next token, or the end of the input if there is none.
+	fn error(&self, reason: &'static str) -> SelectorParseError {
+		let position = self.tokens.get(self.idx).map_or(self.end, |(_, span)| span.start);
+		SelectorParseError { position, reason }
+	}
+
+	fn selector_list(&mut self) -> Result<Vec<ComplexSelector>, SelectorParseError> {
+		let mut selectors = vec![];
+		loop {
+			self.skip_whitespace();
+			selectors.push(self.complex_selector()?);
+			match self.peek() {
+				None => return Ok(selectors),
+				Some(CSSToken::Comma) => self.idx += 1,
+				Some(_) => return Err(self.error("expected `,` or the end of the selector"))
+			}
+		}
+	}
+
+	/// The selectors of a forgiving list that parse on their own.
+	fn forgiving_selector_list(&self, tokens: SpannedTokens, end: SourcePosition) -> Vec<ComplexSelector> {
+		split_commas(tokens).into_iter().filter_map(|part| {
+			let mut parser = self.nested(part, end, false);
+			parser.skip_whitespace();
+			parser.complex_selector().ok().filter(|_| parser.peek().is_none())
+		}).collect()
+	}
+
+	fn relative_selector_list(&mut self) -> Result<Vec<Rc<RelativeSelector>>, SelectorParseError> {
+		let mut selectors = vec![];
+		loop {
+			self.skip_whitespace();
+			let combinator = match self.peek() {
+				Some(CSSToken::Delim('>')) => Combinator::Child,
+				Some(CSSToken::Delim('+')) => Combinator::NextSibling,
+				Some(CSSToken::Delim('~')) => Combinator::SubsequentSibling,
+				_ => Combinator::Descendant
+			};
+			if combinator != Combinator::Descendant {
+				self.idx += 1;
+				self.skip_whitespace();
+			}
+
+			selectors.push(Rc::new(RelativeSelector { combinator, selector: self.complex_selector()? }));
+			match self.peek() {
+				None => return Ok(selectors),
+				Some(CSSToken::Comma) => self.idx += 1,
+				Some(_) => return Err(self.error("expected `,` or the end of the selector"))
+			}
+		}
+	}
+
+	/// Compound selectors up to the next `,` or the end. Whitespace is only a descendant combinator when no other
+	/// combinator follows it.
+	fn complex_selector(&mut self) -> Result<ComplexSelector, SelectorParseError> {
+		let mut compounds = vec![self.compound_selector()?];
+		let mut combinators = vec![];
+		loop {
+			let whitespace = self.peek() == Some(&CSSToken::Whitespace);
+			self.skip_whitespace();
+			let combinator = match self.peek() {
+				None | Some(CSSToken::Comma) => return Ok(ComplexSelector::new(compounds, combinators)),
+				Some(CSSToken::Delim('>')) => Combinator::Child,
+				Some(CSSToken::Delim('+')) => Combinator::NextSibling,
+				Some(CSSToken::Delim('~')) => Combinator::SubsequentSibling,
+				Some(_) if whitespace => Combinator::Descendant,
+				Some(_) => return Err(self.error("expected a combinator, `,` or the end of the selector"))
+			};
+			if combinator != Combinator::Descendant {
+				self.idx += 1;
+				self.skip_whitespace();
+			}
+
+			combinators.push(combinator);
+			compounds.push(self.compound_selector()?);
+		}
+	}
+
+	/// `*|` is the only namespace prefix allowed, as there is no `@namespace` to declare others.
+	fn type_selector(&mut self) -> Result<Option<SelectorComponent>, SelectorParseError> {
+		match (self.peek(), self.peek_at(1)) {
+			(Some(CSSToken::Delim('*')), Some(CSSToken::Delim('|'))) => {
+				self.idx += 2;
+				if !matches!(self.peek(), Some(CSSToken::Ident(_)) | Some(CSSToken::Delim('*'))) {
+					return Err(self.error("expected an element name"));
+				}
+			},
+			(Some(CSSToken::Delim('|')), _) | (Some(CSSToken::Ident(_)), Some(CSSToken::Delim('|'))) => return Err(self.error("undeclared namespace prefix")),
+			_ => {}
+		}
+
+		let component = match self.peek() {
+			Some(CSSToken::Ident(name)) => SelectorComponent::TagName(name.clone()),
+			Some(CSSToken::Delim('*')) => SelectorComponent::Universal,
+			_ => return Ok(None)
+		};
+		self.idx += 1;
+		Ok(Some(component))
+	}
+
+	fn attribute_selector(&mut self) -> Result<AttributeSelector, SelectorParseError> {
+		self.idx += 1;
+		self.skip_whitespace();
+
+		let mut any_namespace = false;
+		match (self.peek(), self.peek_at(1), self.peek_at(2)) {
+			(Some(CSSToken::Delim('*')), Some(CSSToken::Delim('|')), _) => {
+				any_namespace = true;
+				self.idx += 2;
+			},
+			(Some(CSSToken::Delim('|')), Some(CSSToken::Ident(_)), _) => self.idx += 1,
+			(Some(CSSToken::Ident(_)), Some(CSSToken::Delim('|')), next) if next != Some(&CSSToken::Delim('=')) => return Err(self.error("undeclared namespace prefix")),
+			_ => {}
+		}
+
+		let name = match self.peek() {
+			Some(CSSToken::Ident(name)) => name.clone(),
+			_ => return Err(self.error("expected an attribute name"))
+		};
+		self.idx += 1;
+		self.skip_whitespace();
+
+		let operator = match (self.peek(), self.peek_at(1)) {
+			(Some(CSSToken::CloseSquare), _) => {
+				self.idx += 1;
+				return Ok(AttributeSelector { name, any_namespace, operator: AttributeOperator::Exists, value: String::new(), case: CaseSensitivity::Default });
+			},
+			(Some(CSSToken::Delim('=')), _) => AttributeOperator::Equals,
+			(Some(CSSToken::Delim(ch)), Some(CSSToken::Delim('='))) => match ch {
+				'~' => AttributeOperator::Includes,
+				'|' => AttributeOperator::DashMatch,
+				'^' => AttributeOperator::Prefix,
+				'$' => AttributeOperator::Suffix,
+				'*' => AttributeOperator::Substring,
+				_ => return Err(self.error("expected an attribute operator"))
+			},
+			_ => return Err(self.error("expected an attribute operator or `]`"))
+		};
+		self.idx += if operator == AttributeOperator::Equals { 1 } else { 2 };
+		self.skip_whitespace();
+
+		let value = match self.peek() {
+			Some(CSSToken::Ident(value)) | Some(CSSToken::String(value)) => value.clone(),
+			_ => return Err(self.error("expected an identifier or string as the attribute value"))
+		};
+		self.idx += 1;
+		self.skip_whitespace();
+
+		let case = match self.peek() {
+			Some(CSSToken::Ident(flag)) if flag.eq_ignore_ascii_case("i") => CaseSensitivity::Insensitive,
+			Some(CSSToken::Ident(flag)) if flag.eq_ignore_ascii_case("s") => CaseSensitivity::Sensitive,
+			Some(CSSToken::Ident(_)) => return Err(self.error("unknown attribute selector flag")),
+			_ => CaseSensitivity::Default
+		};
+		if case != CaseSensitivity::Default {
+			self.idx += 1;
+			self.skip_whitespace();
+		}
+
+		if self.peek() != Some(&CSSToken::CloseSquare) { return Err(self.error("expected `]`")); }
+		self.idx += 1;
+		Ok(AttributeSelector { name, any_namespace, operator, value, case })
+	}
+
+	/// Consumes a function token and its arguments, returning the arguments and where the closing parenthesis is.
+	fn arguments(&mut self) -> (SpannedTokens, SourcePosition) {
+		self.idx += 1;
+		let start = self.idx;
+		let mut depth = 0usize;
+		while let Some((token, span)) = self.tokens.get(self.idx) {
+			match token {
+				CSSToken::Function(_) | CSSToken::OpenParen => depth += 1,
+				CSSToken::CloseParen if depth == 0 => {
+					self.idx += 1;
+					return (self.tokens[start..self.idx - 1].to_vec(), span.start);
+				},
+				CSSToken::CloseParen => depth -= 1,
+				_ => {}
+			}
+			self.idx += 1;
+		}
+
+		(self.tokens[start..].to_vec(), self.end)
+	}
+
+	fn nth(&self, tokens: &[(CSSToken, SourceSpan)], end: SourcePosition) -> Result<AnPlusB, SelectorParseError> {
+		let text: String = tokens.iter().map(|(token, _)| token.to_css()).collect();
+		AnPlusB::parse(&text).ok_or_else(|| SelectorParseError {
+			position: tokens.first().map_or(end, |(_, span)| span.start),
+			reason: "expected `An+B`"
+		})
+	}
+
+	/// `:nth-child()` and `:nth-last-child()` arguments, `An+B` with an optional `of S`.
+	fn nth_child(&self, tokens: SpannedTokens, end: SourcePosition) -> Result<(AnPlusB, Option<Selector>), SelectorParseError> {
+		let of = tokens.iter().position(|(token, _)| matches!(token, CSSToken::Ident(ident) if ident.eq_ignore_ascii_case("of")));
+		match of {
+			Some(of) => {
+				let nth = self.nth(&tokens[..of], tokens[of].1.start)?;
+				let mut parser = self.nested(tokens[of + 1..].to_vec(), end, false);
+				Ok((nth, Some(Selector::new(parser.selector_list()?))))
+			},
+			None => Ok((self.nth(&tokens, end)?, None))
+		}
+	}
+
+	fn pseudo_class(&mut self) -> Result<SelectorComponent, SelectorParseError> {
+		self.idx += 1;
+		let name = match self.peek() {
+			Some(CSSToken::Colon) => return Err(self.error("pseudo-elements are not supported")),
+			Some(CSSToken::Ident(name)) => name.to_ascii_lowercase(),
+			Some(CSSToken::Function(name)) => {
+				let name = name.to_ascii_lowercase();
+				let start = self.idx;
+				let (arguments, end) = self.arguments();
+				let component = match name.as_str() {
+					"nth-child" => self.nth_child(arguments, end).map(|(nth, selector)| SelectorComponent::NthChild(nth, selector)),
+					"nth-last-child" => self.nth_child(arguments, end).map(|(nth, selector)| SelectorComponent::NthLastChild(nth, selector)),
+					"nth-of-type" => self.nth(&arguments, end).map(SelectorComponent::NthOfType),
+					"nth-last-of-type" => self.nth(&arguments, end).map(SelectorComponent::NthLastOfType),
+					"not" => self.nested(arguments, end, false).selector_list().map(|selectors| SelectorComponent::Not(Selector::new(selectors))),
+					"is" => Ok(SelectorComponent::Is(Selector::new(self.forgiving_selector_list(arguments, end)))),
+					"where" => Ok(SelectorComponent::Where(Selector::new(self.forgiving_selector_list(arguments, end)))),
+					"has" if !self.in_has => self.nested(arguments, end, true).relative_selector_list().map(SelectorComponent::Has),
+					_ => {
+						self.idx = start;
+						Err(self.error(if name == "has" { "`:has()` can't be nested" } else { "unknown pseudo-class" }))
+					}
+				};
+				return component;
+			},
+			_ => return Err(self.error("expected a pseudo-class name"))
+		};
+
+		let component = match name.as_str() {
+			"root" => SelectorComponent::Root,
+			"empty" => SelectorComponent::Empty,
+			"first-child" => SelectorComponent::FirstChild,
+			"last-child" => SelectorComponent::LastChild,
+			"only-child" => SelectorComponent::OnlyChild,
+			_ => return Err(self.error("unknown pseudo-class"))
+		};
+		self.idx += 1;
+		Ok(component)
+	}
+
+	/// A type or universal selector first, then any number of id, class, attribute selectors and pseudo-classes.
+	fn compound_selector(&mut self) -> Result<CompoundSelector, SelectorParseError> {
+		let mut components: Vec<SelectorComponent> = self.type_selector()?.into_iter().collect();
+		loop {
+			let component = match self.peek() {
+				Some(CSSToken::Hash(name, true)) => SelectorComponent::Id(name.clone()),
+				Some(CSSToken::Hash(_, false)) => return Err(self.error("an id must be a valid identifier")),
+				Some(CSSToken::Delim('.')) => {
+					self.idx += 1;
+					match self.peek() {
+						Some(CSSToken::Ident(name)) => SelectorComponent::Class(name.clone()),
+						_ => return Err(self.error("expected a class name"))
+					}
+				},
+				Some(CSSToken::OpenSquare) => {
+					components.push(SelectorComponent::Attribute(self.attribute_selector()?));
+					continue;
+				},
+				Some(CSSToken::Colon) => {
+					components.push(self.pseudo_class()?);
+					continue;
+				},
+				Some(CSSToken::Ident(_)) | Some(CSSToken::Delim('*')) | Some(CSSToken::Delim('|')) if !components.is_empty() => return Err(self.error("a type selector must come first")),
+				_ => break
+			};
+			self.idx += 1;
+			components.push(component);
+		}
+
+		if components.is_empty() { return Err(self.error("expected a selector")); }
+		Ok(CompoundSelector::new(components))
+	}
+}
+
+impl FromStr for Selector {
+	type Err = SelectorParseError;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(Selector::new(Parser::new(css_tokenize::tokenize_spanned(s)).selector_list()?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::parse_html_new;
+
+	fn parse(selector: &str) -> Selector {
+		Selector::from_str(selector).unwrap_or_else(|err| panic!("{:?} failed to parse: {}", selector, err))
+	}
+
+	fn attribute(selector: &str) -> AttributeSelector {
+		match parse(selector).selectors()[0].subject().components() {
+			[SelectorComponent::Attribute(attribute)] => attribute.clone(),
+			components => panic!("{:?} is not an attribute selector: {:?}", selector, components)
+		}
+	}
+
+	/// The `(line, column)` and reason of the error parsing `selector`.
+	fn error(selector: &str) -> (usize, usize, &'static str) {
+		let err = Selector::from_str(selector).expect_err(selector);
+		(err.position.line, err.position.column, err.reason)
+	}
+
+	/// The ids of the elements of `html` that `selector` matches, in tree order.
+	fn matching(html: &str, selector: &str) -> Vec<String> {
+		let document = parse_html_new(html);
+		let selector = parse(selector);
+		let context = MatchingContext::new();
+		document.root().find(|node| selector.matches_with(&node, &context)).iter().filter_map(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) => el.id().map(String::from),
+			_ => None
+		}).collect()
+	}
+
+	#[test]
+	fn attribute_operators() {
+		let operators = [
+			("[a]", AttributeOperator::Exists),
+			("[a=v]", AttributeOperator::Equals),
+			("[a~=v]", AttributeOperator::Includes),
+			("[a|=v]", AttributeOperator::DashMatch),
+			("[a^=v]", AttributeOperator::Prefix),
+			("[a$=v]", AttributeOperator::Suffix),
+			("[a*=v]", AttributeOperator::Substring)
+		];
+		for (selector, operator) in operators {
+			let attribute = attribute(selector);
+			assert_eq!((attribute.name.as_str(), attribute.operator, attribute.case), ("a", operator, CaseSensitivity::Default), "{}", selector);
+		}
+
+		assert_eq!(attribute("[ a = \"v w\" ]").value, "v w");
+		assert!(attribute("[*|a]").any_namespace);
+		assert_eq!(attribute("[a=v i]").case, CaseSensitivity::Insensitive);
+		assert_eq!(attribute("[a='v' S]").case, CaseSensitivity::Sensitive);
+	}
+
+	#[test]
+	fn attribute_matching() {
+		let html = r#"<p id=a title="one two-three" lang=EN-us data-x=Value></p><p id=b title="" data-x=val></p>"#;
+		assert_eq!(matching(html, "[title]"), ["a", "b"]);
+		assert_eq!(matching(html, "[title='']"), ["b"]);
+		assert_eq!(matching(html, "[title~=two-three]"), ["a"]);
+		assert!(matching(html, "[title~='one two-three']").is_empty());
+		assert_eq!(matching(html, "[lang|=en]"), ["a"]);
+		assert!(matching(html, "[title|=two]").is_empty());
+		assert_eq!(matching(html, "[title^=one]"), ["a"]);
+		assert!(matching(html, "[title^='']").is_empty());
+		assert_eq!(matching(html, "[title$=three]"), ["a"]);
+		assert_eq!(matching(html, "[title*='e t']"), ["a"]);
+		assert_eq!(matching(html, "[DATA-X]"), ["a", "b"]);
+	}
+
+	#[test]
+	fn attribute_case_flags() {
+		let html = r#"<p id=a lang=EN data-x=Value></p>"#;
+		assert!(matching(html, "[data-x=value]").is_empty());
+		assert_eq!(matching(html, "[data-x=value i]"), ["a"]);
+		assert_eq!(matching(html, "[lang=en]"), ["a"]);
+		assert!(matching(html, "[lang=en s]").is_empty());
+	}
+
+	#[test]
+	fn error_positions() {
+		assert_eq!(error(""), (1, 1, "expected a selector"));
+		assert_eq!(error("div >"), (1, 6, "expected a selector"));
+		assert_eq!(error("div,\n  p ["), (2, 6, "expected an attribute name"));
+		assert_eq!(error("[a=v x]"), (1, 6, "unknown attribute selector flag"));
+		assert_eq!(error("[a==v]"), (1, 4, "expected an identifier or string as the attribute value"));
+		assert_eq!(error("p.1"), (1, 2, "expected a combinator, `,` or the end of the selector"));
+		assert_eq!(error("p. a"), (1, 3, "expected a class name"));
+		assert_eq!(error("p:hover"), (1, 3, "unknown pseudo-class"));
+		assert_eq!(error("p::before"), (1, 3, "pseudo-elements are not supported"));
+		assert_eq!(error(".a*"), (1, 3, "a type selector must come first"));
+		assert_eq!(error("li:nth-child(+ 2n)"), (1, 14, "expected `An+B`"));
+	}
+
+	#[test]
+	fn an_plus_b_forms() {
+		let forms = [
+			("odd", AnPlusB(2, 1)),
+			("EVEN", AnPlusB(2, 0)),
+			("3", AnPlusB(0, 3)),
+			("-3", AnPlusB(0, -3)),
+			("n", AnPlusB(1, 0)),
+			("-n+3", AnPlusB(-1, 3)),
+			("+n", AnPlusB(1, 0)),
+			("2n - 1", AnPlusB(2, -1)),
+			("2n+ 1", AnPlusB(2, 1)),
+			("-2N-0", AnPlusB(-2, 0)),
+			(" 10n ", AnPlusB(10, 0))
+		];
+		for (text, expected) in forms {
+			assert_eq!(AnPlusB::parse(text), Some(expected), "{:?}", text);
+		}
+
+		for text in ["+ 2n", "- n", "2 n", "n-", "2n + -1", "2n 1", "n1", "1.5n", ""] {
+			assert_eq!(AnPlusB::parse(text), None, "{:?}", text);
+		}
+	}
+
+	#[test]
+	fn an_plus_b_matching() {
+		let indices = |an_plus_b: AnPlusB| (1..=8).filter(|idx| an_plus_b.matches(*idx)).collect::<Vec<usize>>();
+		assert_eq!(indices(AnPlusB(2, 1)), [1, 3, 5, 7]);
+		assert_eq!(indices(AnPlusB(-1, 3)), [1, 2, 3]);
+		assert_eq!(indices(AnPlusB(3, -1)), [2, 5, 8]);
+		assert_eq!(indices(AnPlusB(0, 4)), [4]);
+		assert!(indices(AnPlusB(-2, -1)).is_empty());
+	}
+
+	#[test]
+	fn specificity() {
+		let specificities = [
+			("*", Specificity(0, 0, 0)),
+			("li", Specificity(0, 0, 1)),
+			("ul li", Specificity(0, 0, 2)),
+			("ul > li.red", Specificity(0, 1, 2)),
+			("#a.b[c]:first-child", Specificity(1, 3, 0)),
+			("a:not(#b, .c)", Specificity(1, 0, 1)),
+			(":is(p, #x) span", Specificity(1, 0, 1)),
+			(":where(#x) p", Specificity(0, 0, 1)),
+			(":has(> #x, p)", Specificity(1, 0, 0)),
+			("li:nth-child(2n of .a, #b)", Specificity(1, 1, 1))
+		];
+		for (selector, expected) in specificities {
+			assert_eq!(parse(selector).specificity(), expected, "{}", selector);
+		}
+	}
+
+	#[test]
+	fn match_specificity_is_of_the_matching_selector() {
+		let document = parse_html_new("<p id=x class=y></p>");
+		let node = document.get_element_by_id("x").unwrap();
+		let context = MatchingContext::new();
+		assert_eq!(parse("p, .y, #z").match_specificity(&node, &context), Some(Specificity(0, 1, 0)));
+		assert_eq!(parse("#z").match_specificity(&node, &context), None);
+	}
+
+	#[test]
+	fn combinators() {
+		let html = "<div id=d><h1 id=h></h1> text <p id=p1></p><section id=s><p id=p2></p></section><p id=p3></p></div><p id=p4></p>";
+		assert_eq!(matching(html, "div p"), ["p1", "p2", "p3"]);
+		assert_eq!(matching(html, "div > p"), ["p1", "p3"]);
+		assert_eq!(matching(html, "h1 + p"), ["p1"]);
+		assert_eq!(matching(html, "h1 ~ p"), ["p1", "p3"]);
+		assert_eq!(matching(html, "section + p"), ["p3"]);
+		assert_eq!(matching(html, "h1 ~ section > p"), ["p2"]);
+		assert_eq!(matching(html, "div > h1 + p ~ p"), ["p3"]);
+		assert_eq!(matching(html, "div>p"), matching(html, "div > p"));
+		assert!(matching(html, "h1 + section").is_empty());
+		assert!(matching(html, "section p + p").is_empty());
+	}
+
+	#[test]
+	fn relative_selectors() {
+		let html = "<div id=a><ul><li></li></ul></div><div id=b><ul></ul><li></li></div><div id=c><h1></h1><p></p></div><div id=e><h1></h1><span></span><p></p></div>";
+		assert_eq!(matching(html, "div:has(ul)"), ["a", "b"]);
+		assert_eq!(matching(html, "div:has(> ul li)"), ["a"]);
+		assert_eq!(matching(html, "div:has(h1 + p)"), ["c"]);
+		assert_eq!(matching(html, "div:has(h1 ~ p)"), ["c", "e"]);
+		assert_eq!(matching(html, "div:has(+ div ul + li)"), ["a"]);
+		assert_eq!(matching(html, "div:has(~ div > h1 + span)"), ["a", "b", "c"]);
+	}
+
+	#[test]
+	fn nth_matching() {
+		let html = "<ul><li id=l1 class=a></li><li id=l2></li><p id=p1></p><li id=l3 class=a></li><li id=l4 class=a></li><p id=p2></p></ul>";
+		assert_eq!(matching(html, "ul > :nth-child(2n)"), ["l2", "l3", "p2"]);
+		assert_eq!(matching(html, "li:nth-child(odd)"), ["l1", "l4"]);
+		assert_eq!(matching(html, ":nth-child(2 of .a)"), ["l3"]);
+		assert_eq!(matching(html, ":nth-child(-n+2 of li)"), ["l1", "l2"]);
+		assert_eq!(matching(html, "ul > :nth-last-child(2)"), ["l4"]);
+		assert_eq!(matching(html, ":nth-last-child(1 of .a)"), ["l4"]);
+		assert_eq!(matching(html, "li:nth-of-type(2n+1)"), ["l1", "l3"]);
+		assert_eq!(matching(html, "p:nth-of-type(2)"), ["p2"]);
+		assert_eq!(matching(html, "li:nth-last-of-type(1)"), ["l4"]);
+	}
+
+	#[test]
+	fn logical_pseudo_classes() {
+		let html = "<p id=a class=x></p><p id=b></p><span id=c class=x></span>";
+		assert_eq!(matching(html, "p:not(.x)"), ["b"]);
+		assert_eq!(matching(html, ":not(p, span, html, head, body)"), Vec::<String>::new());
+		assert_eq!(matching(html, ":is(p, #c).x"), ["a", "c"]);
+		assert_eq!(matching(html, ":where(span, #b)"), ["b", "c"]);
+		assert_eq!(matching(html, "p:is(.x, :unknown)"), ["a"]);
+
+		let document = parse_html_new(html);
+		let context = MatchingContext::new();
+		let specificity = |id: &str, selector: &str| parse(selector).match_specificity(&document.get_element_by_id(id).unwrap(), &context);
+		assert_eq!(specificity("b", "p:not(.x, #y)"), Some(Specificity(1, 0, 1)));
+		assert_eq!(specificity("a", ":is(p, #y)"), Some(Specificity(1, 0, 0)));
+		assert_eq!(specificity("a", ":where(#a, .x) p"), None);
+		assert_eq!(specificity("a", ":where(#a, .x)"), Some(Specificity(0, 0, 0)));
+		assert_eq!(specificity("a", ":where(#a):is(.x)"), Some(Specificity(0, 1, 0)));
+	}
+
+	#[test]
+	fn structural_pseudo_classes() {
+		let html = "<div id=d><p id=p1></p><p id=p2>text</p><p id=p3><!-- comment --></p></div><section id=s><span id=only></span></section>";
+		assert_eq!(matching(html, "p:empty"), ["p1", "p3"]);
+		assert_eq!(matching(html, "div:empty"), Vec::<String>::new());
+		assert_eq!(matching(html, "p:first-child"), ["p1"]);
+		assert_eq!(matching(html, "p:last-child"), ["p3"]);
+		assert_eq!(matching(html, ":only-child"), ["only"]);
+		assert_eq!(matching(html, "div:first-child"), ["d"]);
+		assert_eq!(matching(html, "section:last-child"), ["s"]);
+		assert_eq!(matching("<html id=h><body id=b></body></html>", ":root"), ["h"]);
+	}
+}
@@ -0,0 +1,47 @@
+/// A location in the source text. `offset` is in bytes, `line` and `column` are 1-based with columns counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourcePosition {
+	pub offset: usize,
+	pub line: usize,
+	pub column: usize
+}
+impl SourcePosition {
+	pub fn new() -> SourcePosition {
+		SourcePosition {
+			offset: 0, line: 1, column: 1
+		}
+	}
+
+	pub fn advance(&mut self, ch: char) {
+		self.offset += ch.len_utf8();
+		if ch == '\n' {
+			self.line += 1;
+			self.column = 1;
+		} else {
+			self.column += 1;
+		}
+	}
+}
+impl Default for SourcePosition {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// The half-open range `start..end` of source text that produced a token or node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SourceSpan {
+	pub start: SourcePosition,
+	pub end: SourcePosition
+}
+impl SourceSpan {
+	pub fn new(start: SourcePosition, end: SourcePosition) -> SourceSpan {
+		SourceSpan {
+			start, end
+		}
+	}
+
+	pub fn contains(&self, offset: usize) -> bool {
+		self.start.offset <= offset && offset < self.end.offset
+	}
+}
@@ -1,46 +1,122 @@
 use std::collections::HashMap;
+use super::source_position::{SourcePosition, SourceSpan};
 
 #[derive(Debug)]
 pub struct HTMLStartTag {
 	pub name: String,
 	pub self_close: bool,
-	pub attrs: HashMap<String, String>
+	pub attrs: Vec<(String, String)>,
+	pub span: SourceSpan
 }
 impl HTMLStartTag {
 	pub fn new(tagname: String, self_close: bool) -> HTMLStartTag {
 		HTMLStartTag {
 			name: tagname,
 			self_close,
-			attrs: HashMap::new()
+			attrs: vec![],
+			span: SourceSpan::default()
 		}
 	}
 
-	pub fn new_with_attrs(tagname: String, self_close: bool, attrs: HashMap<String, String>) -> HTMLStartTag {
+	pub fn new_with_attrs(tagname: String, self_close: bool, attrs: Vec<(String, String)>) -> HTMLStartTag {
 		HTMLStartTag {
 			name: tagname,
 			self_close,
-			attrs
+			attrs,
+			span: SourceSpan::default()
 		}
 	}
 
 	pub fn clone_name(&self) -> String {
-		return self.name.clone();
+		self.name.clone()
 	}
 
 	pub fn clone_attrs(&self) -> HashMap<String, String> {
-		return self.attrs.clone();
+		self.attrs.iter().cloned().collect()
+	}
+
+	/// Later duplicates of an attribute are dropped, as in the HTML tokenizer.
+	pub fn add_attr(&mut self, name: String, value: String) {
+		if !self.attrs.iter().any(|(existing, _)| *existing == name) {
+			self.attrs.push((name, value));
+		}
 	}
 
 	pub fn is_self_close(&self) -> bool {
-		return self.self_close;
+		self.self_close
 	}
 }
 
 #[derive(Debug)]
-pub struct HTMLEndTag {}
+pub struct HTMLEndTag {
+	pub name: String,
+	pub span: SourceSpan
+}
 impl HTMLEndTag {
-	pub fn new() -> HTMLEndTag {
-		HTMLEndTag {}
+	pub fn new(name: String) -> HTMLEndTag {
+		HTMLEndTag {
+			name,
+			span: SourceSpan::default()
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct HTMLDoctype {
+	pub name: Option<String>,
+	pub public_id: Option<String>,
+	pub system_id: Option<String>,
+	pub force_quirks: bool,
+	pub span: SourceSpan
+}
+impl HTMLDoctype {
+	fn parse(content: &str) -> HTMLDoctype {
+		let mut doctype = HTMLDoctype { name: None, public_id: None, system_id: None, force_quirks: false, span: SourceSpan::default() };
+		let rest = content.trim_start_matches(|c: char| c.is_ascii_whitespace());
+		let name_end = rest.find(|c: char| c.is_ascii_whitespace()).unwrap_or(rest.len());
+		if name_end == 0 {
+			doctype.force_quirks = true;
+			return doctype;
+		}
+		doctype.name = Some(rest[..name_end].to_ascii_lowercase());
+
+		let rest = rest[name_end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+		if rest.is_empty() { return doctype; }
+
+		let keyword = rest.get(..6).unwrap_or("").to_ascii_uppercase();
+		let mut rest = &rest[keyword.len().min(rest.len())..];
+		match keyword.as_str() {
+			"PUBLIC" => {
+				doctype.public_id = HTMLDoctype::quoted(&mut rest, &mut doctype.force_quirks);
+				if doctype.public_id.is_none() { doctype.force_quirks = true; }
+				doctype.system_id = HTMLDoctype::quoted(&mut rest, &mut doctype.force_quirks);
+			},
+			"SYSTEM" => {
+				doctype.system_id = HTMLDoctype::quoted(&mut rest, &mut doctype.force_quirks);
+				if doctype.system_id.is_none() { doctype.force_quirks = true; }
+			},
+			_ => doctype.force_quirks = true
+		}
+
+		doctype
+	}
+
+	fn quoted(rest: &mut &str, force_quirks: &mut bool) -> Option<String> {
+		let trimmed = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+		let quote = trimmed.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+		let inner = &trimmed[1..];
+
+		match inner.find(quote) {
+			Some(end) => {
+				*rest = &inner[end + 1..];
+				Some(String::from(&inner[..end]))
+			},
+			None => {
+				*force_quirks = true;
+				*rest = "";
+				Some(String::from(inner))
+			}
+		}
 	}
 }
 
@@ -48,39 +124,77 @@ impl HTMLEndTag {
 pub enum HTMLChild {
 	StartTag(HTMLStartTag),
 	EndTag(HTMLEndTag),
-	Text(String)
+	Text(String, SourceSpan),
+	Comment(String, SourceSpan),
+	Doctype(HTMLDoctype)
+}
+impl HTMLChild {
+	pub fn span(&self) -> SourceSpan {
+		match self {
+			HTMLChild::StartTag(tag) => tag.span,
+			HTMLChild::EndTag(tag) => tag.span,
+			HTMLChild::Text(_, span) | HTMLChild::Comment(_, span) => *span,
+			HTMLChild::Doctype(doctype) => doctype.span
+		}
+	}
+
+	fn set_span(&mut self, new_span: SourceSpan) {
+		match self {
+			HTMLChild::StartTag(tag) => tag.span = new_span,
+			HTMLChild::EndTag(tag) => tag.span = new_span,
+			HTMLChild::Text(_, span) | HTMLChild::Comment(_, span) => *span = new_span,
+			HTMLChild::Doctype(doctype) => doctype.span = new_span
+		}
+	}
 }
 
 pub struct TagParser {
 	state: Option<Box<dyn ParseState>>,
-	nodes: Vec<HTMLChild>
+	nodes: Vec<HTMLChild>,
+	token_start: SourcePosition,
+	position: SourcePosition,
+	next_position: SourcePosition
 }
 impl TagParser {
 	pub fn new() -> TagParser {
 		TagParser {
 			state: Some(Box::new(InitialState {})),
-			nodes: vec![]
+			nodes: vec![],
+			token_start: SourcePosition::new(),
+			position: SourcePosition::new(),
+			next_position: SourcePosition::new()
 		}
 	}
 
 	pub fn get_nodes(self) -> Vec<HTMLChild> {
-		return self.nodes
+		self.nodes
 	}
 
-	fn emit(&mut self, node: HTMLChild) {
+	/// Text ends just before the `<` that interrupts it, every other token ends after the character that completes it.
+	fn emit(&mut self, mut node: HTMLChild) {
+		let end = if let HTMLChild::Text(..) = node { self.position } else { self.next_position };
+		node.set_span(SourceSpan::new(self.token_start, end));
+		self.token_start = end;
 		self.nodes.push(node);
 	}
 
 	fn consume(&mut self, ch: char) {
+		self.next_position.advance(ch);
 		if let Some(mut state) = self.state.take() {
 			state.consume(ch, self);
 			self.state = Some(state.next(ch));
 		}
+		self.position = self.next_position;
 	}
 
 	pub fn parse(&mut self, html: &str) {
-		for ch in html.bytes() {
-			self.consume(ch as char);
+		for ch in html.chars() {
+			self.consume(ch);
+		}
+
+		if let Some(mut state) = self.state.take() {
+			state.finish(self);
+			self.state = Some(Box::new(InitialState {}));
 		}
 	}
 }
@@ -89,6 +203,7 @@ impl TagParser {
 trait ParseState {
 	fn consume(&mut self, ch: char, parser: &mut TagParser) {}
 	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState>;
+	fn finish(&mut self, parser: &mut TagParser) {}
 }
 
 struct InitialState {}
@@ -115,7 +230,7 @@ impl ParseState for TextNodeState {
 	fn consume(&mut self, ch: char, parser: &mut TagParser) {
 		if ch == '<' {
 			if let Some(s) = self.content.take() {
-				parser.emit(HTMLChild::Text(s));
+				parser.emit(HTMLChild::Text(s, SourceSpan::default()));
 			} else { panic!("Unreachable") }
 		} else {
 			if let Some(s) = self.content.as_mut() {
@@ -130,6 +245,11 @@ impl ParseState for TextNodeState {
 			self
 		}
 	}
+	fn finish(&mut self, parser: &mut TagParser) {
+		if let Some(s) = self.content.take() {
+			parser.emit(HTMLChild::Text(s, SourceSpan::default()));
+		}
+	}
 }
 
 struct TagNameState {
@@ -153,18 +273,21 @@ impl TagNameState {
 impl ParseState for TagNameState {
 	fn consume(&mut self, ch: char, parser: &mut TagParser) {
 		match ch {
-			' ' => {},
 			'/' => {},
+			'!' if self.name.as_ref().is_some_and(|name| name.is_empty()) && !self.is_end => {},
 			'>' => {
 				if self.is_end {
-					parser.emit(HTMLChild::EndTag(HTMLEndTag::new()));
+					if let Some(name) = self.name.take() {
+						parser.emit(HTMLChild::EndTag(HTMLEndTag::new(name)));
+					} else { panic!("Unreachable") }
 				} else if let Some(name) = self.name.take() {
 					parser.emit(HTMLChild::StartTag(HTMLStartTag::new(name, self.self_closing)));
 				} else { panic!("Unreachable") }
 			},
+			_ if ch.is_ascii_whitespace() => {},
 			_ => {
 				if let Some(name) = self.name.as_mut() {
-					name.push(ch);
+					name.push(ch.to_ascii_lowercase());
 				} else { panic!("Unreachable") }
 			}
 		}
@@ -172,20 +295,20 @@ impl ParseState for TagNameState {
 
 	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
 		match ch {
-			' ' => {
+			_ if ch.is_ascii_whitespace() => {
 				if let Some(name) = &self.name {
-					if name.len() == 0 || self.is_end { return self; }
+					if name.is_empty() || self.is_end { return self; }
 				}
 
 				if let Some(name) = self.name {
-					return Box::new(TagBodyState::new(name, self.self_closing, HashMap::new()));
+					return Box::new(TagBodyState::new(name, self.self_closing, vec![]));
 				}
 				
 				panic!("Unreachable");
 			},
 			'/' => {
 				if let Some(name) = self.name {
-					if name.len() == 0 {
+					if name.is_empty() {
 						Box::new(TagNameState::from(name, self.self_closing, true))
 					} else {
 						Box::new(TagNameState::from(name, true, self.is_end))
@@ -197,6 +320,9 @@ impl ParseState for TagNameState {
 			'>' => {
 				Box::new(InitialState {})
 			},
+			'!' if self.name.as_ref().is_some_and(|name| name.is_empty()) && !self.is_end => {
+				Box::new(MarkupDeclarationState::new())
+			},
 			_ => {
 				self
 			}
@@ -204,11 +330,118 @@ impl ParseState for TagNameState {
 	}
 }
 
+struct MarkupDeclarationState {
+	opening: String
+}
+impl MarkupDeclarationState {
+	fn new() -> MarkupDeclarationState {
+		MarkupDeclarationState {
+			opening: String::new()
+		}
+	}
+}
+impl ParseState for MarkupDeclarationState {
+	fn consume(&mut self, ch: char, parser: &mut TagParser) {
+		if ch == '>' {
+			parser.emit(HTMLChild::Comment(self.opening.clone(), SourceSpan::default()));
+		} else {
+			self.opening.push(ch);
+		}
+	}
+	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
+		let lowercase = self.opening.to_ascii_lowercase();
+		if ch == '>' {
+			Box::new(InitialState {})
+		} else if self.opening == "--" {
+			Box::new(CommentState::new())
+		} else if lowercase == "doctype" {
+			Box::new(DoctypeState::new())
+		} else if "--".starts_with(&self.opening) || "doctype".starts_with(&lowercase) {
+			self
+		} else {
+			Box::new(BogusCommentState::new(self.opening))
+		}
+	}
+}
+
+struct CommentState {
+	content: String,
+	done: bool
+}
+impl CommentState {
+	fn new() -> CommentState {
+		CommentState {
+			content: String::new(),
+			done: false
+		}
+	}
+}
+impl ParseState for CommentState {
+	fn consume(&mut self, ch: char, parser: &mut TagParser) {
+		if ch == '>' && (self.content.is_empty() || self.content.ends_with("--")) {
+			let end = self.content.len().saturating_sub(2);
+			parser.emit(HTMLChild::Comment(String::from(&self.content[..end]), SourceSpan::default()));
+			self.done = true;
+		} else {
+			self.content.push(ch);
+		}
+	}
+	fn next(self: Box<Self>, _ch: char) -> Box<dyn ParseState> {
+		if self.done { Box::new(InitialState {}) } else { self }
+	}
+}
+
+struct BogusCommentState {
+	content: String
+}
+impl BogusCommentState {
+	fn new(content: String) -> BogusCommentState {
+		BogusCommentState {
+			content
+		}
+	}
+}
+impl ParseState for BogusCommentState {
+	fn consume(&mut self, ch: char, parser: &mut TagParser) {
+		if ch == '>' {
+			parser.emit(HTMLChild::Comment(self.content.clone(), SourceSpan::default()));
+		} else {
+			self.content.push(ch);
+		}
+	}
+	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
+		if ch == '>' { Box::new(InitialState {}) } else { self }
+	}
+}
+
+struct DoctypeState {
+	content: String
+}
+impl DoctypeState {
+	fn new() -> DoctypeState {
+		DoctypeState {
+			content: String::new()
+		}
+	}
+}
+impl ParseState for DoctypeState {
+	fn consume(&mut self, ch: char, parser: &mut TagParser) {
+		if ch == '>' {
+			parser.emit(HTMLChild::Doctype(HTMLDoctype::parse(&self.content)));
+		} else {
+			self.content.push(ch);
+		}
+	}
+	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
+		if ch == '>' { Box::new(InitialState {}) } else { self }
+	}
+}
+
 struct TagBodyState {
 	tagstart: Option<HTMLStartTag>
 }
 impl TagBodyState {
-	fn new(tagname: String, self_closes: bool, attrs: HashMap<String, String>) -> TagBodyState {
+	fn new(tagname: String, self_closes: bool, attrs: Vec<(String, String)>) -> TagBodyState {
 		TagBodyState {
 			tagstart: Some(HTMLStartTag::new_with_attrs(tagname, self_closes, attrs))
 		}
@@ -239,10 +472,11 @@ impl ParseState for TagBodyState {
 	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
 		match ch {
 			'>' => { Box::new(InitialState {}) },
-			' ' => { self },
+			'/' => { self },
+			_ if ch.is_ascii_whitespace() => { self },
 			_ => {
 				if let Some(tag) = self.tagstart {
-					Box::new(AttributeNameState::new(tag, ch.to_string()))
+					Box::new(AttributeNameState::new(tag, ch.to_ascii_lowercase().to_string()))
 				} else { panic!("Unreachable") }
 			}
 		}
@@ -260,26 +494,55 @@ impl AttributeNameState {
 		}
 	}
 }
+impl AttributeNameState {
+	fn bind_empty(&mut self) {
+		if let Some(tag) = self.tag.as_mut() {
+			if let Some(name) = self.name.take() {
+				tag.add_attr(name, String::new());
+			}
+		} else { panic!("Unreachable"); }
+	}
+}
 impl ParseState for AttributeNameState {
-	fn consume(&mut self, ch: char, _parser: &mut TagParser) {
-		if ch != '=' {
-			if let Some(name) = self.name.as_mut() {
-				name.push(ch);
-			} else { panic!("Unreachable") }
+	fn consume(&mut self, ch: char, parser: &mut TagParser) {
+		match ch {
+			'=' => {},
+			'/' => {
+				self.bind_empty();
+				if let Some(tag) = self.tag.as_mut() { tag.self_close = true; }
+			},
+			'>' => {
+				self.bind_empty();
+				if let Some(tag) = self.tag.take() {
+					parser.emit(HTMLChild::StartTag(tag));
+				} else { panic!("Unreachable"); }
+			},
+			_ if ch.is_ascii_whitespace() => self.bind_empty(),
+			_ => {
+				if let Some(name) = self.name.as_mut() {
+					name.push(ch.to_ascii_lowercase());
+				} else { panic!("Unreachable") }
+			}
 		}
-
 	}
 	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
-		if ch == '=' {
-			if let Some(name) = self.name {
-				if let Some(tag) = self.tag {
-					return Box::new(AttributeValueState::new(name, String::new(), tag, false));
+		match ch {
+			'=' => {
+				if let Some(name) = self.name {
+					if let Some(tag) = self.tag {
+						return Box::new(AttributeValueState::new(name, String::new(), tag));
+					}
 				}
-			}
 
-			panic!("Unreachable!");
-		} else {
-			self
+				panic!("Unreachable!");
+			},
+			'>' => Box::new(InitialState {}),
+			_ if ch == '/' || ch.is_ascii_whitespace() => {
+				if let Some(tag) = self.tag {
+					Box::new(TagBodyState::new_from_tag(tag))
+				} else { panic!("Unreachable"); }
+			},
+			_ => self
 		}
 	}
 }
@@ -288,43 +551,51 @@ struct AttributeValueState {
 	name: Option<String>,
 	value: Option<String>,
 	tag: Option<HTMLStartTag>,
-	has_quotation: bool
+	quote: Option<char>,
+	started: bool,
+	finished: bool
 }
 impl AttributeValueState {
-	fn new(name: String, value: String, tag: HTMLStartTag, has_quotation: bool) -> AttributeValueState {
+	fn new(name: String, value: String, tag: HTMLStartTag) -> AttributeValueState {
 		AttributeValueState {
-			name: Some(name), value: Some(value), tag: Some(tag), has_quotation
+			name: Some(name), value: Some(value), tag: Some(tag), quote: None, started: false, finished: false
 		}
 	}
 
 	fn bind(&mut self, _parser: &mut TagParser) {
-		if let Some(mut tag) = self.tag.take() {
+		if let Some(tag) = self.tag.as_mut() {
 			if let Some(name) = self.name.take() { if let Some(value) = self.value.take() {
-				tag.attrs.insert(name, value);
+				tag.add_attr(name, value);
 			}} else { panic!("Unreachable"); }
-			self.tag = Some(tag);
 		} else { panic!("Unreachable"); }
 	}
 
 	fn bind_and_emit(&mut self, parser: &mut TagParser) {
-		if let Some(mut tag) = self.tag.take() {
-			if let Some(name) = self.name.take() { if let Some(value) = self.value.take() {
-				tag.attrs.insert(name, value);
-			}} else { panic!("Unreachable"); }
+		self.bind(parser);
+		if let Some(tag) = self.tag.take() {
 			parser.emit(HTMLChild::StartTag(tag));
 		} else { panic!("Unreachable"); }
 	}
 }
 impl ParseState for AttributeValueState {
 	fn consume(&mut self, ch: char, parser: &mut TagParser) {
-		match ch {
-			'>' => self.bind_and_emit(parser),
-			'"' if !self.has_quotation => self.has_quotation = true,
-			'"' if self.has_quotation => {
+		match self.quote {
+			None if !self.started && ch.is_ascii_whitespace() => {},
+			None if !self.started && (ch == '"' || ch == '\'') => {
+				self.quote = Some(ch);
+				self.started = true;
+			},
+			Some(quote) if ch == quote => {
+				self.bind(parser);
+				self.finished = true;
+			},
+			None if ch == '>' => self.bind_and_emit(parser),
+			None if ch.is_ascii_whitespace() => {
 				self.bind(parser);
-				self.has_quotation = false;
+				self.finished = true;
 			},
 			_ => {
+				self.started = true;
 				if let Some(value) = self.value.as_mut() {
 					value.push(ch);
 				} else { panic!("Unreachable") }
@@ -332,19 +603,14 @@ impl ParseState for AttributeValueState {
 		}
 	}
 	fn next(self: Box<Self>, ch: char) -> Box<dyn ParseState> {
-		match ch {
-			'>' => Box::new(InitialState {}),
-			'"' if self.has_quotation => {
-				self
-			},
-			'"' if !self.has_quotation => {
-				if let Some(tag) = self.tag {
-					Box::new(TagBodyState::new_from_tag(tag))
-				} else { panic!("Unreachable"); }
-			},
-			_ => {
-				self
-			}
+		if self.finished {
+			if let Some(tag) = self.tag {
+				Box::new(TagBodyState::new_from_tag(tag))
+			} else { panic!("Unreachable"); }
+		} else if self.quote.is_none() && ch == '>' {
+			Box::new(InitialState {})
+		} else {
+			self
 		}
 	}
-}
\ No newline at end of file
+}
@@ -8,6 +8,12 @@ use super::tag_parse::*;
 pub struct HTMLRoot<R> {
     pub children: RefCell<Vec<Rc<HTMLNode<R>>>>
 }
+impl<R> Default for HTMLRoot<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<R> HTMLRoot<R> {
     pub fn new() -> HTMLRoot<R> {
         HTMLRoot {
@@ -37,7 +43,7 @@ impl<R> HTMLElement<R> {
         let children = self.children.borrow();
         let children: &Vec<Rc<HTMLNode<R>>> = children.as_ref();
         println!("{:?}", children.len());
-        if let Some(child) = children.get(0) { Some(child.clone()) } else { None }
+        children.first().cloned()
     }
 }
 
@@ -109,7 +115,7 @@ pub fn create_elements<R>(tags: Vec<HTMLChild>, generator: &mut impl RenderGener
                     parent = np;
                 }
             },
-            HTMLChild::Text(string) => {
+            HTMLChild::Text(string, _) => {
                 let core_element = Rc::new(HTMLText::new(string, RefCell::new(Rc::downgrade(&parent))));
                 let element = Rc::new(HTMLNode::new(HTMLNodeContent::Text(core_element.clone()), generator));
 
@@ -118,7 +124,8 @@ pub fn create_elements<R>(tags: Vec<HTMLChild>, generator: &mut impl RenderGener
                 } else if let HTMLNodeContent::Element(p) = &parent.as_ref().content.as_ref() {
                     p.children.borrow_mut().push(element.clone());
                 } else { panic!("Unreachable") }
-            }
+            },
+            HTMLChild::Comment(..) | HTMLChild::Doctype(_) => {}
         }
     }
 
@@ -1,21 +1,72 @@
 use tree;
 use super::tag_parse::*;
-use std::collections::HashMap;
+use super::foreign_content;
+use super::document::Document;
+use super::source_position::{SourcePosition, SourceSpan};
+use super::attributes::{Attribute, ClassList, ClassListMut, Dataset, DatasetMut};
 use std::rc::Rc;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Namespace {
+	HTML,
+	SVG,
+	MathML,
+	XLink,
+	XML,
+	XMLNS
+}
+impl Namespace {
+	pub fn url(&self) -> &'static str {
+		match self {
+			Namespace::HTML => "http://www.w3.org/1999/xhtml",
+			Namespace::SVG => "http://www.w3.org/2000/svg",
+			Namespace::MathML => "http://www.w3.org/1998/Math/MathML",
+			Namespace::XLink => "http://www.w3.org/1999/xlink",
+			Namespace::XML => "http://www.w3.org/XML/1998/namespace",
+			Namespace::XMLNS => "http://www.w3.org/2000/xmlns/"
+		}
+	}
+}
+
 #[derive(Debug)]
 pub struct HTMLElement {
-	attributes: HashMap<String, String>,
-	tagname: String
+	attributes: Vec<Attribute>,
+	namespace: Namespace,
+	tagname: String,
+	template_contents: Option<Rc<tree::TreeNode<HTMLNode>>>,
+	span: SourceSpan
 }
 impl HTMLElement {
-	fn new(tagname: String, attributes: HashMap<String, String>) -> HTMLElement {
+	fn new(namespace: Namespace, tagname: String, attrs: Vec<(String, String)>, span: SourceSpan) -> HTMLElement {
+		let attributes = attrs.into_iter().map(|(name, value)| {
+			match namespace {
+				Namespace::SVG | Namespace::MathML => {
+					let name = if namespace == Namespace::SVG {
+						foreign_content::adjust_svg_attribute_name(&name)
+					} else {
+						foreign_content::adjust_mathml_attribute_name(&name)
+					};
+
+					match foreign_content::adjust_foreign_attribute(&name) {
+						Some((prefix, local_name, attr_namespace)) => Attribute::new(Some(attr_namespace), prefix, local_name, value),
+						None => Attribute::new(None, None, name, value)
+					}
+				},
+				_ => Attribute::new(None, None, name, value)
+			}
+		}).collect();
+
+		let template_contents = if namespace == Namespace::HTML && tagname == "template" {
+			Some(tree::TreeNode::root(HTMLNode::DocumentFragment))
+		} else { None };
+
 		HTMLElement {
-			tagname, attributes
+			tagname, namespace, attributes, template_contents, span
 		}
 	}
 
-	pub fn attributes(&self) -> &HashMap<String, String> {
+	/// The element's attributes in source order.
+	pub fn attributes(&self) -> &[Attribute] {
 		&self.attributes
 	}
 
@@ -23,55 +74,348 @@ impl HTMLElement {
 		&self.tagname
 	}
 
-	pub fn is_tag(&self, name: &String) -> bool {
-		self.tagname == *name
+	pub fn namespace(&self) -> Namespace {
+		self.namespace
+	}
+
+	/// The inert fragment holding the children of a `<template>`, which are not part of the document tree.
+	pub fn template_contents(&self) -> Option<Rc<tree::TreeNode<HTMLNode>>> {
+		self.template_contents.clone()
+	}
+
+	/// Runs from the start of the start tag to the end of the end tag, or to wherever the element was implicitly closed.
+	pub fn span(&self) -> SourceSpan {
+		self.span
+	}
+
+	pub fn is_tag(&self, name: &str) -> bool {
+		self.tagname == name
 	}
 
-	pub fn has_attribute(&self, name: &String) -> bool {
-		self.attributes.contains_key(name)
+	pub fn is_html_tag(&self, name: &str) -> bool {
+		self.namespace == Namespace::HTML && self.tagname == name
 	}
 
-	pub fn get_attribute(&self, name: &String) -> Option<&String> {
-		self.attributes.get(name)
+	/// Attribute names on HTML elements are matched ASCII case-insensitively, foreign elements match exactly.
+	fn attribute_index(&self, name: &str) -> Option<usize> {
+		if self.namespace == Namespace::HTML {
+			let name = name.to_ascii_lowercase();
+			self.attributes.iter().position(|attr| attr.has_qualified_name(&name))
+		} else {
+			self.attributes.iter().position(|attr| attr.has_qualified_name(name))
+		}
 	}
 
-	pub fn get_attribute_unwrapped(&self, name: &String) -> &String {
-		self.attributes.get(name).unwrap()
+	pub fn has_attribute(&self, name: &str) -> bool {
+		self.attribute_index(name).is_some()
+	}
+
+	pub fn get_attribute(&self, name: &str) -> Option<&str> {
+		self.attribute_index(name).map(|idx| self.attributes[idx].value())
+	}
+
+	pub fn get_attribute_unwrapped(&self, name: &str) -> &str {
+		self.get_attribute(name).unwrap()
+	}
+
+	pub fn set_attribute(&mut self, name: &str, value: &str) {
+		match self.attribute_index(name) {
+			Some(idx) => self.attributes[idx].set_value(String::from(value)),
+			None => {
+				let name = if self.namespace == Namespace::HTML { name.to_ascii_lowercase() } else { String::from(name) };
+				self.attributes.push(Attribute::new(None, None, name, String::from(value)));
+			}
+		}
+	}
+
+	pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+		self.attribute_index(name).map(|idx| String::from(self.attributes.remove(idx).value()))
+	}
+
+	pub fn toggle_attribute(&mut self, name: &str) -> bool {
+		if self.remove_attribute(name).is_some() { return false; }
+		self.set_attribute(name, "");
+		true
+	}
+
+	pub fn id(&self) -> Option<&str> {
+		self.get_attribute("id")
+	}
+
+	pub fn class_list(&self) -> ClassList<'_> {
+		ClassList::new(self)
+	}
+
+	pub fn class_list_mut(&mut self) -> ClassListMut<'_> {
+		ClassListMut::new(self)
+	}
+
+	pub fn dataset(&self) -> Dataset<'_> {
+		Dataset::new(self)
+	}
+
+	pub fn dataset_mut(&mut self) -> DatasetMut<'_> {
+		DatasetMut::new(self)
+	}
+
+	pub fn is_html_integration_point(&self) -> bool {
+		match self.namespace {
+			Namespace::SVG => ["foreignObject", "desc", "title"].contains(&self.tagname.as_str()),
+			Namespace::MathML if self.tagname == "annotation-xml" => match self.get_attribute("encoding") {
+				Some(encoding) => {
+					let encoding = encoding.to_ascii_lowercase();
+					encoding == "text/html" || encoding == "application/xhtml+xml"
+				},
+				None => false
+			},
+			_ => false
+		}
+	}
+
+	pub fn is_mathml_text_integration_point(&self) -> bool {
+		self.namespace == Namespace::MathML && ["mi", "mo", "mn", "ms", "mtext"].contains(&self.tagname.as_str())
 	}
 }
 
 #[derive(Debug)]
 pub enum HTMLNode {
 	Root,
-	Text(String),
+	DocumentFragment,
+	Text(String, SourceSpan),
 	Element(HTMLElement)
 }
+impl HTMLNode {
+	pub fn span(&self) -> Option<SourceSpan> {
+		match self {
+			HTMLNode::Root | HTMLNode::DocumentFragment => None,
+			HTMLNode::Text(_, span) => Some(*span),
+			HTMLNode::Element(el) => Some(el.span())
+		}
+	}
+}
 
-pub fn create_node_tree(tags: Vec<HTMLChild>) -> Rc<tree::TreeNode<HTMLNode>> {
-	let root = tree::TreeNode::root(HTMLNode::Root);
-	let mut parent = root.clone();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertionMode {
+	Initial,
+	InBody,
+	InTemplate
+}
 
-	for tag in tags {
-		match tag {
-			HTMLChild::StartTag(tag) => {
-				let is_close = tag.is_self_close();
-				let inner_element = HTMLNode::Element(HTMLElement::new(tag.name, tag.attrs));
-				let element = tree::TreeNode::new(inner_element, parent.clone());
-				parent.clone().add_child(element.clone());
-				if !is_close { parent = element; }
+struct TreeBuilder {
+	root: Rc<tree::TreeNode<HTMLNode>>,
+	open_elements: Vec<Rc<tree::TreeNode<HTMLNode>>>,
+	insertion_mode: InsertionMode,
+	template_insertion_modes: Vec<InsertionMode>,
+	doctype: Option<HTMLDoctype>,
+	end: SourcePosition
+}
+impl TreeBuilder {
+	fn new() -> TreeBuilder {
+		TreeBuilder {
+			root: tree::TreeNode::root(HTMLNode::Root),
+			open_elements: vec![],
+			insertion_mode: InsertionMode::Initial,
+			template_insertion_modes: vec![],
+			doctype: None,
+			end: SourcePosition::new()
+		}
+	}
+
+	fn current_node(&self) -> Rc<tree::TreeNode<HTMLNode>> {
+		self.open_elements.last().unwrap_or(&self.root).clone()
+	}
+
+	fn current_namespace(&self) -> Namespace {
+		match self.open_elements.last() {
+			Some(node) => match &*node.value.borrow() {
+				HTMLNode::Element(el) => el.namespace(),
+				_ => Namespace::HTML
 			},
-			HTMLChild::EndTag(_) => {
-				if let Some(new_parent) = parent.get_parent() {
-					parent = new_parent.clone();
-				}
+			None => Namespace::HTML
+		}
+	}
+
+	fn current_element_matches<A>(&self, cb: A) -> bool where A: Fn(&HTMLElement) -> bool {
+		match self.open_elements.last() {
+			Some(node) => match &*node.value.borrow() {
+				HTMLNode::Element(el) => cb(el),
+				_ => false
 			},
-			HTMLChild::Text(string) => {
-				let inner_element = HTMLNode::Text(string);
-				let element = tree::TreeNode::new(inner_element, parent.clone());
-				parent.clone().add_child(element.clone());
+			None => false
+		}
+	}
+
+	fn insertion_target(&self) -> Rc<tree::TreeNode<HTMLNode>> {
+		let node = self.current_node();
+		let contents = match &*node.value.borrow() {
+			HTMLNode::Element(el) => el.template_contents(),
+			_ => None
+		};
+
+		contents.unwrap_or(node)
+	}
+
+	fn insert(&self, value: HTMLNode) -> Rc<tree::TreeNode<HTMLNode>> {
+		let parent = self.insertion_target();
+		let node = tree::TreeNode::new(value, parent.clone());
+		parent.add_child(node.clone());
+		node
+	}
+
+	fn namespace_for_start_tag(&mut self, tag: &HTMLStartTag) -> Namespace {
+		if self.current_namespace() != Namespace::HTML {
+			if tag.name != "mglyph" && tag.name != "malignmark" && self.current_element_matches(|el| el.is_mathml_text_integration_point()) {
+				return TreeBuilder::html_namespace_for(&tag.name);
+			}
+			if tag.name == "svg" && self.current_element_matches(|el| el.namespace() == Namespace::MathML && el.tagname() == "annotation-xml") {
+				return Namespace::SVG;
+			}
+			if self.current_element_matches(|el| el.is_html_integration_point()) {
+				return TreeBuilder::html_namespace_for(&tag.name);
+			}
+			if !foreign_content::is_breakout_tag(&tag.name, &tag.attrs) {
+				return self.current_namespace();
+			}
+
+			while self.current_namespace() != Namespace::HTML && !self.current_element_matches(|el| el.is_html_integration_point() || el.is_mathml_text_integration_point()) {
+				self.pop_to(self.open_elements.len() - 1, tag.span.start);
+			}
+		}
+
+		TreeBuilder::html_namespace_for(&tag.name)
+	}
+
+	fn html_namespace_for(name: &str) -> Namespace {
+		match name {
+			"svg" => Namespace::SVG,
+			"math" => Namespace::MathML,
+			_ => Namespace::HTML
+		}
+	}
+
+	fn reset_insertion_mode(&mut self) {
+		self.insertion_mode = *self.template_insertion_modes.last().unwrap_or(&InsertionMode::InBody);
+	}
+
+	fn pop_to(&mut self, len: usize, end: SourcePosition) {
+		while self.open_elements.len() > len {
+			if let Some(node) = self.open_elements.pop() {
+				if let HTMLNode::Element(el) = &mut *node.value.borrow_mut() { el.span.end = end; }
+			}
+		}
+	}
+
+	fn process(&mut self, token: HTMLChild) {
+		self.end = token.span().end;
+		match self.insertion_mode {
+			InsertionMode::Initial => self.initial(token),
+			InsertionMode::InBody => self.in_body(token),
+			InsertionMode::InTemplate => self.in_template(token)
+		}
+	}
+
+	fn initial(&mut self, token: HTMLChild) {
+		match token {
+			HTMLChild::Text(string, _) if string.chars().all(|c| c.is_ascii_whitespace()) => {},
+			HTMLChild::Comment(..) => {},
+			HTMLChild::Doctype(doctype) => {
+				self.doctype = Some(doctype);
+				self.insertion_mode = InsertionMode::InBody;
+			},
+			token => {
+				self.insertion_mode = InsertionMode::InBody;
+				self.process(token);
+			}
+		}
+	}
+
+	fn in_body(&mut self, token: HTMLChild) {
+		match token {
+			HTMLChild::StartTag(tag) => self.start_tag(tag),
+			HTMLChild::EndTag(tag) if tag.name == "template" => self.template_end_tag(tag),
+			HTMLChild::EndTag(tag) => self.end_tag(tag),
+			HTMLChild::Text(string, span) => self.text(string, span),
+			HTMLChild::Comment(..) | HTMLChild::Doctype(_) => {}
+		}
+	}
+
+	fn in_template(&mut self, token: HTMLChild) {
+		match token {
+			HTMLChild::StartTag(tag) if tag.name != "template" => {
+				self.template_insertion_modes.pop();
+				self.template_insertion_modes.push(InsertionMode::InBody);
+				self.insertion_mode = InsertionMode::InBody;
+				self.process(HTMLChild::StartTag(tag));
+			},
+			HTMLChild::EndTag(tag) if tag.name != "template" => {},
+			token => self.in_body(token)
+		}
+	}
+
+	fn start_tag(&mut self, tag: HTMLStartTag) {
+		let namespace = self.namespace_for_start_tag(&tag);
+		let tagname = if namespace == Namespace::SVG { foreign_content::adjust_svg_tag_name(&tag.name) } else { tag.name };
+		let is_template = namespace == Namespace::HTML && tagname == "template";
+		let element = self.insert(HTMLNode::Element(HTMLElement::new(namespace, tagname, tag.attrs, tag.span)));
+		if tag.self_close && !is_template { return; }
+
+		self.open_elements.push(element);
+		if is_template {
+			self.template_insertion_modes.push(InsertionMode::InTemplate);
+			self.insertion_mode = InsertionMode::InTemplate;
+		}
+	}
+
+	fn template_end_tag(&mut self, tag: HTMLEndTag) {
+		let template = self.open_elements.iter().rposition(|node| match &*node.value.borrow() {
+			HTMLNode::Element(el) => el.is_html_tag("template"),
+			_ => false
+		});
+
+		let idx = match template {
+			Some(idx) => idx,
+			None => return
+		};
+		self.pop_to(idx + 1, tag.span.start);
+		self.pop_to(idx, tag.span.end);
+		self.template_insertion_modes.pop();
+		self.reset_insertion_mode();
+	}
+
+	fn end_tag(&mut self, tag: HTMLEndTag) {
+		let in_foreign_content = self.current_namespace() != Namespace::HTML;
+
+		for idx in (0..self.open_elements.len()).rev() {
+			let (matches, is_html, is_template) = match &*self.open_elements[idx].value.borrow() {
+				HTMLNode::Element(el) => (el.tagname().to_ascii_lowercase() == tag.name, el.namespace() == Namespace::HTML, el.is_html_tag("template")),
+				_ => (false, true, false)
+			};
+
+			if matches && (is_html || in_foreign_content) {
+				self.pop_to(idx + 1, tag.span.start);
+				self.pop_to(idx, tag.span.end);
+				return;
 			}
+			if is_template { return; }
 		}
 	}
 
-	root
-}
\ No newline at end of file
+	fn text(&mut self, string: String, span: SourceSpan) {
+		self.insert(HTMLNode::Text(string, span));
+	}
+}
+
+pub fn create_document(tags: Vec<HTMLChild>) -> Document {
+	let mut builder = TreeBuilder::new();
+
+	for tag in tags {
+		builder.process(tag);
+	}
+	builder.pop_to(0, builder.end);
+
+	Document::new(builder.root, builder.doctype)
+}
+
+pub fn create_node_tree(tags: Vec<HTMLChild>) -> Rc<tree::TreeNode<HTMLNode>> {
+	create_document(tags).root()
+}
@@ -30,7 +30,33 @@ impl<T> TreeNode<T> {
     }
 
     pub fn get_child(&self, idx: usize) -> Option<Rc<TreeNode<T>>> {
-        if let Some(r) = self.children.borrow().get(idx) { Some(r.clone()) } else { None }
+        self.children.borrow().get(idx).cloned()
+    }
+
+    pub fn child_count(&self) -> usize {
+        self.children.borrow().len()
+    }
+
+    pub fn index_of(&self, child: &Rc<TreeNode<T>>) -> Option<usize> {
+        self.children.borrow().iter().position(|existing| Rc::ptr_eq(existing, child))
+    }
+
+    /// Inserts `child` at `idx` and makes `self` its parent. The child should already be detached.
+    pub fn insert_child(self: Rc<Self>, idx: usize, child: Rc<TreeNode<T>>) {
+        *child.parent.borrow_mut() = Rc::downgrade(&self);
+        self.children.borrow_mut().insert(idx, child);
+    }
+
+    pub fn append_child(self: Rc<Self>, child: Rc<TreeNode<T>>) {
+        let idx = self.child_count();
+        self.insert_child(idx, child);
+    }
+
+    pub fn remove_child(&self, child: &Rc<TreeNode<T>>) -> Option<Rc<TreeNode<T>>> {
+        let idx = self.index_of(child)?;
+        let child = self.children.borrow_mut().remove(idx);
+        *child.parent.borrow_mut() = Weak::new();
+        Some(child)
     }
 
     pub fn get_parent(&self) -> Option<Rc<TreeNode<T>>> {